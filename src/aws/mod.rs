@@ -17,6 +17,13 @@ pub struct Event {
     pub dirichlet_noise: Option<f32>,
     pub rollout_depth: u16,
     pub rollout_temperature: f64,
+    #[serde(default)]
+    pub flat_opening_plies: usize,
+    #[serde(default)]
+    pub contempt: f32,
+    /// In half-flats
+    #[serde(default)]
+    pub komi: i8,
 }
 
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
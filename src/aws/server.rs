@@ -20,7 +20,7 @@ pub fn handle_aws_event_generic<const S: usize>(
     e: Event,
     _c: Context,
 ) -> Result<Output, HandlerError> {
-    let mut position = <Position<S>>::default();
+    let mut position = <Position<S>>::start_position_with_komi(e.komi);
     for mv in e.moves {
         position.do_move(mv);
     }
@@ -36,10 +36,14 @@ pub fn handle_aws_event_generic<const S: usize>(
             .add_dirichlet(dirichlet)
             .add_rollout_depth(e.rollout_depth)
             .add_rollout_temperature(e.rollout_temperature)
+            .with_flat_opening(e.flat_opening_plies)
+            .with_contempt(e.contempt)
     } else {
         MctsSetting::default()
             .add_rollout_depth(e.rollout_depth)
             .add_rollout_temperature(e.rollout_temperature)
+            .with_flat_opening(e.flat_opening_plies)
+            .with_contempt(e.contempt)
     };
 
     let (best_move, score) = search::play_move_time(position, max_time, settings);
@@ -10,6 +10,7 @@ use pgn_traits::PgnPosition;
 use rayon::prelude::*;
 
 use tiltak::evaluation::parameters;
+use tiltak::game_clock::GameClock;
 use tiltak::minmax;
 use tiltak::position::Move;
 #[cfg(feature = "constant-tuning")]
@@ -24,14 +25,17 @@ use tiltak::{position, search};
 mod tests;
 
 pub mod playtak;
+pub mod repl;
 pub mod tei;
 
 fn main() {
     println!("play: Play against the engine through the command line");
-    println!("aimatch: Watch the engine play against a very simple minmax implementation");
+    println!("aimatch [--games <games>] [--depth <depth>] [--nodes <nodes,nodes,...>]: Watch the engine play against a very simple minmax implementation. Each game uses the next node count in the list");
     println!("analyze <size>: Analyze a given position, provided from a PTN or a simple move list");
     println!("tps <size>: Analyze a given position, provided from a tps string");
-    println!("game <size>: Analyze a whole game, provided from a PTN or a simple move list");
+    println!("game <size> [precision]: Analyze a whole game, provided from a PTN or a simple move list. Outputs annotated PTN, with evaluations to [precision] decimals");
+    println!("repl [size]: Interactive analysis, with the commands move <move>, undo, eval, go <nodes>, tps and pv");
+    println!("perft --depth <depth> [--size <size>] [--tps <tps>] [--divide]: Count leaf nodes of the game tree");
     loop {
         let mut input = String::new();
         io::stdin().read_line(&mut input).unwrap();
@@ -44,11 +48,16 @@ fn main() {
                 let position = Position::default();
                 play_human(position);
             }
-            "aimatch" => {
-                for i in 1..10 {
-                    mcts_vs_minmax(3, 50000 * i);
+            "aimatch" => match parse_aimatch_args(&words[1..]) {
+                Ok(settings) => {
+                    let score = aimatch(&settings);
+                    println!(
+                        "Final score: mcts {} wins, minmax {} wins, {} draws",
+                        score.mcts_wins, score.minmax_wins, score.draws
+                    );
                 }
-            }
+                Err(err) => println!("Error: {}", err),
+            },
             "analyze" => match words.get(1) {
                 Some(&"4") => analyze_position_from_ptn::<4>(),
                 Some(&"5") => analyze_position_from_ptn::<5>(),
@@ -65,6 +74,15 @@ fn main() {
                 Some(&"8") => analyze_position_from_tps::<8>(),
                 _ => analyze_position_from_tps::<5>(),
             },
+            "repl" => match words.get(1) {
+                Some(&"4") => repl::run_repl::<4>(),
+                Some(&"6") => repl::run_repl::<6>(),
+                _ => repl::run_repl::<5>(),
+            },
+            "perft" => match perft_command(&words[1..]) {
+                Ok(output) => println!("{}", output),
+                Err(err) => println!("Error: {}", err),
+            },
             #[cfg(feature = "constant-tuning")]
             "openings" => {
                 let depth = 4;
@@ -104,6 +122,11 @@ fn main() {
                 println!("Enter move list or a full PTN, then press enter followed by CTRL+D");
                 let mut input = String::new();
 
+                let precision = words
+                    .get(2)
+                    .and_then(|precision| precision.parse().ok())
+                    .unwrap_or(2);
+
                 match words.get(1) {
                     Some(&"6") => {
                         io::stdin().read_to_string(&mut input).unwrap();
@@ -113,7 +136,7 @@ fn main() {
                         }
                         println!("Analyzing 1 game: ");
 
-                        analyze_game::<6>(games[0].clone());
+                        analyze_game::<6>(games[0].clone(), precision);
                     }
                     None | Some(&"5") => {
                         io::stdin().read_to_string(&mut input).unwrap();
@@ -124,19 +147,102 @@ fn main() {
                         }
                         println!("Analyzing 1 game: ");
 
-                        analyze_game::<5>(games[0].clone());
+                        analyze_game::<5>(games[0].clone(), precision);
                     }
                     Some(s) => println!("Game analysis at size {} not available", s),
                 }
             }
             "mem_usage" => mem_usage(),
             "bench" => bench(),
-            "selfplay" => mcts_selfplay(time::Duration::from_secs(10)),
+            "bench_roads" => {
+                bench_roads::<5>();
+                bench_roads::<6>();
+            }
+            "selfplay" => {
+                mcts_selfplay(time::Duration::from_secs(10));
+            }
             s => println!("Unknown option \"{}\"", s),
         }
     }
 }
 
+/// Run perft for a position given as command line arguments,
+/// for example `perft --size 5 --tps "x5/x5/x5/x5/x5 1 1" --depth 3 --divide`.
+/// If no size is given, it is taken from the TPS, or defaults to 5.
+fn perft_command(args: &[&str]) -> Result<String, String> {
+    let mut size = None;
+    let mut depth = None;
+    let mut tps = None;
+    let mut divide = false;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match *arg {
+            "--size" => {
+                let size_string = args.next().ok_or("Missing value for --size")?;
+                size = Some(
+                    size_string
+                        .parse::<usize>()
+                        .map_err(|_| format!("Invalid size \"{}\"", size_string))?,
+                );
+            }
+            "--depth" => {
+                let depth_string = args.next().ok_or("Missing value for --depth")?;
+                depth = Some(
+                    depth_string
+                        .parse::<u16>()
+                        .map_err(|_| format!("Invalid depth \"{}\"", depth_string))?,
+                );
+            }
+            "--tps" => {
+                let tps_words: Vec<&str> = args.by_ref().take(3).cloned().collect();
+                if tps_words.len() < 3 {
+                    return Err("Expected board, side to move and move number after --tps".into());
+                }
+                tps = Some(tps_words.join(" ").trim_matches('"').to_string());
+            }
+            "--divide" => divide = true,
+            s => return Err(format!("Unknown argument \"{}\"", s)),
+        }
+    }
+
+    let depth = depth.ok_or("Missing --depth")?;
+    let size = size
+        .or_else(|| {
+            tps.as_ref()
+                .map(|tps| tps.split_whitespace().next().unwrap().split('/').count())
+        })
+        .unwrap_or(5);
+
+    match size {
+        4 => perft_for_size::<4>(tps.as_deref(), depth, divide),
+        5 => perft_for_size::<5>(tps.as_deref(), depth, divide),
+        6 => perft_for_size::<6>(tps.as_deref(), depth, divide),
+        7 => perft_for_size::<7>(tps.as_deref(), depth, divide),
+        8 => perft_for_size::<8>(tps.as_deref(), depth, divide),
+        s => Err(format!("Unsupported size {}", s)),
+    }
+}
+
+fn perft_for_size<const S: usize>(
+    tps: Option<&str>,
+    depth: u16,
+    divide: bool,
+) -> Result<String, String> {
+    let mut position = match tps {
+        Some(tps) => <Position<S>>::from_fen(tps).map_err(|err| err.to_string())?,
+        None => Position::start_position(),
+    };
+    let mut output = String::new();
+    if divide && depth > 0 {
+        for (mv, num_moves) in position.perft_divide(depth) {
+            output.push_str(&format!("{}: {}\n", position.move_to_san(&mv), num_moves));
+        }
+    }
+    output.push_str(&position.perft(depth).to_string());
+    Ok(output)
+}
+
 fn analyze_openings<const S: usize>(nodes: u64) {
     let mut input = String::new();
     io::stdin().read_to_string(&mut input).unwrap();
@@ -205,22 +311,19 @@ fn generate_openings<const S: usize>(
         .collect()
 }
 
-fn mcts_selfplay(max_time: time::Duration) {
+/// Play a game against itself, and return the time used by each side
+fn mcts_selfplay(max_time: time::Duration) -> GameClock {
     let mut position = <Position<5>>::default();
     let mut moves = vec![];
 
-    let mut white_elapsed = time::Duration::default();
-    let mut black_elapsed = time::Duration::default();
+    let mut clock = GameClock::new();
 
     while position.game_result().is_none() {
         let start_time = time::Instant::now();
         let (best_move, score) =
             search::play_move_time::<5>(position.clone(), max_time, MctsSetting::default());
 
-        match position.side_to_move() {
-            Color::White => white_elapsed += start_time.elapsed(),
-            Color::Black => black_elapsed += start_time.elapsed(),
-        }
+        clock.record_move(position.side_to_move(), start_time.elapsed());
 
         position.do_move(best_move.clone());
         moves.push(best_move.clone());
@@ -234,9 +337,11 @@ fn mcts_selfplay(max_time: time::Duration) {
     }
 
     println!(
-        "{:.1} used by white, {:.1} for black",
-        white_elapsed.as_secs_f32(),
-        black_elapsed.as_secs_f32()
+        "{:.1} used by white, {:.1} for black, {:.2}s and {:.2}s per move",
+        clock.time_used(Color::White).as_secs_f32(),
+        clock.time_used(Color::Black).as_secs_f32(),
+        clock.average_time_per_move(Color::White).as_secs_f32(),
+        clock.average_time_per_move(Color::Black).as_secs_f32()
     );
 
     print!("\n[");
@@ -255,9 +360,88 @@ fn mcts_selfplay(max_time: time::Duration) {
     println!();
 
     println!("\n{:?}\nResult: {:?}", position, position.game_result());
+    clock
+}
+
+/// Settings for a match between mcts and minmax, parsed from the arguments to `aimatch`
+#[derive(Debug, PartialEq)]
+struct AimatchSettings {
+    games: usize,
+    minmax_depth: u16,
+    /// Node counts for mcts. Game `i` uses the `i`th count, starting over from the first if there are more games than counts
+    mcts_nodes: Vec<u64>,
+}
+
+impl Default for AimatchSettings {
+    fn default() -> Self {
+        AimatchSettings {
+            games: 9,
+            minmax_depth: 3,
+            mcts_nodes: (1..10).map(|i| 50000 * i).collect(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Default)]
+struct AimatchScore {
+    mcts_wins: u64,
+    minmax_wins: u64,
+    draws: u64,
+}
+
+/// Parse arguments to `aimatch`, for example `--games 4 --depth 2 --nodes 10000,20000`
+fn parse_aimatch_args(args: &[&str]) -> Result<AimatchSettings, String> {
+    let mut settings = AimatchSettings::default();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match *arg {
+            "--games" => {
+                let games_string = args.next().ok_or("Missing value for --games")?;
+                settings.games = games_string
+                    .parse()
+                    .map_err(|_| format!("Invalid number of games \"{}\"", games_string))?;
+            }
+            "--depth" => {
+                let depth_string = args.next().ok_or("Missing value for --depth")?;
+                settings.minmax_depth = depth_string
+                    .parse()
+                    .map_err(|_| format!("Invalid depth \"{}\"", depth_string))?;
+            }
+            "--nodes" => {
+                let nodes_string = args.next().ok_or("Missing value for --nodes")?;
+                settings.mcts_nodes = nodes_string
+                    .split(',')
+                    .map(|nodes| {
+                        nodes
+                            .parse()
+                            .map_err(|_| format!("Invalid node count \"{}\"", nodes))
+                    })
+                    .collect::<Result<_, _>>()?;
+            }
+            s => return Err(format!("Unknown argument \"{}\"", s)),
+        }
+    }
+    Ok(settings)
+}
+
+/// Play a match between mcts and minmax, and return the final score
+fn aimatch(settings: &AimatchSettings) -> AimatchScore {
+    let mut score = AimatchScore::default();
+    for i in 0..settings.games {
+        let mcts_nodes = settings.mcts_nodes[i % settings.mcts_nodes.len()];
+        // Mcts always plays black
+        match mcts_vs_minmax(settings.minmax_depth, mcts_nodes) {
+            Some(GameResult::BlackWin) => score.mcts_wins += 1,
+            Some(GameResult::WhiteWin) => score.minmax_wins += 1,
+            // Games are also stopped if the same moves are repeated
+            Some(GameResult::Draw) | None => score.draws += 1,
+        }
+    }
+    score
 }
 
-fn mcts_vs_minmax(minmax_depth: u16, mcts_nodes: u64) {
+fn mcts_vs_minmax(minmax_depth: u16, mcts_nodes: u64) -> Option<GameResult> {
     println!("Minmax depth {} vs mcts {} nodes", minmax_depth, mcts_nodes);
     let mut position = <Position<5>>::default();
     let mut moves = vec![];
@@ -300,6 +484,7 @@ fn mcts_vs_minmax(minmax_depth: u16, mcts_nodes: u64) {
     println!();
 
     println!("\n{:?}\nResult: {:?}", position, position.game_result());
+    position.game_result()
 }
 
 fn analyze_position_from_ptn<const S: usize>() {
@@ -379,61 +564,42 @@ fn analyze_position<const S: usize>(position: &Position<S>) {
                 search::cp_to_win_percentage(position.static_eval()) * 100.0
             );
             tree.print_info();
-            println!("Best move: {:?}", tree.best_move())
+            println!("Best move: {:?}", tree.best_move());
+            if let Some((game_result, plies)) = tree.proven_result() {
+                println!("Proven result: {:?} in {} plies", game_result, plies);
+            }
         }
     }
 }
 
-fn analyze_game<const S: usize>(game: Game<Position<S>>) {
+fn analyze_game<const S: usize>(game: Game<Position<S>>, precision: usize) {
+    let annotated_game = annotate_game(game, 1_000_000, precision);
+    annotated_game.game_to_ptn(&mut io::stdout()).unwrap();
+}
+
+/// Search every position in the game, and add the evaluation as a comment to each move.
+/// Each search uses up to `nodes` nodes, but stops early if the best move is stable.
+/// The comment has the winning percentage for the player making the move, with `precision` decimals, and the best reply.
+fn annotate_game<const S: usize>(
+    mut game: Game<Position<S>>,
+    nodes: u64,
+    precision: usize,
+) -> Game<Position<S>> {
     let mut position = game.start_position.clone();
-    let mut ply_number = 2;
-    for PtnMove { mv, .. } in game.moves {
+    for PtnMove { mv, comment, .. } in game.moves.iter_mut() {
         position.do_move(mv.clone());
-        if let Some(game_result) = position.game_result() {
-            let result_string = match game_result {
-                GameResult::WhiteWin => "1-0",
-                GameResult::BlackWin => "0-1",
-                GameResult::Draw => "1/2-1/2",
-            };
-            if ply_number % 2 == 0 {
-                print!(
-                    "{}. {} {}",
-                    ply_number / 2,
-                    mv.to_string::<S>(),
-                    result_string
-                );
-                io::stdout().flush().unwrap();
-            } else {
-                println!(
-                    "{}... {} {}",
-                    ply_number / 2,
-                    mv.to_string::<S>(),
-                    result_string
-                );
-            }
-        } else {
-            let (best_move, score) = search::mcts::<S>(position.clone(), 1_000_000);
-            if ply_number % 2 == 0 {
-                print!(
-                    "{}. {} {{{:.2}%, best reply {}}} ",
-                    ply_number / 2,
-                    position.move_to_san(&mv),
-                    (1.0 - score) * 100.0,
-                    best_move.to_string::<S>()
-                );
-                io::stdout().flush().unwrap();
-            } else {
-                println!(
-                    "{}... {} {{{:.2}%, best reply {}}}",
-                    ply_number / 2,
-                    position.move_to_san(&mv),
-                    (1.0 - score) * 100.0,
-                    best_move.to_string::<S>()
-                );
-            }
+        if position.game_result().is_none() {
+            let (best_move, score, _) =
+                search::mcts_until_stable::<S>(position.clone(), nodes, nodes / 100, 20);
+            *comment = format!(
+                "{:.*}%, best reply {}",
+                precision,
+                (1.0 - score) * 100.0,
+                position.move_to_san(&best_move)
+            );
         }
-        ply_number += 1;
     }
+    game
 }
 
 /// Play a game against the engine through stdin
@@ -529,6 +695,48 @@ fn bench() {
     );
 }
 
+/// Benchmark road detection, by checking the game result of positions from random games.
+/// Random games have many large groups, and often end in roads.
+fn bench_roads<const S: usize>() {
+    use rand::seq::SliceRandom;
+    use rand::SeedableRng;
+
+    const GAMES: usize = 1000;
+    const ITERATIONS: usize = 20;
+
+    let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+    let mut positions = vec![];
+    let mut moves = vec![];
+    for _ in 0..GAMES {
+        let mut position = <Position<S>>::start_position();
+        while position.game_result().is_none() {
+            moves.clear();
+            position.generate_moves(&mut moves);
+            position.do_move(moves.choose(&mut rng).unwrap().clone());
+            positions.push(position.clone());
+        }
+    }
+
+    let start_time = time::Instant::now();
+    let mut decisive_games = 0;
+    for _ in 0..ITERATIONS {
+        for position in positions.iter() {
+            if let Some(GameResult::WhiteWin | GameResult::BlackWin) = position.game_result() {
+                decisive_games += 1;
+            }
+        }
+    }
+    let time_taken = start_time.elapsed();
+    println!(
+        "{}s: {} positions checked in {} ms, {:.1} ns per position, {} decisive",
+        S,
+        positions.len() * ITERATIONS,
+        time_taken.as_millis(),
+        time_taken.as_nanos() as f64 / (positions.len() * ITERATIONS) as f64,
+        decisive_games / ITERATIONS
+    );
+}
+
 /// Print memory usage of various data types in the project, for debugging purposes
 fn mem_usage() {
     use std::mem;
@@ -553,7 +761,7 @@ fn mem_usage() {
 fn do_moves_and_check_validity(position: &mut Position<5>, move_strings: &[&str]) {
     let mut moves = vec![];
     for mv_san in move_strings.iter() {
-        let mv = position.move_from_san(&mv_san).unwrap();
+        let mv = position.move_from_san(mv_san).unwrap();
         position.generate_moves(&mut moves);
         assert!(
             moves.contains(&mv),
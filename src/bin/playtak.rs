@@ -8,7 +8,7 @@ use std::{io, net, thread};
 use board_game_traits::{Color, GameResult, Position as PositionTrait};
 use bufstream::BufStream;
 use chrono::{Datelike, Local};
-use clap::{App, Arg};
+use clap::{value_t, App, Arg, ArgMatches};
 use log::error;
 use log::{debug, info, warn};
 
@@ -16,7 +16,7 @@ use rand::seq::SliceRandom;
 #[cfg(feature = "aws-lambda-client")]
 use tiltak::aws;
 use tiltak::position::Position;
-use tiltak::position::{squares_iterator, Move, Role, Square};
+use tiltak::position::{squares_iterator, starting_capstones, starting_stones, Move, Role, Square};
 use tiltak::ptn::{Game, PtnMove};
 #[cfg(not(feature = "aws-lambda-client"))]
 use tiltak::search;
@@ -28,6 +28,8 @@ pub struct PlaytakSettings {
     dirichlet_noise: Option<f32>,
     rollout_depth: u16,
     rollout_temperature: f64,
+    flat_opening_plies: usize,
+    contempt: f32,
 }
 
 pub fn main() -> Result<()> {
@@ -75,6 +77,40 @@ pub fn main() -> Result<()> {
                 .help("Instead of seeking any game, accept any seek from the specified bot")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("acceptSeeks")
+                .long("accept-seeks")
+                .help("Instead of seeking any game, accept other players' seeks for the board size. Can be combined with the other --accept options, and with --play-bot."),
+        )
+        .arg(
+            Arg::with_name("acceptRated")
+                .long("accept-rated")
+                .help("Only accept rated or unrated seeks")
+                .takes_value(true)
+                .possible_values(&["rated", "unrated", "any"])
+                .default_value("any"),
+        )
+        .arg(
+            Arg::with_name("acceptMinTime")
+                .long("accept-min-time")
+                .value_name("seconds")
+                .help("Only accept seeks with at least this much time for the game")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("acceptMaxTime")
+                .long("accept-max-time")
+                .value_name("seconds")
+                .help("Only accept seeks with at most this much time for the game")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("acceptKomi")
+                .long("accept-komi")
+                .value_name("flats")
+                .help("Only accept seeks with this komi, for example 2.5. Accepts any komi if not set.")
+                .takes_value(true),
+        )
         .arg(Arg::with_name("policyNoise")
             .long("policy-noise")
             .help("Add dirichlet noise to the policy scores of the root node in search. This gives the bot a small amount of randomness in its play, especially on low nodecounts.")
@@ -91,7 +127,19 @@ pub fn main() -> Result<()> {
             .help("Add a random component to move selection in MCTS rollouts. Has no effect if --rollout-depth is 0. For full rollouts, even the 'low' setting is enough to give highly variable play.")
             .takes_value(true)
             .possible_values(&["low", "medium", "high"])
-            .default_value("low"));
+            .default_value("low"))
+        .arg(Arg::with_name("flatOpening")
+            .long("flat-opening")
+            .value_name("plies")
+            .help("Only play flat placements for the first plies of the game. Gives a faster and more human-like opening, at the cost of some strength.")
+            .takes_value(true)
+            .default_value("0"))
+        .arg(Arg::with_name("contempt")
+            .long("contempt")
+            .value_name("score")
+            .help("Score draws as a loss by this much, between 0.0 and 0.5. Positive values make the engine avoid draws, which is useful against weaker opponents.")
+            .takes_value(true)
+            .default_value("0"));
 
     if cfg!(feature = "aws-lambda-client") {
         app = app.arg(
@@ -156,10 +204,56 @@ pub fn main() -> Result<()> {
         s => panic!("rolloutTemperature cannot be {}", s),
     };
 
+    let flat_opening_plies: usize = matches.value_of("flatOpening").unwrap().parse().unwrap();
+    let contempt = value_t!(matches, "contempt", f32).unwrap_or_else(|err| err.exit());
+    if !(0.0..=0.5).contains(&contempt) {
+        clap::Error::with_description(
+            &format!("--contempt must be between 0.0 and 0.5, was {}", contempt),
+            clap::ErrorKind::InvalidValue,
+        )
+        .exit()
+    }
+
     let playtak_settings = PlaytakSettings {
         dirichlet_noise,
         rollout_depth,
         rollout_temperature,
+        flat_opening_plies,
+        contempt,
+    };
+
+    let seekmode = if matches.is_present("acceptSeeks") || matches.is_present("playBot") {
+        let (stones, capstones) = match size {
+            4 => (starting_stones::<4>(), starting_capstones::<4>()),
+            5 => (starting_stones::<5>(), starting_capstones::<5>()),
+            6 => (starting_stones::<6>(), starting_capstones::<6>()),
+            s => panic!("Unsupported size {}", s),
+        };
+        SeekMode::AcceptSeeks(SeekFilter {
+            name: matches.value_of("playBot").map(|name| name.to_string()),
+            board_size: Some(size),
+            min_time: accept_time_arg(&matches, "acceptMinTime"),
+            max_time: accept_time_arg(&matches, "acceptMaxTime"),
+            rated: match matches.value_of("acceptRated") {
+                Some("rated") => Some(true),
+                Some("unrated") => Some(false),
+                _ => None,
+            },
+            komi: matches.value_of("acceptKomi").map(|komi| {
+                <Position<4>>::parse_komi(komi).unwrap_or_else(|| {
+                    clap::Error::with_description(
+                        &format!("Invalid komi \"{}\" for --accept-komi", komi),
+                        clap::ErrorKind::InvalidValue,
+                    )
+                    .exit()
+                })
+            }),
+            // Only the standard piece counts are supported
+            stones: Some(stones),
+            capstones: Some(capstones),
+        })
+    } else {
+        SeekMode::OpenSeek
     };
 
     loop {
@@ -185,20 +279,15 @@ pub fn main() -> Result<()> {
         if let (Some(user), Some(pwd)) =
             (matches.value_of("username"), matches.value_of("password"))
         {
-            session.login("Tiltak", &user, &pwd)?;
+            session.login("Tiltak", user, pwd)?;
         } else {
             warn!("No username/password provided, logging in as guest");
             session.login_guest()?;
         }
-        let seekmode = match matches.value_of("playBot") {
-            Some(name) => SeekMode::PlayOtherBot(name.to_string()),
-            None => SeekMode::OpenSeek,
-        };
-
         let result = match size {
-            4 => session.seek_game::<4>(seekmode, playtak_settings),
-            5 => session.seek_game::<5>(seekmode, playtak_settings),
-            6 => session.seek_game::<6>(seekmode, playtak_settings),
+            4 => session.seek_game::<4>(seekmode.clone(), playtak_settings),
+            5 => session.seek_game::<5>(seekmode.clone(), playtak_settings),
+            6 => session.seek_game::<6>(seekmode.clone(), playtak_settings),
             s => panic!("Unsupported size {}", s),
         };
 
@@ -217,6 +306,17 @@ pub fn main() -> Result<()> {
     }
 }
 
+/// The value of an optional `--accept-*-time` argument, exiting with a clap error if it is not a number of seconds
+fn accept_time_arg(matches: &ArgMatches, name: &str) -> Option<Duration> {
+    if matches.is_present(name) {
+        Some(Duration::from_secs(
+            value_t!(matches, name, u64).unwrap_or_else(|err| err.exit()),
+        ))
+    } else {
+        None
+    }
+}
+
 struct PlaytakSession {
     #[cfg(feature = "aws-lambda-client")]
     aws_function_name: Option<String>,
@@ -226,10 +326,93 @@ struct PlaytakSession {
     ping_thread: Option<thread::JoinHandle<io::Result<()>>>,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone)]
 enum SeekMode {
     OpenSeek,
-    PlayOtherBot(String),
+    /// Accept other players' seeks that match the filter, instead of seeking
+    AcceptSeeks(SeekFilter),
+}
+
+/// A game seek from another player, as announced by the server
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct PlaytakSeek {
+    pub number: u64,
+    pub name: String,
+    pub board_size: usize,
+    pub time: Duration,
+    pub increment: Duration,
+    /// In half-flats. Older servers do not send komi, and play without it
+    pub komi: i8,
+    /// The number of stones and capstones for each player. Older servers do not send them, and use the standard counts
+    pub stones: Option<u8>,
+    pub capstones: Option<u8>,
+    /// Older servers do not say whether the seek is rated
+    pub rated: Option<bool>,
+}
+
+impl PlaytakSeek {
+    /// Parse a `Seek new` message, for example `Seek new 12 Tiltak 5 900 30 A 0 21 1 0 0`
+    pub fn parse(input: &str) -> Option<Self> {
+        let words: Vec<&str> = input.split_whitespace().collect();
+        if words.len() < 7 || words[0] != "Seek" || words[1] != "new" {
+            return None;
+        }
+        Some(PlaytakSeek {
+            number: words[2].parse().ok()?,
+            name: words[3].to_string(),
+            board_size: words[4].parse().ok()?,
+            time: Duration::from_secs(words[5].parse().ok()?),
+            increment: Duration::from_secs(words[6].parse().ok()?),
+            komi: words.get(8).map_or(Some(0), |komi| komi.parse().ok())?,
+            stones: words.get(9).map(|stones| stones.parse()).transpose().ok()?,
+            capstones: words
+                .get(10)
+                .map(|capstones| capstones.parse())
+                .transpose()
+                .ok()?,
+            // The server sends whether the seek is unrated
+            rated: words.get(11).map(|unrated| *unrated == "0"),
+        })
+    }
+}
+
+/// Criteria for which seeks to accept. Criteria that are not set accept any seek.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct SeekFilter {
+    /// Only accept seeks from this player, case-insensitive
+    pub name: Option<String>,
+    pub board_size: Option<usize>,
+    pub min_time: Option<Duration>,
+    pub max_time: Option<Duration>,
+    /// Only accept rated or unrated seeks. Seeks that do not say whether they are rated are not accepted.
+    pub rated: Option<bool>,
+    /// In half-flats
+    pub komi: Option<i8>,
+    /// Seeks that do not say how many pieces they use are assumed to use the standard counts, and are accepted
+    pub stones: Option<u8>,
+    pub capstones: Option<u8>,
+}
+
+impl SeekFilter {
+    pub fn matches(&self, seek: &PlaytakSeek) -> bool {
+        self.name
+            .as_ref()
+            .is_none_or(|name| name.eq_ignore_ascii_case(&seek.name))
+            && self
+                .board_size
+                .is_none_or(|board_size| board_size == seek.board_size)
+            && self.min_time.is_none_or(|min_time| seek.time >= min_time)
+            && self.max_time.is_none_or(|max_time| seek.time <= max_time)
+            && self.rated.is_none_or(|rated| seek.rated == Some(rated))
+            && self.komi.is_none_or(|komi| seek.komi == komi)
+            && self
+                .stones
+                .is_none_or(|stones| seek.stones.is_none_or(|seek_stones| seek_stones == stones))
+            && self.capstones.is_none_or(|capstones| {
+                seek.capstones
+                    .is_none_or(|seek_capstones| seek_capstones == capstones)
+            })
+    }
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -241,6 +424,43 @@ struct PlaytakGame<'a> {
     our_color: Color,
     time_left: Duration,
     increment: Duration,
+    /// In half-flats
+    komi: i8,
+}
+
+/// Draw offers in an ongoing game. On playtak, a draw is agreed when both players have offered one.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum DrawOfferState {
+    NoOffer,
+    OfferedByUs,
+    OfferedByOpponent,
+}
+
+impl DrawOfferState {
+    /// Decide whether to offer (or accept) a draw, after searching our next move.
+    /// `score` is our winning probability according to the search.
+    /// Returns the new state, and whether a draw offer should be sent to the server.
+    pub fn after_search(self, score: f32, half_moves_played: usize) -> (Self, bool) {
+        match self {
+            DrawOfferState::OfferedByOpponent if Self::should_accept_draw(score) => {
+                (DrawOfferState::NoOffer, true)
+            }
+            DrawOfferState::NoOffer if Self::should_offer_draw(score, half_moves_played) => {
+                (DrawOfferState::OfferedByUs, true)
+            }
+            _ => (self, false),
+        }
+    }
+
+    /// Accept draws unless we think we are winning
+    fn should_accept_draw(score: f32) -> bool {
+        score < 0.55
+    }
+
+    /// Only offer draws in dead-drawn positions, and not in the opening
+    fn should_offer_draw(score: f32, half_moves_played: usize) -> bool {
+        half_moves_played >= 60 && (score - 0.5).abs() < 0.02
+    }
 }
 
 impl PlaytakSession {
@@ -343,8 +563,10 @@ impl PlaytakSession {
         seek_mode: SeekMode,
         playtak_settings: PlaytakSettings,
     ) -> io::Result<std::convert::Infallible> {
-        let mut time_for_game = Duration::from_secs(900);
-        let mut increment = Duration::from_secs(30);
+        let time_for_game = Duration::from_secs(900);
+        let increment = Duration::from_secs(30);
+        // Only one seek is accepted at a time, so that we know the settings of the game when it starts
+        let mut accepted_seek: Option<PlaytakSeek> = None;
 
         if seek_mode == SeekMode::OpenSeek {
             self.send_line(&format!(
@@ -363,6 +585,10 @@ impl PlaytakSession {
             }
             match words[0] {
                 "Game" => {
+                    let (time_left, increment, komi) = match &accepted_seek {
+                        Some(seek) => (seek.time, seek.increment, seek.komi),
+                        None => (time_for_game, increment, 0),
+                    };
                     let playtak_game = PlaytakGame {
                         game_no: u64::from_str(words[2]).unwrap(),
                         _board_size: usize::from_str(words[3]).unwrap(),
@@ -373,30 +599,35 @@ impl PlaytakSession {
                             "black" => Color::Black,
                             color => panic!("Bad color \"{}\"", color),
                         },
-                        time_left: time_for_game,
+                        time_left,
                         increment,
+                        komi,
                     };
                     self.play_game::<S>(playtak_game, playtak_settings)?;
                     unreachable!()
                 }
 
                 "Seek" => {
-                    if let SeekMode::PlayOtherBot(ref bot_name) = seek_mode {
-                        if words[1] == "new" {
-                            let number = u64::from_str(words[2]).unwrap();
-                            let name = words[3];
-                            let time = Duration::from_secs(u64::from_str(words[5]).unwrap());
-                            let inc = Duration::from_secs(u64::from_str(words[6]).unwrap());
-                            if name.eq_ignore_ascii_case(bot_name) {
-                                self.send_line(&format!("Accept {}", number))?;
-                                time_for_game = time;
-                                increment = inc;
+                    if let (SeekMode::AcceptSeeks(filter), None) = (&seek_mode, &accepted_seek) {
+                        if let Some(seek) = PlaytakSeek::parse(&input) {
+                            if filter.matches(&seek) {
+                                info!("Accepting seek {:?}", seek);
+                                self.send_line(&format!("Accept {}", seek.number))?;
+                                accepted_seek = Some(seek);
                             }
                         }
                     }
                 }
                 "NOK" => {
-                    warn!("Received NOK from server, ignoring. This may happen if the game was aborted while we were thinking");
+                    if let Some(seek) = accepted_seek.take() {
+                        // Most likely somebody else accepted the seek first
+                        warn!(
+                            "Received NOK from server, failed to accept seek {}",
+                            seek.number
+                        );
+                    } else {
+                        warn!("Received NOK from server, ignoring. This may happen if the game was aborted while we were thinking");
+                    }
                 }
                 _ => debug!("Ignoring server message \"{}\"", input.trim()),
             }
@@ -411,17 +642,19 @@ impl PlaytakSession {
         playtak_settings: PlaytakSettings,
     ) -> io::Result<Infallible> {
         info!(
-            "Starting game #{}, {} vs {} as {}, {}+{:.1}",
+            "Starting game #{}, {} vs {} as {}, {}+{:.1}, komi {}",
             game.game_no,
             game.white_player,
             game.black_player,
             game.our_color,
             game.time_left.as_secs(),
-            game.increment.as_secs_f32()
+            game.increment.as_secs_f32(),
+            game.komi as f32 / 2.0
         );
-        let mut position = <Position<S>>::start_position();
+        let mut position = <Position<S>>::start_position_with_komi(game.komi);
         let mut moves = vec![];
         let mut our_time_left = game.time_left;
+        let mut draw_offer = DrawOfferState::NoOffer;
         'gameloop: loop {
             if position.game_result().is_some() {
                 break;
@@ -431,12 +664,10 @@ impl PlaytakSession {
                     // On the very first move, always place instantly in a random corner
                     if squares_iterator::<S>().all(|square| position[square].is_empty()) {
                         let mut rng = rand::thread_rng();
-                        let moves = vec![
-                            Move::Place(Role::Flat, Square(0)),
+                        let moves = [Move::Place(Role::Flat, Square(0)),
                             Move::Place(Role::Flat, Square(S as u8 - 1)),
                             Move::Place(Role::Flat, Square((S * (S - 1)) as u8)),
-                            Move::Place(Role::Flat, Square((S * S - 1) as u8)),
-                        ];
+                            Move::Place(Role::Flat, Square((S * S - 1) as u8))];
                         (moves.choose(&mut rng).unwrap().clone(), 0.0)
                     } else {
                         #[cfg(feature = "aws-lambda-client")]
@@ -453,6 +684,9 @@ impl PlaytakSession {
                                 dirichlet_noise: playtak_settings.dirichlet_noise,
                                 rollout_depth: playtak_settings.rollout_depth,
                                 rollout_temperature: playtak_settings.rollout_temperature,
+                                flat_opening_plies: playtak_settings.flat_opening_plies,
+                                contempt: playtak_settings.contempt,
+                                komi: game.komi,
                             };
                             let aws::Output { best_move, score } =
                                 aws::client::best_move_aws(aws_function_name, &event)?;
@@ -467,21 +701,42 @@ impl PlaytakSession {
                                     .add_dirichlet(dirichlet)
                                     .add_rollout_depth(playtak_settings.rollout_depth)
                                     .add_rollout_temperature(playtak_settings.rollout_temperature)
+                                    .with_flat_opening(playtak_settings.flat_opening_plies)
+                                    .with_contempt(playtak_settings.contempt)
                             }
                             else {
                                 MctsSetting::default()
                                     .add_rollout_depth(playtak_settings.rollout_depth)
                                     .add_rollout_temperature(playtak_settings.rollout_temperature)
+                                    .with_flat_opening(playtak_settings.flat_opening_plies)
+                                    .with_contempt(playtak_settings.contempt)
                             };
                             search::play_move_time(position.clone(), maximum_time, settings)
                         }
                     };
 
+                if position.half_moves_played() > 0 {
+                    let accepting_draw = draw_offer == DrawOfferState::OfferedByOpponent;
+                    let (new_draw_offer, send_draw_offer) =
+                        draw_offer.after_search(score, position.half_moves_played());
+                    draw_offer = new_draw_offer;
+                    if send_draw_offer {
+                        self.send_line(&format!("Game#{} OfferDraw", game.game_no))?;
+                        // The server then ends the game, which is handled while waiting for the opponent's move
+                        if accepting_draw {
+                            info!("Accepted draw offer with score {:.3}", score);
+                        } else {
+                            info!("Offered draw with score {:.3}", score);
+                        }
+                    }
+                }
+
                 position.do_move(best_move.clone());
                 moves.push(PtnMove {
                     mv: best_move.clone(),
                     annotations: vec![],
                     comment: score.to_string(),
+                    clock: None,
                 });
 
                 let output_string = format!(
@@ -529,19 +784,28 @@ impl PlaytakSession {
                                     mv: move_played,
                                     annotations: vec![],
                                     comment: "0.0".to_string(),
+                                    clock: None,
                                 });
                                 break;
                             }
                             "Time" => {
                                 let white_time_left =
-                                    Duration::from_secs(u64::from_str(&words[2]).unwrap());
+                                    Duration::from_secs(u64::from_str(words[2]).unwrap());
                                 let black_time_left =
-                                    Duration::from_secs(u64::from_str(&words[3]).unwrap());
+                                    Duration::from_secs(u64::from_str(words[3]).unwrap());
                                 our_time_left = match game.our_color {
                                     Color::White => white_time_left,
                                     Color::Black => black_time_left,
                                 };
                             }
+                            "OfferDraw" => {
+                                info!("Opponent offered a draw");
+                                draw_offer = DrawOfferState::OfferedByOpponent;
+                            }
+                            "RemoveDraw" => {
+                                info!("Opponent removed their draw offer");
+                                draw_offer = DrawOfferState::NoOffer;
+                            }
                             "Abandoned" | "Abandoned." | "Over" => break 'gameloop,
                             _ => debug!("Ignoring server message \"{}\"", line),
                         }
@@ -570,7 +834,7 @@ impl PlaytakSession {
             ];
 
             let game = Game {
-                start_position: <Position<S>>::start_position(),
+                start_position: <Position<S>>::start_position_with_komi(game.komi),
                 moves: moves.clone(),
                 game_result: position.game_result(),
                 tags,
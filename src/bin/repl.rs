@@ -0,0 +1,127 @@
+use std::io;
+use std::io::{BufRead, BufReader};
+use std::str::FromStr;
+
+use board_game_traits::{EvalPosition, Position as PositionTrait};
+use pgn_traits::PgnPosition;
+use tiltak::position::{Position, ReverseMove};
+use tiltak::search;
+use tiltak::search::MonteCarloTree;
+
+/// An interactive analysis session, with a position that is changed by the user's commands.
+/// The search tree is kept between `go` commands, and follows the moves that are played.
+pub struct Repl<const S: usize> {
+    pub position: Position<S>,
+    reverse_moves: Vec<ReverseMove>,
+    tree: MonteCarloTree<S>,
+}
+
+impl<const S: usize> Default for Repl<S> {
+    fn default() -> Self {
+        Repl::new(Position::default())
+    }
+}
+
+impl<const S: usize> Repl<S> {
+    pub fn new(position: Position<S>) -> Self {
+        Repl {
+            tree: MonteCarloTree::new(position.clone()),
+            position,
+            reverse_moves: vec![],
+        }
+    }
+
+    /// Run a single command, and return its output
+    pub fn execute(&mut self, line: &str) -> Result<String, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["move", move_string] => {
+                if self.position.game_result().is_some() {
+                    return Err("The game is already over".to_string());
+                }
+                let mv = self
+                    .position
+                    .move_from_san(move_string)
+                    .map_err(|err| err.to_string())?;
+                if !self.position.is_legal(&mv) {
+                    return Err(format!("{} is not legal in the position", move_string));
+                }
+                let reverse_move = self.position.do_move(mv.clone());
+                self.reverse_moves.push(reverse_move);
+                self.tree.advance_root(&mv);
+                Ok(format!("Played {}", move_string))
+            }
+            ["undo"] => {
+                let reverse_move = self
+                    .reverse_moves
+                    .pop()
+                    .ok_or_else(|| "No moves to undo".to_string())?;
+                self.position.reverse_move(reverse_move);
+                // The tree cannot be walked backwards, so start a new one
+                self.tree = MonteCarloTree::new(self.position.clone());
+                Ok("Undid 1 move".to_string())
+            }
+            ["eval"] => {
+                let static_eval = self.position.static_eval();
+                Ok(format!(
+                    "Static eval {:.4}, {:.2}% winning probability for white",
+                    static_eval,
+                    search::cp_to_win_percentage(static_eval) * 100.0
+                ))
+            }
+            ["go", nodes] => {
+                let nodes = u64::from_str(nodes).map_err(|err| err.to_string())?;
+                if self.position.game_result().is_some() {
+                    return Err("Cannot search a finished game".to_string());
+                }
+                for _ in 0..nodes.max(search::MIN_MCTS_NODES) {
+                    self.tree.select();
+                }
+                let (best_move, score) = self.tree.best_move();
+                Ok(format!(
+                    "{} visits, best move {} with {:.2}% winning probability",
+                    self.tree.visits(),
+                    self.position.move_to_san(&best_move),
+                    score * 100.0
+                ))
+            }
+            ["tps"] => Ok(self.position.to_fen()),
+            ["pv"] => {
+                if self.tree.visits() == 0 {
+                    return Err("No search has been run in this position".to_string());
+                }
+                Ok(self
+                    .tree
+                    .pv()
+                    .map(|mv| self.position.move_to_san(&mv))
+                    .collect::<Vec<_>>()
+                    .join(" "))
+            }
+            [] => Err("Empty command".to_string()),
+            _ => Err(format!("Unknown command \"{}\"", line.trim())),
+        }
+    }
+}
+
+pub fn main() {
+    match std::env::args().nth(1).as_deref() {
+        Some("4") => run_repl::<4>(),
+        Some("6") => run_repl::<6>(),
+        _ => run_repl::<5>(),
+    }
+}
+
+/// Read commands from stdin until `quit`, and print their output
+pub fn run_repl<const S: usize>() {
+    println!("Commands: move <move>, undo, eval, go <nodes>, tps, pv, quit");
+    let mut repl = <Repl<S>>::default();
+    for line in BufReader::new(io::stdin()).lines().map(Result::unwrap) {
+        if line.trim() == "quit" {
+            break;
+        }
+        match repl.execute(&line) {
+            Ok(output) => println!("{}", output),
+            Err(err) => println!("Error: {}", err),
+        }
+    }
+}
@@ -4,11 +4,83 @@ use std::io;
 use std::io::{BufRead, BufReader};
 use std::str::FromStr;
 use std::time::{Duration, Instant};
-use tiltak::position::Position;
+use tiltak::position::{Move, Position};
 
 use std::any::Any;
+use std::fmt;
 use tiltak::search;
-use tiltak::search::MctsSetting;
+use tiltak::search::{MctsSetting, Score};
+
+/// An `info` line sent to the GUI during search. Only the fields that are set are printed.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct TeiInfo {
+    /// The root move this line is about, if it is not about the whole search
+    pub currmove: Option<String>,
+    pub depth: Option<u64>,
+    pub seldepth: Option<u64>,
+    pub score_cp: Option<i64>,
+    /// Winning probability for the side to move
+    pub score_winrate: Option<Score>,
+    pub nodes: Option<u64>,
+    pub time_ms: Option<u128>,
+    pub pv: Vec<String>,
+}
+
+impl fmt::Display for TeiInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "info")?;
+        if let Some(currmove) = &self.currmove {
+            write!(f, " currmove {}", currmove)?;
+        }
+        if let Some(depth) = self.depth {
+            write!(f, " depth {}", depth)?;
+        }
+        if let Some(seldepth) = self.seldepth {
+            write!(f, " seldepth {}", seldepth)?;
+        }
+        if let Some(score_cp) = self.score_cp {
+            write!(f, " score cp {}", score_cp)?;
+        }
+        if let Some(score_winrate) = self.score_winrate {
+            write!(f, " score winrate {:.3}", score_winrate)?;
+        }
+        if let Some(nodes) = self.nodes {
+            write!(f, " nodes {}", nodes)?;
+        }
+        if let Some(time_ms) = self.time_ms {
+            write!(f, " time {}", time_ms)?;
+        }
+        if !self.pv.is_empty() {
+            write!(f, " pv {}", self.pv.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+impl TeiInfo {
+    /// An info line with the search's score, both as a winning probability and in centipawns
+    pub fn from_score(score: Score) -> Self {
+        // Keep the centipawn score finite for proven wins and losses
+        let clamped_score = score.clamp(0.0001, 0.9999);
+        TeiInfo {
+            score_cp: Some((search::win_percentage_to_cp(clamped_score) * 100.0).round() as i64),
+            score_winrate: Some(score),
+            ..TeiInfo::default()
+        }
+    }
+}
+
+/// The `bestmove` line sent to the GUI when the search finishes
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TeiBestmove {
+    pub mv: String,
+}
+
+impl fmt::Display for TeiBestmove {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "bestmove {}", self.mv)
+    }
+}
 
 pub fn main() {
     loop {
@@ -97,11 +169,30 @@ fn parse_position_string<const S: usize>(line: &str) -> Position<S> {
     position
 }
 
+/// Number of nodes to search for `go searchmoves`, if no time control is given
+const SEARCHMOVES_NODES: u64 = 100_000;
+
+/// Arguments to `go` that end the move list of `searchmoves`
+const GO_KEYWORDS: [&str; 5] = ["movetime", "wtime", "btime", "winc", "binc"];
+
 fn parse_go_string<const S: usize>(line: &str, position: &Position<S>) {
-    let mut words = line.split_whitespace();
-    words.next(); // go
+    let mut words: Vec<&str> = line.split_whitespace().skip(1).collect();
 
-    let mcts_settings = MctsSetting::default();
+    // Restrict the search to the given root moves, by excluding all others
+    let mut search_moves = vec![];
+    if words.first() == Some(&"searchmoves") {
+        let num_moves = words[1..]
+            .iter()
+            .take_while(|word| !GO_KEYWORDS.contains(word))
+            .count();
+        match parse_search_moves(position, &words[1..=num_moves]) {
+            Ok(moves) => search_moves = moves,
+            Err(err) => println!("info string {}, searching all moves", err),
+        }
+        words.drain(0..=num_moves);
+    }
+    let mcts_settings = search_moves_settings(position, &search_moves);
+    let mut words = words.into_iter();
 
     match words.next() {
         Some("movetime") => {
@@ -118,19 +209,25 @@ fn parse_go_string<const S: usize>(line: &str, position: &Position<S>) {
                 }
                 total_nodes += nodes_to_search;
                 let (best_move, score) = tree.best_move();
-                println!(
-                    "info depth {} seldepth {} score cp {} nodes {} time {} pv {}",
-                    i / 2 + 1,
-                    tree.pv().count(),
-                    (score * 200.0 - 100.0) as i64,
-                    total_nodes,
-                    start_time.elapsed().as_millis(),
-                    tree.pv()
-                        .map(|mv| mv.to_string::<S>() + " ")
-                        .collect::<String>()
-                );
+                let info = TeiInfo {
+                    depth: Some(i / 2 + 1),
+                    seldepth: Some(tree.pv().count() as u64),
+                    nodes: Some(total_nodes),
+                    time_ms: Some(start_time.elapsed().as_millis()),
+                    pv: tree.pv().map(|mv| mv.to_string::<S>()).collect(),
+                    ..TeiInfo::from_score(score)
+                };
+                println!("{}", info);
                 if start_time.elapsed().as_secs_f64() > movetime.as_secs_f64() * 0.7 {
-                    println!("bestmove {}", position.move_to_san(&best_move));
+                    if !search_moves.is_empty() {
+                        for info in root_move_infos(&tree) {
+                            println!("{}", info);
+                        }
+                    }
+                    let bestmove = TeiBestmove {
+                        mv: position.move_to_san(&best_move),
+                    };
+                    println!("{}", bestmove);
                     break;
                 }
             }
@@ -167,17 +264,93 @@ fn parse_go_string<const S: usize>(line: &str, position: &Position<S>) {
             let (best_move, score) =
                 search::play_move_time::<S>(position.clone(), max_time, mcts_settings);
 
-            println!(
-                "info score cp {} time {} pv {}",
-                (score * 200.0 - 100.0) as i64,
-                start_time.elapsed().as_millis(),
-                position.move_to_san(&best_move)
-            );
+            let info = TeiInfo {
+                time_ms: Some(start_time.elapsed().as_millis()),
+                pv: vec![position.move_to_san(&best_move)],
+                ..TeiInfo::from_score(score)
+            };
+            println!("{}", info);
 
-            println!("bestmove {}", position.move_to_san(&best_move));
+            let bestmove = TeiBestmove {
+                mv: position.move_to_san(&best_move),
+            };
+            println!("{}", bestmove);
+        }
+        None if !search_moves.is_empty() => {
+            let (infos, bestmove) =
+                analyze_search_moves(position, &search_moves, SEARCHMOVES_NODES);
+            for info in infos {
+                println!("{}", info);
+            }
+            println!("{}", bestmove);
         }
         Some(_) | None => {
             panic!("Invalid go command \"{}\"", line);
         }
     }
 }
+
+/// Parse the moves of `go searchmoves`. Returns an error if any of them are invalid or illegal in the position
+pub fn parse_search_moves<const S: usize>(
+    position: &Position<S>,
+    move_strings: &[&str],
+) -> Result<Vec<Move>, pgn_traits::Error> {
+    move_strings
+        .iter()
+        .map(|move_string| {
+            let mv = position.move_from_san(move_string)?;
+            if position.is_legal(&mv) {
+                Ok(mv)
+            } else {
+                Err(pgn_traits::Error::new(
+                    pgn_traits::ErrorKind::IllegalMove,
+                    format!("Illegal searchmove {}", move_string),
+                ))
+            }
+        })
+        .collect()
+}
+
+/// Search only the given root moves for `nodes` nodes, and report the evaluation of each of them
+pub fn analyze_search_moves<const S: usize>(
+    position: &Position<S>,
+    search_moves: &[Move],
+    nodes: u64,
+) -> (Vec<TeiInfo>, TeiBestmove) {
+    let settings = search_moves_settings(position, search_moves);
+    let mut tree = search::MonteCarloTree::with_settings(position.clone(), settings);
+    for _ in 0..nodes.max(search::MIN_MCTS_NODES) {
+        tree.select();
+    }
+    let (best_move, _) = tree.best_move();
+    let bestmove = TeiBestmove {
+        mv: position.move_to_san(&best_move),
+    };
+    (root_move_infos(&tree), bestmove)
+}
+
+/// Search settings that exclude every root move not in `search_moves`. If it is empty, all moves are searched.
+fn search_moves_settings<const S: usize>(
+    position: &Position<S>,
+    search_moves: &[Move],
+) -> MctsSetting<S> {
+    if search_moves.is_empty() {
+        return MctsSetting::default();
+    }
+    let mut legal_moves = vec![];
+    position.generate_moves(&mut legal_moves);
+    legal_moves.retain(|mv| !search_moves.contains(mv));
+    MctsSetting::default().exclude_moves(legal_moves)
+}
+
+/// An `info currmove` line for each root move of the search
+fn root_move_infos<const S: usize>(tree: &search::MonteCarloTree<S>) -> Vec<TeiInfo> {
+    tree.root_moves()
+        .into_iter()
+        .map(|(mv, score, visits)| TeiInfo {
+            currmove: Some(mv.to_string::<S>()),
+            nodes: Some(visits),
+            ..TeiInfo::from_score(score)
+        })
+        .collect()
+}
@@ -0,0 +1,28 @@
+use crate::{aimatch, parse_aimatch_args, AimatchSettings};
+
+#[test]
+fn parse_aimatch_args_test() {
+    assert_eq!(parse_aimatch_args(&[]), Ok(AimatchSettings::default()));
+    assert_eq!(
+        parse_aimatch_args(&["--games", "4", "--depth", "2", "--nodes", "1000,2000"]),
+        Ok(AimatchSettings {
+            games: 4,
+            minmax_depth: 2,
+            mcts_nodes: vec![1000, 2000],
+        })
+    );
+    assert!(parse_aimatch_args(&["--games"]).is_err());
+    assert!(parse_aimatch_args(&["--nodes", "1000,"]).is_err());
+    assert!(parse_aimatch_args(&["--size", "5"]).is_err());
+}
+
+#[test]
+fn aimatch_single_game_test() {
+    let settings = AimatchSettings {
+        games: 1,
+        minmax_depth: 1,
+        mcts_nodes: vec![100],
+    };
+    let score = aimatch(&settings);
+    assert_eq!(score.mcts_wins + score.minmax_wins + score.draws, 1);
+}
@@ -0,0 +1,28 @@
+use tiltak::position::Position;
+use tiltak::ptn::ptn_parser;
+
+use crate::annotate_game;
+
+#[test]
+fn annotated_game_reparses_test() {
+    let games =
+        ptn_parser::parse_ptn::<Position<5>>("1. a1 e1 2. e2 a2 3. e3 a3 4. e4 a4 5. e5 1-0")
+            .unwrap();
+    let annotated_game = annotate_game(games[0].clone(), 1000, 1);
+
+    let (last_move, moves) = annotated_game.moves.split_last().unwrap();
+    assert!(last_move.comment.is_empty());
+    for ptn_move in moves {
+        let (eval, _) = ptn_move.comment.split_once("%, best reply ").unwrap();
+        assert_eq!(eval.split_once('.').unwrap().1.len(), 1, "{}", eval);
+    }
+
+    let mut output = vec![];
+    annotated_game.game_to_ptn(&mut output).unwrap();
+    let reparsed_games =
+        ptn_parser::parse_ptn::<Position<5>>(&String::from_utf8(output).unwrap()).unwrap();
+
+    assert_eq!(reparsed_games.len(), 1);
+    assert_eq!(reparsed_games[0].moves, annotated_game.moves);
+    assert_eq!(reparsed_games[0].game_result, annotated_game.game_result);
+}
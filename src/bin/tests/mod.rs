@@ -1 +1,8 @@
+mod aimatch_tests;
+mod analyze_game_tests;
+mod perft_command_tests;
+mod playtak_draw_tests;
 mod playtak_parse_tests;
+mod playtak_seek_tests;
+mod repl_tests;
+mod tei_tests;
@@ -0,0 +1,45 @@
+use crate::perft_command;
+
+#[test]
+fn perft_start_position_test() {
+    assert_eq!(
+        perft_command(&["--size", "4", "--depth", "1"]),
+        Ok("16".to_string())
+    );
+    assert_eq!(perft_command(&["--depth", "1"]), Ok("25".to_string()));
+    assert_eq!(
+        perft_command(&["--size", "6", "--depth", "1"]),
+        Ok("36".to_string())
+    );
+}
+
+#[test]
+fn perft_tps_test() {
+    let output = perft_command(&["--tps", "\"x5/x5/x5/x5/x5", "1", "1\"", "--depth", "2"]);
+    assert_eq!(output, Ok("600".to_string()));
+
+    let output = perft_command(&["--tps", "x4/x4/x4/x4", "1", "1", "--depth", "1", "--divide"]);
+    let output = output.unwrap();
+    assert_eq!(output.lines().count(), 17);
+    assert_eq!(output.lines().next(), Some("a4: 1"));
+    assert_eq!(output.lines().last(), Some("16"));
+}
+
+#[test]
+fn perft_invalid_arguments_test() {
+    assert!(perft_command(&["--size", "5"]).is_err());
+    assert!(perft_command(&["--depth", "two"]).is_err());
+    assert!(perft_command(&["--size", "3", "--depth", "1"]).is_err());
+    assert!(perft_command(&["--size", "9", "--depth", "1"]).is_err());
+    assert!(perft_command(&[
+        "--tps",
+        "x5/x5/x5/x5",
+        "1",
+        "1",
+        "--depth",
+        "1",
+        "--size",
+        "5"
+    ])
+    .is_err());
+}
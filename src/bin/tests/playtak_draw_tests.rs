@@ -0,0 +1,43 @@
+use crate::playtak::DrawOfferState;
+
+#[test]
+fn accept_draw_when_not_winning_test() {
+    for &score in &[0.05, 0.3, 0.5, 0.54] {
+        assert_eq!(
+            DrawOfferState::OfferedByOpponent.after_search(score, 20),
+            (DrawOfferState::NoOffer, true)
+        );
+    }
+}
+
+#[test]
+fn decline_draw_when_winning_test() {
+    for &score in &[0.6, 0.8, 0.99] {
+        assert_eq!(
+            DrawOfferState::OfferedByOpponent.after_search(score, 80),
+            (DrawOfferState::OfferedByOpponent, false)
+        );
+    }
+}
+
+#[test]
+fn offer_draw_in_dead_drawn_position_test() {
+    assert_eq!(
+        DrawOfferState::NoOffer.after_search(0.5, 80),
+        (DrawOfferState::OfferedByUs, true)
+    );
+    // Not in the opening, and not with a clear advantage to either side
+    assert_eq!(
+        DrawOfferState::NoOffer.after_search(0.5, 10),
+        (DrawOfferState::NoOffer, false)
+    );
+    assert_eq!(
+        DrawOfferState::NoOffer.after_search(0.3, 80),
+        (DrawOfferState::NoOffer, false)
+    );
+    // Don't repeat an existing offer
+    assert_eq!(
+        DrawOfferState::OfferedByUs.after_search(0.5, 80),
+        (DrawOfferState::OfferedByUs, false)
+    );
+}
@@ -0,0 +1,97 @@
+use std::time::Duration;
+
+use crate::playtak::{PlaytakSeek, SeekFilter};
+
+#[test]
+fn parse_seek_test() {
+    assert_eq!(
+        PlaytakSeek::parse("Seek new 12 Tiltak 5 900 30 A 4 21 1 1 0"),
+        Some(PlaytakSeek {
+            number: 12,
+            name: "Tiltak".to_string(),
+            board_size: 5,
+            time: Duration::from_secs(900),
+            increment: Duration::from_secs(30),
+            komi: 4,
+            stones: Some(21),
+            capstones: Some(1),
+            rated: Some(false),
+        })
+    );
+    let old_seek = PlaytakSeek::parse("Seek new 3 Taktician 6 600 10").unwrap();
+    assert_eq!(old_seek.rated, None);
+    assert_eq!(old_seek.komi, 0);
+    assert_eq!(old_seek.stones, None);
+    assert_eq!(old_seek.capstones, None);
+    assert_eq!(PlaytakSeek::parse("Seek remove 12 Tiltak 5 900 30"), None);
+    assert_eq!(PlaytakSeek::parse("Seek new 12 Tiltak"), None);
+}
+
+#[test]
+fn seek_filter_test() {
+    let seek = PlaytakSeek::parse("Seek new 12 Taktician 5 900 30 A 0 21 1 0 0").unwrap();
+    assert_eq!(seek.rated, Some(true));
+
+    assert!(SeekFilter::default().matches(&seek));
+
+    let filter = SeekFilter {
+        name: Some("taktician".to_string()),
+        board_size: Some(5),
+        min_time: Some(Duration::from_secs(300)),
+        max_time: Some(Duration::from_secs(900)),
+        rated: Some(true),
+        komi: Some(0),
+        stones: Some(21),
+        capstones: Some(1),
+    };
+    assert!(filter.matches(&seek));
+
+    // Seeks that do not send their piece counts use the standard ones
+    let old_seek = PlaytakSeek::parse("Seek new 3 Taktician 5 900 30").unwrap();
+    assert!(SeekFilter {
+        rated: None,
+        ..filter.clone()
+    }
+    .matches(&old_seek));
+
+    for non_matching_filter in [
+        SeekFilter {
+            name: Some("Tiltak".to_string()),
+            ..filter.clone()
+        },
+        SeekFilter {
+            board_size: Some(6),
+            ..filter.clone()
+        },
+        SeekFilter {
+            min_time: Some(Duration::from_secs(1200)),
+            ..filter.clone()
+        },
+        SeekFilter {
+            max_time: Some(Duration::from_secs(600)),
+            ..filter.clone()
+        },
+        SeekFilter {
+            rated: Some(false),
+            ..filter.clone()
+        },
+        SeekFilter {
+            komi: Some(4),
+            ..filter.clone()
+        },
+        SeekFilter {
+            stones: Some(30),
+            ..filter.clone()
+        },
+        SeekFilter {
+            capstones: Some(2),
+            ..filter.clone()
+        },
+    ] {
+        assert!(
+            !non_matching_filter.matches(&seek),
+            "{:?}",
+            non_matching_filter
+        );
+    }
+}
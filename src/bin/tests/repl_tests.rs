@@ -0,0 +1,53 @@
+use board_game_traits::Position as PositionTrait;
+use pgn_traits::PgnPosition;
+use tiltak::position::Position;
+
+use crate::repl::Repl;
+
+#[test]
+fn scripted_repl_session_test() {
+    let mut repl = <Repl<5>>::default();
+
+    assert!(repl.execute("move a1").is_ok());
+    assert!(repl.execute("move e5").is_ok());
+    assert!(repl.execute("move c3").is_ok());
+    assert_eq!(repl.position.half_moves_played(), 3);
+
+    assert!(repl.execute("undo").is_ok());
+    let mut position = <Position<5>>::start_position();
+    position.do_san_move("a1").unwrap();
+    position.do_san_move("e5").unwrap();
+    assert_eq!(repl.execute("tps"), Ok(position.to_fen()));
+
+    assert!(repl.execute("pv").is_err());
+    let output = repl.execute("go 1000").unwrap();
+    assert!(output.contains("best move"), "{}", output);
+    let pv = repl.execute("pv").unwrap();
+    assert!(!pv.is_empty());
+
+    // Playing the first move of the pv keeps the searched subtree
+    let first_move = pv.split_whitespace().next().unwrap().to_string();
+    assert!(repl.execute(&format!("move {}", first_move)).is_ok());
+    assert!(repl.execute("pv").is_ok());
+
+    assert!(repl
+        .execute("eval")
+        .unwrap()
+        .contains("winning probability"));
+}
+
+#[test]
+fn invalid_repl_commands_test() {
+    let mut repl = <Repl<5>>::default();
+
+    assert!(repl.execute("undo").is_err());
+    assert!(repl.execute("move").is_err());
+    assert!(repl.execute("move z9").is_err());
+    assert!(repl.execute("go many").is_err());
+    assert!(repl.execute("castle").is_err());
+    assert_eq!(repl.position.half_moves_played(), 0);
+
+    repl.execute("move a1").unwrap();
+    assert!(repl.execute("move a1").is_err());
+    assert_eq!(repl.position.half_moves_played(), 1);
+}
@@ -0,0 +1,94 @@
+use board_game_traits::Position as PositionTrait;
+use pgn_traits::PgnPosition;
+use tiltak::position::Position;
+
+use crate::tei::{analyze_search_moves, parse_search_moves, TeiBestmove, TeiInfo};
+
+#[test]
+fn format_info_test() {
+    let info = TeiInfo {
+        currmove: None,
+        depth: Some(3),
+        seldepth: Some(5),
+        score_cp: Some(-12),
+        score_winrate: None,
+        nodes: Some(1200),
+        time_ms: Some(45),
+        pv: vec!["c3".to_string(), "d3".to_string(), "1c3>".to_string()],
+    };
+    assert_eq!(
+        info.to_string(),
+        "info depth 3 seldepth 5 score cp -12 nodes 1200 time 45 pv c3 d3 1c3>"
+    );
+}
+
+#[test]
+fn format_partial_info_test() {
+    let info = TeiInfo {
+        score_cp: Some(40),
+        time_ms: Some(1000),
+        pv: vec!["Cc3".to_string()],
+        ..TeiInfo::default()
+    };
+    assert_eq!(info.to_string(), "info score cp 40 time 1000 pv Cc3");
+}
+
+#[test]
+fn format_bestmove_test() {
+    let bestmove = TeiBestmove {
+        mv: "2a1>11".to_string(),
+    };
+    assert_eq!(bestmove.to_string(), "bestmove 2a1>11");
+}
+
+#[test]
+fn searchmoves_only_searches_given_move_test() {
+    let mut position = <Position<5>>::start_position();
+    for move_string in ["a1", "e5"] {
+        position.do_move(position.move_from_san(move_string).unwrap());
+    }
+    let c3 = position.move_from_san("c3").unwrap();
+
+    let (infos, bestmove) = analyze_search_moves(&position, &[c3], 1000);
+
+    assert_eq!(bestmove.to_string(), "bestmove c3");
+    assert_eq!(infos.len(), 1);
+    assert_eq!(infos[0].currmove.as_deref(), Some("c3"));
+    assert!(infos[0].score_cp.is_some());
+    assert!(infos[0]
+        .to_string()
+        .starts_with("info currmove c3 score cp "));
+}
+
+#[test]
+fn parse_invalid_searchmoves_test() {
+    let mut position = <Position<5>>::start_position();
+    for move_string in ["a1", "e5"] {
+        position.do_move(position.move_from_san(move_string).unwrap());
+    }
+    assert_eq!(
+        parse_search_moves(&position, &["c3", "d3"]).unwrap(),
+        vec![
+            position.move_from_san("c3").unwrap(),
+            position.move_from_san("d3").unwrap()
+        ]
+    );
+    assert!(parse_search_moves(&position, &["c3", "x9"]).is_err());
+    // a1 is occupied
+    assert!(parse_search_moves(&position, &["c3", "a1"]).is_err());
+}
+
+#[test]
+fn score_cp_and_winrate_test() {
+    let info = TeiInfo::from_score(0.5);
+    assert_eq!(info.score_cp, Some(0));
+    assert_eq!(info.to_string(), "info score cp 0 score winrate 0.500");
+
+    let info = TeiInfo::from_score(0.9);
+    assert!(info.score_cp.unwrap() > 100);
+    assert!(info.to_string().ends_with("score winrate 0.900"));
+
+    assert!(TeiInfo::from_score(0.1).score_cp.unwrap() < -100);
+    assert!(TeiInfo::from_score(1.0).score_cp.unwrap() > 0);
+    assert!(TeiInfo::from_score(0.0).score_cp.unwrap() < 0);
+}
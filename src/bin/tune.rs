@@ -1,3 +1,5 @@
+use std::fs::File;
+use std::io::BufWriter;
 use std::path::Path;
 
 use clap::{App, Arg, SubCommand};
@@ -7,7 +9,7 @@ use tiltak::evaluation::parameters::{
     NUM_VALUE_PARAMS_5S, NUM_VALUE_PARAMS_6S, POLICY_PARAMS_4S, POLICY_PARAMS_5S, POLICY_PARAMS_6S,
     VALUE_PARAMS_4S, VALUE_PARAMS_5S, VALUE_PARAMS_6S,
 };
-use tiltak::tune::{spsa, training};
+use tiltak::tune::{export, spsa, training};
 
 fn main() {
     let app = App::new("Tiltak variable tuning")
@@ -33,6 +35,22 @@ fn main() {
                     .index(1)
                     .required(true)
                     .value_name("games.ptn")))
+        .subcommand(SubCommand::with_name("value-from-positions-file")
+                .about("Tune value constants from randomly initialized values, using a positions file written by export-positions. Faster than value-from-file, because no games are parsed.")
+                .arg(Arg::with_name("file-name")
+                    .index(1)
+                    .required(true)
+                    .value_name("positions.txt")))
+        .subcommand(SubCommand::with_name("export-positions")
+                .about("Write every position in the given ptn file to a positions file, along with the result of its game.")
+                .arg(Arg::with_name("ptn-file-name")
+                    .index(1)
+                    .required(true)
+                    .value_name("games.ptn"))
+                .arg(Arg::with_name("positions-file-name")
+                    .index(2)
+                    .required(true)
+                    .value_name("positions.txt")))
         .subcommand(
             SubCommand::with_name("both-from-file")
                 .about("Tune value and policy constants from randomly initialized values, using the given text file")
@@ -44,6 +62,36 @@ fn main() {
                     .index(2)
                     .required(true)
                     .value_name("move_scores.txt"))
+                .arg(Arg::with_name("seed")
+                    .long("seed")
+                    .takes_value(true)
+                    .help("Seed for shuffling the training data. Uses a fixed default seed if not set."))
+        )
+        .subcommand(SubCommand::with_name("export-training-binary")
+                .about("Write the games in the given ptn file and their move scores to a binary training data file, which is much faster to read.")
+                .arg(Arg::with_name("value-file-name")
+                    .index(1)
+                    .required(true)
+                    .value_name("games.ptn"))
+                .arg(Arg::with_name("policy-file-name")
+                    .index(2)
+                    .required(true)
+                    .value_name("move_scores.txt"))
+                .arg(Arg::with_name("binary-file-name")
+                    .index(3)
+                    .required(true)
+                    .value_name("training_data.bin")))
+        .subcommand(
+            SubCommand::with_name("both-from-binary-file")
+                .about("Tune value and policy constants from randomly initialized values, using a training data file written by export-training-binary")
+                .arg(Arg::with_name("file-name")
+                    .index(1)
+                    .required(true)
+                    .value_name("training_data.bin"))
+                .arg(Arg::with_name("seed")
+                    .long("seed")
+                    .takes_value(true)
+                    .help("Seed for shuffling the training data. Uses a fixed default seed if not set."))
         )
         .subcommand(SubCommand::with_name("spsa")
             .about("Tune exploration parameters using SPSA. Starting values are hard-coded.")
@@ -144,9 +192,52 @@ fn main() {
                 _ => panic!("Size {} not supported.", size),
             }
         }
+        ("value-from-positions-file", Some(arg)) => {
+            let file_name = arg.value_of("file-name").unwrap();
+            match size {
+                4 => {
+                    let value_params = training::tune_value_from_positions_file::<
+                        4,
+                        NUM_VALUE_PARAMS_4S,
+                    >(file_name)
+                    .unwrap();
+                    println!("{:?}", value_params);
+                }
+                5 => {
+                    let value_params = training::tune_value_from_positions_file::<
+                        5,
+                        NUM_VALUE_PARAMS_5S,
+                    >(file_name)
+                    .unwrap();
+                    println!("{:?}", value_params);
+                }
+                6 => {
+                    let value_params = training::tune_value_from_positions_file::<
+                        6,
+                        NUM_VALUE_PARAMS_6S,
+                    >(file_name)
+                    .unwrap();
+                    println!("{:?}", value_params);
+                }
+                _ => panic!("Size {} not supported.", size),
+            }
+        }
+        ("export-positions", Some(arg)) => {
+            let ptn_file_name = arg.value_of("ptn-file-name").unwrap();
+            let positions_file_name = arg.value_of("positions-file-name").unwrap();
+            match size {
+                4 => export_positions::<4>(ptn_file_name, positions_file_name),
+                5 => export_positions::<5>(ptn_file_name, positions_file_name),
+                6 => export_positions::<6>(ptn_file_name, positions_file_name),
+                _ => panic!("Size {} not supported.", size),
+            }
+        }
         ("both-from-file", Some(arg)) => {
             let value_file_name = arg.value_of("value-file-name").unwrap();
             let policy_file_name = arg.value_of("policy-file-name").unwrap();
+            let shuffle_seed = arg
+                .value_of("seed")
+                .map(|seed| seed.parse::<u64>().unwrap());
             match size {
                 4 => {
                     let (value_params, policy_params) =
@@ -154,7 +245,7 @@ fn main() {
                             4,
                             NUM_VALUE_PARAMS_4S,
                             NUM_POLICY_PARAMS_4S,
-                        >(value_file_name, policy_file_name)
+                        >(value_file_name, policy_file_name, shuffle_seed)
                         .unwrap();
                     println!("Value: {:?}", value_params);
                     println!("Policy: {:?}", policy_params);
@@ -165,7 +256,7 @@ fn main() {
                             5,
                             NUM_VALUE_PARAMS_5S,
                             NUM_POLICY_PARAMS_5S,
-                        >(value_file_name, policy_file_name)
+                        >(value_file_name, policy_file_name, shuffle_seed)
                         .unwrap();
                     println!("Value: {:?}", value_params);
                     println!("Policy: {:?}", policy_params);
@@ -176,7 +267,66 @@ fn main() {
                             6,
                             NUM_VALUE_PARAMS_6S,
                             NUM_POLICY_PARAMS_6S,
-                        >(value_file_name, policy_file_name)
+                        >(value_file_name, policy_file_name, shuffle_seed)
+                        .unwrap();
+                    println!("Value: {:?}", value_params);
+                    println!("Policy: {:?}", policy_params);
+                }
+                _ => panic!("Size {} not supported.", size),
+            }
+        }
+        ("export-training-binary", Some(arg)) => {
+            let value_file_name = arg.value_of("value-file-name").unwrap();
+            let policy_file_name = arg.value_of("policy-file-name").unwrap();
+            let binary_file_name = arg.value_of("binary-file-name").unwrap();
+            match size {
+                4 => {
+                    export_training_binary::<4>(value_file_name, policy_file_name, binary_file_name)
+                }
+                5 => {
+                    export_training_binary::<5>(value_file_name, policy_file_name, binary_file_name)
+                }
+                6 => {
+                    export_training_binary::<6>(value_file_name, policy_file_name, binary_file_name)
+                }
+                _ => panic!("Size {} not supported.", size),
+            }
+        }
+        ("both-from-binary-file", Some(arg)) => {
+            let file_name = arg.value_of("file-name").unwrap();
+            let shuffle_seed = arg
+                .value_of("seed")
+                .map(|seed| seed.parse::<u64>().unwrap());
+            match size {
+                4 => {
+                    let (value_params, policy_params) =
+                        training::tune_value_and_policy_from_binary_file::<
+                            4,
+                            NUM_VALUE_PARAMS_4S,
+                            NUM_POLICY_PARAMS_4S,
+                        >(file_name, shuffle_seed)
+                        .unwrap();
+                    println!("Value: {:?}", value_params);
+                    println!("Policy: {:?}", policy_params);
+                }
+                5 => {
+                    let (value_params, policy_params) =
+                        training::tune_value_and_policy_from_binary_file::<
+                            5,
+                            NUM_VALUE_PARAMS_5S,
+                            NUM_POLICY_PARAMS_5S,
+                        >(file_name, shuffle_seed)
+                        .unwrap();
+                    println!("Value: {:?}", value_params);
+                    println!("Policy: {:?}", policy_params);
+                }
+                6 => {
+                    let (value_params, policy_params) =
+                        training::tune_value_and_policy_from_binary_file::<
+                            6,
+                            NUM_VALUE_PARAMS_6S,
+                            NUM_POLICY_PARAMS_6S,
+                        >(file_name, shuffle_seed)
                         .unwrap();
                     println!("Value: {:?}", value_params);
                     println!("Policy: {:?}", policy_params);
@@ -216,3 +366,27 @@ fn main() {
         _ => unreachable!(),
     }
 }
+
+fn export_positions<const S: usize>(ptn_file_name: &str, positions_file_name: &str) {
+    let games = training::read_games_from_file::<S>(ptn_file_name).unwrap();
+    let (positions, results) = training::positions_and_results_from_games(games);
+    let file = BufWriter::new(File::create(positions_file_name).unwrap());
+    export::write_positions(&positions, &results, file).unwrap();
+    println!(
+        "Wrote {} positions to {}",
+        positions.len(),
+        positions_file_name
+    );
+}
+
+fn export_training_binary<const S: usize>(
+    value_file_name: &str,
+    policy_file_name: &str,
+    binary_file_name: &str,
+) {
+    let games = training::read_games_from_file::<S>(value_file_name).unwrap();
+    let move_scoress = training::read_move_scores_from_file::<S>(policy_file_name).unwrap();
+    let file = BufWriter::new(File::create(binary_file_name).unwrap());
+    export::write_training_binary(&games, &move_scoress, file).unwrap();
+    println!("Wrote {} games to {}", games.len(), binary_file_name);
+}
@@ -0,0 +1,31 @@
+//! Helpers for reading the little-endian binary formats of search trees and training data.
+
+use std::io;
+use std::io::Read;
+
+use crate::position::Move;
+
+pub(crate) fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+pub(crate) fn read_bytes<R: Read, const N: usize>(reader: &mut R) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+pub(crate) fn read_u8<R: Read>(reader: &mut R) -> io::Result<u8> {
+    Ok(u8::from_le_bytes(read_bytes(reader)?))
+}
+
+pub(crate) fn read_u16<R: Read>(reader: &mut R) -> io::Result<u16> {
+    Ok(u16::from_le_bytes(read_bytes(reader)?))
+}
+
+/// Read a move in the encoding of `Move::to_u16`. The move is not checked for legality.
+pub(crate) fn read_move<R: Read, const S: usize>(reader: &mut R) -> io::Result<Move> {
+    let data = read_u16(reader)?;
+    Move::from_u16::<S>(data)
+        .ok_or_else(|| invalid_data(format!("Invalid move encoding {:#x}", data)))
+}
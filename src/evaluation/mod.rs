@@ -1,3 +1,19 @@
 pub mod parameters;
 pub mod policy_eval;
 pub mod value_eval;
+
+/// Expand a list of features and their lengths into a name for each coefficient
+pub(crate) fn feature_names(features: &[(&str, usize)]) -> Vec<String> {
+    features
+        .iter()
+        .flat_map(|&(name, len)| {
+            (0..len).map(move |i| {
+                if len == 1 {
+                    name.to_string()
+                } else {
+                    format!("{}[{}]", name, i)
+                }
+            })
+        })
+        .collect()
+}
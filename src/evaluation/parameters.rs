@@ -1,10 +1,10 @@
-pub const NUM_VALUE_PARAMS_4S: usize = 51;
+pub const NUM_VALUE_PARAMS_4S: usize = 59;
 pub const NUM_POLICY_PARAMS_4S: usize = 78;
 
-pub const NUM_VALUE_PARAMS_5S: usize = 69;
+pub const NUM_VALUE_PARAMS_5S: usize = 77;
 pub const NUM_POLICY_PARAMS_5S: usize = 93;
 
-pub const NUM_VALUE_PARAMS_6S: usize = 72;
+pub const NUM_VALUE_PARAMS_6S: usize = 80;
 pub const NUM_POLICY_PARAMS_6S: usize = 99;
 
 #[allow(clippy::unreadable_literal)]
@@ -60,6 +60,14 @@ pub const VALUE_PARAMS_4S: [f32; NUM_VALUE_PARAMS_4S] = [
     0.048655495,
     -0.0016141674,
     0.003347816,
+    0.0,
+    0.0,
+    0.0,
+    0.0,
+    0.0,
+    0.0,
+    0.0,
+    0.0,
 ];
 #[allow(clippy::unreadable_literal)]
 pub const POLICY_PARAMS_4S: [f32; NUM_POLICY_PARAMS_4S] = [
@@ -214,6 +222,14 @@ pub const VALUE_PARAMS_5S: [f32; NUM_VALUE_PARAMS_5S] = [
     0.103527844,
     0.08759902,
     -0.0380222,
+    0.0,
+    0.0,
+    0.0,
+    0.0,
+    0.0,
+    0.0,
+    0.0,
+    0.0,
 ];
 
 #[allow(clippy::unreadable_literal)]
@@ -387,6 +403,14 @@ pub const VALUE_PARAMS_6S: [f32; NUM_VALUE_PARAMS_6S] = [
     0.028714254,
     0.112023935,
     0.45978305,
+    0.0,
+    0.0,
+    0.0,
+    0.0,
+    0.0,
+    0.0,
+    0.0,
+    0.0,
 ];
 
 #[allow(clippy::unreadable_literal)]
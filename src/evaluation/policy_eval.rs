@@ -1,13 +1,17 @@
 use arrayvec::ArrayVec;
 
+use crate::evaluation::feature_names;
 use crate::position::bitboard::BitBoard;
 use crate::position::color_trait::ColorTr;
 use crate::position::Direction::*;
 use crate::position::Move;
 use crate::position::Role::{Cap, Flat, Wall};
 use crate::position::Square;
-use crate::position::{num_square_symmetries, square_symmetries, GroupData, Position};
+use crate::position::{
+    num_square_symmetries, square_symmetries, GroupData, Position, TunableBoard,
+};
 use crate::search;
+use board_game_traits::Position as PositionTrait;
 
 pub fn sigmoid(x: f32) -> f32 {
     1.0 / (1.0 + f32::exp(-x))
@@ -59,6 +63,52 @@ impl<const S: usize> Position<S> {
 
         sigmoid(total_value)
     }
+
+    /// Returns the policy coefficients for a move, each paired with the name of its feature.
+    pub fn policy_features(&self, mv: &Move) -> Vec<(String, f32)> {
+        let group_data = self.group_data();
+        let mut legal_moves = vec![];
+        self.generate_moves(&mut legal_moves);
+
+        let mut coefficients = vec![0.0; Self::policy_params().len()];
+        self.coefficients_for_move(&mut coefficients, mv, &group_data, legal_moves.len());
+
+        policy_feature_names::<S>()
+            .into_iter()
+            .zip(coefficients)
+            .collect()
+    }
+}
+
+/// Names of each policy coefficient, in the order they are written by `coefficients_for_move_colortr`
+pub(crate) fn policy_feature_names<const S: usize>() -> Vec<String> {
+    let features = &[
+        ("move_count", 1),
+        ("flat_psqt", num_square_symmetries::<S>()),
+        ("wall_psqt", num_square_symmetries::<S>()),
+        ("cap_psqt", num_square_symmetries::<S>()),
+        ("our_road_stones_in_line", S * 3),
+        ("their_road_stones_in_line", S * 3),
+        ("extend_group", 3),
+        ("merge_two_groups", 3),
+        ("block_merger", 3),
+        ("place_critical_square", 5),
+        ("ignore_critical_square", 2),
+        ("next_to_our_last_stone", 1),
+        ("next_to_their_last_stone", 1),
+        ("diagonal_to_our_last_stone", 1),
+        ("diagonal_to_their_last_stone", 1),
+        ("attack_strong_flats", 1),
+        ("blocking_stone_blocks_extensions_of_two_flats", 1),
+        ("move_role_bonus", 3),
+        ("stack_movement_that_gives_us_top_pieces", 6),
+        ("stack_captured_by_movement", 1),
+        ("stack_capture_in_strong_line", 2),
+        ("stack_capture_in_strong_line_cap", 2),
+        ("move_cap_onto_strong_line", 4),
+        ("move_onto_critical_square", 4),
+    ];
+    feature_names(features)
 }
 pub(crate) fn coefficients_for_move_colortr<Us: ColorTr, Them: ColorTr, const S: usize>(
     position: &Position<S>,
@@ -108,7 +158,7 @@ pub(crate) fn coefficients_for_move_colortr<Us: ColorTr, Them: ColorTr, const S:
     match mv {
         Move::Place(role, square) => {
             let their_open_critical_squares =
-                Them::critical_squares(&*group_data) & (!group_data.all_pieces());
+                Them::critical_squares(group_data) & (!group_data.all_pieces());
 
             // Apply PSQT
             match role {
@@ -124,8 +174,8 @@ pub(crate) fn coefficients_for_move_colortr<Us: ColorTr, Them: ColorTr, const S:
             };
 
             for &line in BitBoard::lines_for_square::<S>(*square).iter() {
-                let our_line_score = (Us::road_stones(&group_data) & line).count();
-                let their_line_score = (Them::road_stones(&group_data) & line).count();
+                let our_line_score = (Us::road_stones(group_data) & line).count();
+                let their_line_score = (Them::road_stones(group_data) & line).count();
                 coefficients[our_road_stones_in_line + S * role_id + our_line_score as usize] +=
                     1.0;
                 coefficients
@@ -169,7 +219,7 @@ pub(crate) fn coefficients_for_move_colortr<Us: ColorTr, Them: ColorTr, const S:
             }
 
             if *role == Flat || *role == Cap {
-                if Us::is_critical_square(&*group_data, *square) {
+                if Us::is_critical_square(group_data, *square) {
                     coefficients[place_critical_square] += 1.0;
                 } else if !their_open_critical_squares.is_empty() {
                     if their_open_critical_squares == BitBoard::empty().set(square.0) {
@@ -180,8 +230,12 @@ pub(crate) fn coefficients_for_move_colortr<Us: ColorTr, Them: ColorTr, const S:
                 }
 
                 // If square is next to a road stone laid on our last turn
-                if let Some(Move::Place(last_role, last_square)) =
-                    position.moves().get(position.moves().len() - 2)
+                // Positions set up from TPS may have fewer than two moves played
+                if let Some(Move::Place(last_role, last_square)) = position
+                    .moves()
+                    .len()
+                    .checked_sub(2)
+                    .and_then(|i| position.moves().get(i))
                 {
                     if *last_role == Flat || *last_role == Cap {
                         if square.neighbours::<S>().any(|neigh| neigh == *last_square) {
@@ -236,7 +290,7 @@ pub(crate) fn coefficients_for_move_colortr<Us: ColorTr, Them: ColorTr, const S:
                     }
                 }
             } else if *role == Cap {
-                if Us::is_critical_square(&*group_data, *square) {
+                if Us::is_critical_square(group_data, *square) {
                     coefficients[place_critical_square] += 1.0;
                 } else if !their_open_critical_squares.is_empty() {
                     if their_open_critical_squares == BitBoard::empty().set(square.0) {
@@ -338,7 +392,7 @@ pub(crate) fn coefficients_for_move_colortr<Us: ColorTr, Them: ColorTr, const S:
                                 destination_stack.len() as f32;
                         }
                     }
-                    if Us::is_critical_square(&*group_data, destination_square) {
+                    if Us::is_critical_square(group_data, destination_square) {
                         gets_critical_square = true;
                     }
 
@@ -375,7 +429,7 @@ pub(crate) fn coefficients_for_move_colortr<Us: ColorTr, Them: ColorTr, const S:
             }
 
             let their_open_critical_squares =
-                Them::critical_squares(&*group_data) & (!group_data.all_pieces());
+                Them::critical_squares(group_data) & (!group_data.all_pieces());
 
             if !their_open_critical_squares.is_empty() {
                 if their_pieces_captured == 0 {
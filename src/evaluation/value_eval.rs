@@ -1,10 +1,11 @@
 use board_game_traits::{Color, Position as EvalPosition};
 
+use crate::evaluation::feature_names;
 use crate::position::bitboard::BitBoard;
 use crate::position::color_trait::{BlackTr, ColorTr, WhiteTr};
 use crate::position::{
-    num_square_symmetries, square_symmetries, squares_iterator, GroupData, Piece::*, Position,
-    Role::*, Square,
+    has_road, num_square_symmetries, square_symmetries, squares_iterator, GroupData,
+    GroupEdgeConnection, Piece::*, Position, Role::*, Square, TunableBoard,
 };
 
 pub(crate) fn static_eval_game_phase<const S: usize>(
@@ -58,8 +59,9 @@ pub(crate) fn static_eval_game_phase<const S: usize>(
     let flatstone_lead: usize = side_to_move + 3;
     let i_number_of_groups: usize = flatstone_lead + 3;
 
-    // Give the side to move a bonus/malus depending on flatstone lead
-    let white_flatstone_lead = white_flat_count - black_flat_count;
+    // Give the side to move a bonus/malus depending on flatstone lead, including komi
+    let white_flatstone_lead =
+        white_flat_count as f32 - black_flat_count as f32 - position.komi() as f32 / 2.0;
 
     // Bonus/malus depending on the number of groups each side has
     let mut seen_groups = vec![false; S * S + 1]; // TODO: Can be an array with full const-generics
@@ -77,31 +79,27 @@ pub(crate) fn static_eval_game_phase<const S: usize>(
         })
         .sum::<isize>() as f32;
 
-    let opening_scale_factor = f32::min(
-        f32::max((24.0 - position.half_moves_played() as f32) / 12.0, 0.0),
-        1.0,
-    );
-    let endgame_scale_factor = f32::min(
-        f32::max((position.half_moves_played() as f32 - 24.0) / 24.0, 0.0),
-        1.0,
-    );
+    let opening_scale_factor =
+        ((24.0 - position.half_moves_played() as f32) / 12.0).clamp(0.0, 1.0);
+    let endgame_scale_factor =
+        ((position.half_moves_played() as f32 - 24.0) / 24.0).clamp(0.0, 1.0);
     let middlegame_scale_factor = 1.0 - opening_scale_factor - endgame_scale_factor;
 
     debug_assert!(middlegame_scale_factor <= 1.0);
     debug_assert!(opening_scale_factor == 0.0 || endgame_scale_factor == 0.0);
 
     coefficients[side_to_move] = position.side_to_move().multiplier() as f32 * opening_scale_factor;
-    coefficients[flatstone_lead] = white_flatstone_lead as f32 * opening_scale_factor;
+    coefficients[flatstone_lead] = white_flatstone_lead * opening_scale_factor;
     coefficients[i_number_of_groups] = number_of_groups * opening_scale_factor;
 
     coefficients[side_to_move + 1] =
         position.side_to_move().multiplier() as f32 * middlegame_scale_factor;
-    coefficients[flatstone_lead + 1] = white_flatstone_lead as f32 * middlegame_scale_factor;
+    coefficients[flatstone_lead + 1] = white_flatstone_lead * middlegame_scale_factor;
     coefficients[i_number_of_groups + 1] = number_of_groups * middlegame_scale_factor;
 
     coefficients[side_to_move + 2] =
         position.side_to_move().multiplier() as f32 * endgame_scale_factor;
-    coefficients[flatstone_lead + 2] = white_flatstone_lead as f32 * endgame_scale_factor;
+    coefficients[flatstone_lead + 2] = white_flatstone_lead * endgame_scale_factor;
     coefficients[i_number_of_groups + 2] = number_of_groups * endgame_scale_factor;
 
     let critical_squares: usize = i_number_of_groups + 3;
@@ -187,24 +185,24 @@ pub(crate) fn static_eval_game_phase<const S: usize>(
     let mut num_files_occupied_black = 0;
 
     for line in BitBoard::all_lines::<S>().iter() {
-        line_score::<WhiteTr, BlackTr, S>(&group_data, *line, coefficients, line_control);
-        line_score::<BlackTr, WhiteTr, S>(&group_data, *line, coefficients, line_control);
+        line_score::<WhiteTr, BlackTr, S>(group_data, *line, coefficients, line_control);
+        line_score::<BlackTr, WhiteTr, S>(group_data, *line, coefficients, line_control);
     }
 
     for i in 0..S as u8 {
-        if !WhiteTr::road_stones(&group_data).rank::<S>(i).is_empty() {
+        if !WhiteTr::road_stones(group_data).rank::<S>(i).is_empty() {
             num_ranks_occupied_white += 1;
         }
-        if !BlackTr::road_stones(&group_data).rank::<S>(i).is_empty() {
+        if !BlackTr::road_stones(group_data).rank::<S>(i).is_empty() {
             num_ranks_occupied_black += 1;
         }
     }
 
     for i in 0..S as u8 {
-        if !WhiteTr::road_stones(&group_data).file::<S>(i).is_empty() {
+        if !WhiteTr::road_stones(group_data).file::<S>(i).is_empty() {
             num_files_occupied_white += 1;
         }
-        if !BlackTr::road_stones(&group_data).file::<S>(i).is_empty() {
+        if !BlackTr::road_stones(group_data).file::<S>(i).is_empty() {
             num_files_occupied_black += 1;
         }
     }
@@ -214,11 +212,196 @@ pub(crate) fn static_eval_game_phase<const S: usize>(
     coefficients[num_lines_occupied + num_ranks_occupied_black] -= 1.0;
     coefficients[num_lines_occupied + num_files_occupied_black] -= 1.0;
 
-    let _next_const = line_control + 2 * (S + 1);
+    // Bonus for keeping the capstone in reserve, depending on game phase
+    let capstone_in_reserve: usize = line_control + 2 * (S + 1);
+    let caps_in_reserve = position.white_caps_left() as f32 - position.black_caps_left() as f32;
+
+    coefficients[capstone_in_reserve] = caps_in_reserve * opening_scale_factor;
+    coefficients[capstone_in_reserve + 1] = caps_in_reserve * middlegame_scale_factor;
+    coefficients[capstone_in_reserve + 2] = caps_in_reserve * endgame_scale_factor;
+
+    // Malus for road stones that would cut part of their group off from an edge if captured
+    let road_articulation_points: usize = capstone_in_reserve + 3;
+    coefficients[road_articulation_points] =
+        road_articulation_points_count::<WhiteTr, S>(group_data) as f32
+            - road_articulation_points_count::<BlackTr, S>(group_data) as f32;
+
+    // Bonus for the group that spans the most files (horizontal progress) and ranks (vertical progress)
+    let road_track_progress: usize = road_articulation_points + 1;
+    let (white_files, white_ranks) = road_track_progress_count::<WhiteTr, S>(group_data);
+    let (black_files, black_ranks) = road_track_progress_count::<BlackTr, S>(group_data);
+    coefficients[road_track_progress] = white_files as f32 - black_files as f32;
+    coefficients[road_track_progress + 1] = white_ranks as f32 - black_ranks as f32;
+
+    // Bonus for flats on top of tall stacks, which are hard to dislodge
+    let flat_stack_height: usize = road_track_progress + 2;
+    for square in squares_iterator::<S>() {
+        let stack = &position[square];
+        if let Some(piece) = stack.top_stone() {
+            if piece.role() == Flat {
+                coefficients[flat_stack_height] +=
+                    piece.color().multiplier() as f32 * (stack.height - 1) as f32;
+            }
+        }
+    }
+
+    // Bonus for roads that are only blocked by a single wall, which our capstone can smash
+    // Its weights are not tuned yet, so it is left at zero unless the feature is enabled
+    let cap_smash_road: usize = flat_stack_height + 1;
+    if cfg!(feature = "cap-smash-road") {
+        coefficients[cap_smash_road] =
+            cap_smash_road_count::<WhiteTr, BlackTr, S>(position, group_data) as f32
+                - cap_smash_road_count::<BlackTr, WhiteTr, S>(position, group_data) as f32;
+    }
+
+    let _next_const = cap_smash_road + 1;
 
     assert_eq!(_next_const, coefficients.len());
 }
 
+impl<const S: usize> Position<S> {
+    /// Returns the value coefficients for the position, each paired with the name of its feature.
+    pub fn value_features(&self) -> Vec<(String, f32)> {
+        let mut coefficients = vec![0.0; Self::value_params().len()];
+        static_eval_game_phase(self, &self.group_data(), &mut coefficients);
+
+        value_feature_names::<S>()
+            .into_iter()
+            .zip(coefficients)
+            .collect()
+    }
+}
+
+/// Names of each value coefficient, in the order they are written by `static_eval_game_phase`
+pub(crate) fn value_feature_names<const S: usize>() -> Vec<String> {
+    let features = &[
+        ("flat_psqt", num_square_symmetries::<S>()),
+        ("wall_psqt", num_square_symmetries::<S>()),
+        ("cap_psqt", num_square_symmetries::<S>()),
+        ("our_stack_psqt", num_square_symmetries::<S>()),
+        ("their_stack_psqt", num_square_symmetries::<S>()),
+        ("side_to_move", 3),
+        ("flatstone_lead", 3),
+        ("number_of_groups", 3),
+        ("critical_squares", 6),
+        ("capstone_over_own_piece", 1),
+        ("capstone_on_stack", 1),
+        ("standing_stone_on_stack", 1),
+        ("flat_stone_next_to_our_stack", 1),
+        ("standing_stone_next_to_our_stack", 1),
+        ("capstone_next_to_our_stack", 1),
+        ("num_lines_occupied", S + 1),
+        ("line_control", S + 1),
+        ("block_their_line", S + 1),
+        ("capstone_in_reserve", 3),
+        ("road_articulation_points", 1),
+        ("road_track_progress", 2),
+        ("flat_stack_height", 1),
+        ("cap_smash_road", 1),
+    ];
+    feature_names(features)
+}
+
+/// Number of our road stones that connect two otherwise separate parts of a group,
+/// where one of the parts would lose its connection to an edge that the rest of the group touches
+fn road_articulation_points_count<Us: ColorTr, const S: usize>(group_data: &GroupData<S>) -> u8 {
+    let road_stones = Us::road_stones(group_data);
+    let mut count = 0;
+    for square in road_stones {
+        let neighbours = BitBoard::empty().set(square.0).adjacent::<S>() & road_stones;
+        if neighbours.count() < 2 {
+            continue;
+        }
+        let remaining_stones = road_stones.clear(square.0);
+        let group_edges = edge_connection::<S>(
+            BitBoard::empty()
+                .set(square.0)
+                .flood_fill::<S>(road_stones)
+                .clear(square.0),
+        );
+        if neighbours.into_iter().any(|neighbour| {
+            let part = BitBoard::empty()
+                .set(neighbour.0)
+                .flood_fill::<S>(remaining_stones);
+            edge_connection::<S>(part) != group_edges
+        }) {
+            count += 1;
+        }
+    }
+    count
+}
+
+fn edge_connection<const S: usize>(group: BitBoard) -> GroupEdgeConnection {
+    group
+        .into_iter()
+        .fold(GroupEdgeConnection::default(), |edges, square| {
+            edges.connect_square::<S>(square)
+        })
+}
+
+/// Number of their walls that our capstone can smash to complete a road.
+/// A capstone on a stack can reach walls in a straight line, as far as the number of pieces it can carry,
+/// if no other walls or capstones are in the way. The pieces it drops on the way are ignored.
+/// The square the capstone moves from is only part of the road if it leaves our flat behind
+fn cap_smash_road_count<Us: ColorTr, Them: ColorTr, const S: usize>(
+    position: &Position<S>,
+    group_data: &GroupData<S>,
+) -> u8 {
+    let road_stones = Us::road_stones(group_data);
+    let their_walls = Them::walls(group_data);
+    let blockers =
+        Us::walls(group_data) | their_walls | Us::caps(group_data) | Them::caps(group_data);
+    let mut count = 0;
+    for cap_square in Us::caps(group_data) {
+        let stack = &position[cap_square];
+        let road_stones_after_move =
+            if stack.len() > 1 && stack.get(stack.len() - 2) == Some(Us::flat_piece()) {
+                road_stones
+            } else {
+                road_stones.clear(cap_square.0)
+            };
+        let reach = (stack.len() as usize).min(S);
+        for direction in cap_square.directions::<S>() {
+            let mut square = cap_square;
+            for _ in 0..reach {
+                match square.go_direction::<S>(direction) {
+                    Some(next_square) => square = next_square,
+                    None => break,
+                }
+                if their_walls.get(square.0) {
+                    if has_road::<S>(road_stones_after_move.set(square.0)) {
+                        count += 1;
+                    }
+                    break;
+                }
+                if blockers.get(square.0) {
+                    break;
+                }
+            }
+        }
+    }
+    count
+}
+
+/// The largest number of files, and the largest number of ranks, spanned by any one of our road groups
+fn road_track_progress_count<Us: ColorTr, const S: usize>(group_data: &GroupData<S>) -> (u8, u8) {
+    let mut road_stones = Us::road_stones(group_data);
+    let (mut max_files, mut max_ranks) = (0, 0);
+    while let Some(square) = road_stones.into_iter().next() {
+        let group = BitBoard::empty().set(square.0).flood_fill::<S>(road_stones);
+        let files = (0..S as u8)
+            .filter(|i| !group.file::<S>(*i).is_empty())
+            .count() as u8;
+        let ranks = (0..S as u8)
+            .filter(|i| !group.rank::<S>(*i).is_empty())
+            .count() as u8;
+        max_files = max_files.max(files);
+        max_ranks = max_ranks.max(ranks);
+        road_stones &= !group;
+    }
+    (max_files, max_ranks)
+}
+
 /// Give bonus for our critical squares
 fn critical_squares_eval<Us: ColorTr, Them: ColorTr, const S: usize>(
     position: &Position<S>,
@@ -0,0 +1,63 @@
+//! Bookkeeping of the time used by each side in a game.
+
+use std::time::Duration;
+
+use board_game_traits::Color;
+
+/// Tracks the time used and the number of moves played by each side in a game
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct GameClock {
+    white_time: Duration,
+    black_time: Duration,
+    white_moves: u32,
+    black_moves: u32,
+}
+
+impl GameClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a move by `color` that took `elapsed`
+    pub fn record_move(&mut self, color: Color, elapsed: Duration) {
+        match color {
+            Color::White => {
+                self.white_time += elapsed;
+                self.white_moves += 1;
+            }
+            Color::Black => {
+                self.black_time += elapsed;
+                self.black_moves += 1;
+            }
+        }
+    }
+
+    /// Total time used by `color`
+    pub fn time_used(&self, color: Color) -> Duration {
+        match color {
+            Color::White => self.white_time,
+            Color::Black => self.black_time,
+        }
+    }
+
+    /// Number of moves played by `color`
+    pub fn moves(&self, color: Color) -> u32 {
+        match color {
+            Color::White => self.white_moves,
+            Color::Black => self.black_moves,
+        }
+    }
+
+    /// Number of moves played by both sides
+    pub fn total_moves(&self) -> u32 {
+        self.white_moves + self.black_moves
+    }
+
+    /// Average time used per move by `color`, or zero if it has not moved
+    pub fn average_time_per_move(&self, color: Color) -> Duration {
+        match self.moves(color) {
+            0 => Duration::ZERO,
+            moves => self.time_used(color) / moves,
+        }
+    }
+}
@@ -0,0 +1,129 @@
+//! Import positions from links shared by community tools, such as ptn.ninja or the Tak playground.
+//!
+//! These links carry the game in their query string, as URL-encoded parameters:
+//! * `size`: The board size. Optional if `tps` is given.
+//! * `tps`: The starting position, in TPS notation. Defaults to the start position.
+//! * `moves` or `ptn`: A whitespace-separated list of moves, in PTN notation. Move numbers and game results are skipped.
+
+use board_game_traits::Position as PositionTrait;
+use pgn_traits::PgnPosition;
+
+use crate::position::{Move, Position};
+
+/// Parse a shared link, returning the starting position and the moves played from it.
+/// Returns an error if the link is for a different size than `S`, or if any of the moves are illegal.
+pub fn parse_shared_link<const S: usize>(
+    url: &str,
+) -> Result<(Position<S>, Vec<Move>), pgn_traits::Error> {
+    let params = query_params(url)?;
+    let param = |key: &str| {
+        params
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.as_str())
+    };
+
+    if let Some(size) = shared_link_size(url) {
+        if size != S {
+            return Err(pgn_traits::Error::new_parse_error(format!(
+                "Link is for a {}s game, expected {}s",
+                size, S
+            )));
+        }
+    }
+
+    let start_position = match param("tps") {
+        Some(tps) => Position::from_fen(tps)?,
+        None => Position::start_position(),
+    };
+
+    let mut position = start_position.clone();
+    let mut moves = vec![];
+    let mut legal_moves = vec![];
+
+    for word in param("moves")
+        .or_else(|| param("ptn"))
+        .unwrap_or_default()
+        .split_whitespace()
+        .filter(|word| !word.ends_with('.'))
+        .filter(|word| {
+            !<Position<S>>::POSSIBLE_GAME_RESULTS
+                .iter()
+                .any(|(result, _)| result == word)
+        })
+    {
+        let move_string = word.trim_end_matches(&['\'', '!', '?'][..]);
+        let mv = position.move_from_san(move_string)?;
+        position.generate_moves(&mut legal_moves);
+        if !legal_moves.contains(&mv) {
+            return Err(pgn_traits::Error::new(
+                pgn_traits::ErrorKind::IllegalMove,
+                word,
+            ));
+        }
+        legal_moves.clear();
+        position.do_move(mv.clone());
+        moves.push(mv);
+    }
+
+    Ok((start_position, moves))
+}
+
+/// Returns the board size of a shared link, if it can be determined.
+/// This is either the `size` parameter, or the size of the `tps` parameter.
+pub fn shared_link_size(url: &str) -> Option<usize> {
+    let params = query_params(url).ok()?;
+    if let Some((_, size)) = params.iter().find(|(key, _)| key == "size") {
+        size.parse().ok()
+    } else {
+        params
+            .iter()
+            .find(|(key, _)| key == "tps")
+            .and_then(|(_, tps)| tps.split_whitespace().next())
+            .map(|board| board.split('/').count())
+    }
+}
+
+fn query_params(url: &str) -> Result<Vec<(String, String)>, pgn_traits::Error> {
+    let query = match url.find(&['?', '#'][..]) {
+        Some(i) => &url[i + 1..],
+        None => url,
+    };
+
+    query
+        .split(&['&', '#'][..])
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let mut key_and_value = pair.splitn(2, '=');
+            let key = key_and_value.next().unwrap();
+            let value = key_and_value.next().unwrap_or_default();
+            Ok((percent_decode(key)?, percent_decode(value)?))
+        })
+        .collect()
+}
+
+fn percent_decode(input: &str) -> Result<String, pgn_traits::Error> {
+    let mut bytes = vec![];
+    let mut chars = input.bytes();
+    while let Some(byte) = chars.next() {
+        match byte {
+            b'+' => bytes.push(b' '),
+            b'%' => {
+                let hex: Vec<u8> = chars.by_ref().take(2).collect();
+                let value = std::str::from_utf8(&hex)
+                    .ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                    .filter(|_| hex.len() == 2)
+                    .ok_or_else(|| {
+                        pgn_traits::Error::new_parse_error(format!(
+                            "Invalid percent-encoding in \"{}\"",
+                            input
+                        ))
+                    })?;
+                bytes.push(value);
+            }
+            _ => bytes.push(byte),
+        }
+    }
+    String::from_utf8(bytes).map_err(pgn_traits::Error::new_parse_error)
+}
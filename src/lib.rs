@@ -34,11 +34,21 @@ pub use search::mcts;
 
 #[cfg(any(feature = "aws-lambda-runtime", feature = "aws-lambda-client"))]
 pub mod aws;
+mod binary_io;
+pub mod game_clock;
+pub mod interop;
 pub mod minmax;
 pub mod move_gen;
 pub mod position;
 pub mod search;
 #[cfg(test)]
+#[allow(
+    clippy::manual_is_multiple_of,
+    clippy::match_like_matches_macro,
+    clippy::needless_borrow,
+    clippy::useless_conversion,
+    clippy::useless_vec
+)]
 mod tests;
 #[cfg(feature = "constant-tuning")]
 pub mod tune;
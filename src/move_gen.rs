@@ -1,10 +1,102 @@
-use crate::position::color_trait::ColorTr;
+use board_game_traits::{Color, Position as PositionTrait};
+
+use crate::position::color_trait::{BlackTr, ColorTr, WhiteTr};
 use crate::position::Move;
 use crate::position::{
     squares_iterator, Direction, Movement, Piece, Position, Role::*, Square, StackMovement,
 };
 
 impl<const S: usize> Position<S> {
+    /// Count the number of leaf nodes in the game tree, `depth` plies from the current position.
+    /// Used to validate move generation against known answers. Stops at terminal positions.
+    pub fn perft(&mut self, depth: u16) -> u64 {
+        if depth == 0 || self.game_result().is_some() {
+            1
+        } else {
+            let mut moves = vec![];
+            self.generate_moves(&mut moves);
+            moves
+                .into_iter()
+                .map(|mv| self.perft_after_move(mv, depth - 1))
+                .sum()
+        }
+    }
+
+    /// Like `perft`, but returns the count for each legal move separately.
+    pub fn perft_divide(&mut self, depth: u16) -> Vec<(Move, u64)> {
+        let mut moves = vec![];
+        self.generate_moves(&mut moves);
+        moves
+            .into_iter()
+            .map(|mv| {
+                let num_moves = self.perft_after_move(mv.clone(), depth.saturating_sub(1));
+                (mv, num_moves)
+            })
+            .collect()
+    }
+
+    fn perft_after_move(&mut self, mv: Move, depth: u16) -> u64 {
+        let reverse_move = self.do_move(mv);
+        let num_moves = self.perft(depth);
+        self.reverse_move(reverse_move);
+        num_moves
+    }
+
+    /// Check whether a move is legal, without generating every legal move.
+    /// Gives the same result as checking if the move is generated by `generate_moves`
+    pub fn is_legal(&self, mv: &Move) -> bool {
+        if self.half_moves_played() < 2 {
+            return matches!(mv, Move::Place(Flat, square) if self[*square].is_empty());
+        }
+        match self.side_to_move() {
+            Color::White => self.is_legal_colortr::<WhiteTr>(mv),
+            Color::Black => self.is_legal_colortr::<BlackTr>(mv),
+        }
+    }
+
+    fn is_legal_colortr<Us: ColorTr>(&self, mv: &Move) -> bool {
+        match mv {
+            Move::Place(role, square) => {
+                self[*square].is_empty()
+                    && match role {
+                        Flat | Wall => Us::stones_left(self) > 0,
+                        Cap => Us::caps_left(self) > 0,
+                    }
+            }
+            Move::Move(square, direction, stack_movement) => {
+                let piece = match self[*square].top_stone() {
+                    Some(piece) if Us::piece_is_ours(piece) => piece,
+                    _ => return false,
+                };
+                if stack_movement.is_empty() {
+                    return false;
+                }
+                let mut max_pieces_to_take = self[*square].len().min(S as u8);
+                let mut current_square = *square;
+                for (i, Movement { pieces_to_take }) in stack_movement.into_iter().enumerate() {
+                    if pieces_to_take == 0 || pieces_to_take > max_pieces_to_take {
+                        return false;
+                    }
+                    current_square = match current_square.go_direction::<S>(*direction) {
+                        Some(neighbour) => neighbour,
+                        None => return false,
+                    };
+                    match self[current_square].top_stone().map(Piece::role) {
+                        None | Some(Flat) => (),
+                        // Only a lone capstone can flatten a wall, at the end of the move
+                        Some(Wall)
+                            if piece == Us::cap_piece()
+                                && pieces_to_take == 1
+                                && i == stack_movement.len() - 1 => {}
+                        Some(_) => return false,
+                    }
+                    max_pieces_to_take = pieces_to_take - 1;
+                }
+                true
+            }
+        }
+    }
+
     pub(crate) fn generate_moves_colortr<Us: ColorTr, Them: ColorTr>(
         &self,
         moves: &mut Vec<<Position<S> as board_game_traits::Position>::Move>,
@@ -12,11 +104,11 @@ impl<const S: usize> Position<S> {
         for square in squares_iterator::<S>() {
             match self[square].top_stone() {
                 None => {
-                    if Us::stones_left(&self) > 0 {
+                    if Us::stones_left(self) > 0 {
                         moves.push(Move::Place(Flat, square));
                         moves.push(Move::Place(Wall, square));
                     }
-                    if Us::caps_left(&self) > 0 {
+                    if Us::caps_left(self) > 0 {
                         moves.push(Move::Place(Cap, square));
                     }
                 }
@@ -28,7 +120,7 @@ impl<const S: usize> Position<S> {
                                 direction,
                                 square,
                                 square,
-                                self[square].len() as u8,
+                                self[square].len(),
                                 StackMovement::new(),
                                 &mut movements,
                             );
@@ -37,7 +129,7 @@ impl<const S: usize> Position<S> {
                                 direction,
                                 square,
                                 square,
-                                self[square].len() as u8,
+                                self[square].len(),
                                 StackMovement::new(),
                                 &mut movements,
                             );
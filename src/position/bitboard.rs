@@ -54,9 +54,7 @@ impl BitBoard {
     }
     #[inline]
     pub const fn full() -> Self {
-        BitBoard {
-            board: u64::max_value(),
-        }
+        BitBoard { board: u64::MAX }
     }
 
     pub fn all_lines<const S: usize>() -> Vec<Self> {
@@ -136,6 +134,30 @@ impl BitBoard {
         BitBoard::from_u64(self.board & (mask << i as u64))
     }
 
+    /// All squares orthogonally adjacent to at least one square in the bitboard
+    #[inline]
+    pub fn adjacent<const S: usize>(self) -> Self {
+        let not_first_file = !BitBoard::full().file::<S>(0);
+        let not_last_file = !BitBoard::full().file::<S>(S as u8 - 1);
+        let board = BitBoard::from_u64(self.board << S)
+            | BitBoard::from_u64(self.board >> S)
+            | (BitBoard::from_u64((self & not_last_file).board << 1))
+            | (BitBoard::from_u64((self & not_first_file).board >> 1));
+        board & BitBoard::lower_n_bits((S * S) as u8)
+    }
+
+    /// All squares in `mask` that are orthogonally connected to the bitboard, through other squares in `mask`
+    pub fn flood_fill<const S: usize>(self, mask: BitBoard) -> Self {
+        let mut filled = self & mask;
+        loop {
+            let next = (filled | filled.adjacent::<S>()) & mask;
+            if next == filled {
+                return filled;
+            }
+            filled = next;
+        }
+    }
+
     #[inline]
     pub fn is_empty(self) -> bool {
         self.board == 0
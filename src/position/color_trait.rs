@@ -19,6 +19,7 @@ pub(crate) trait ColorTr {
 
     fn road_stones<const S: usize>(group_data: &GroupData<S>) -> BitBoard;
 
+    #[allow(dead_code)]
     fn blocking_stones<const S: usize>(group_data: &GroupData<S>) -> BitBoard;
 
     fn flats<const S: usize>(group_data: &GroupData<S>) -> BitBoard;
@@ -11,6 +11,7 @@ use board_game_traits::GameResult::{BlackWin, Draw, WhiteWin};
 use board_game_traits::{Color, GameResult};
 use board_game_traits::{EvalPosition as EvalPositionTrait, Position as PositionTrait};
 use lazy_static::lazy_static;
+use pgn_traits::PgnPosition;
 use rand::{Rng, SeedableRng};
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
@@ -20,8 +21,8 @@ use color_trait::{BlackTr, WhiteTr};
 
 use utils::AbstractBoard;
 pub use utils::{
-    squares_iterator, Direction, Movement, Piece, Piece::*, Role, Role::*, Square, Stack,
-    StackMovement,
+    squares_iterator, AttackerDefender, Direction, MoveKinds, Movement, Piece, Piece::*, Role,
+    Role::*, Square, Stack, StackMovement, Symmetry,
 };
 
 pub use mv::{Move, ReverseMove};
@@ -342,7 +343,9 @@ impl<const S: usize> ZobristKeys<S> {
         for word in random_vec.iter_mut() {
             *word = rng.gen();
         }
-        let zobrist = unsafe { mem::transmute(Box::from_raw(random_vec.as_mut_ptr())) };
+        let zobrist = unsafe {
+            mem::transmute::<Box<u64>, Box<Self>>(Box::from_raw(random_vec.as_mut_ptr()))
+        };
 
         mem::forget(random_vec);
         zobrist
@@ -362,6 +365,8 @@ pub struct Position<const S: usize> {
     moves: Vec<Move>,
     hash: u64,              // Zobrist hash of current position
     hash_history: Vec<u64>, // Zobrist hashes of previous board states, up to the last irreversible move. Does not include the corrent position
+    flattens: u16,          // Number of walls flattened by capstones in the moves played
+    komi: i8,               // Bonus to black in flat counts, in half-flats
 }
 
 impl<const S: usize> PartialEq for Position<S> {
@@ -373,6 +378,7 @@ impl<const S: usize> PartialEq for Position<S> {
             && self.white_caps_left == other.white_caps_left
             && self.black_caps_left == other.black_caps_left
             && self.half_moves_played == other.half_moves_played
+            && self.komi == other.komi
     }
 }
 
@@ -380,13 +386,37 @@ impl<const S: usize> Eq for Position<S> {}
 
 impl<const S: usize> Hash for Position<S> {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        self.cells.hash(state);
-        self.to_move.hash(state);
-        self.white_stones_left.hash(state);
-        self.black_stones_left.hash(state);
-        self.white_caps_left.hash(state);
-        self.black_caps_left.hash(state);
-        self.half_moves_played.hash(state);
+        // Equal positions have the same pieces and side to move, and therefore the same Zobrist hash
+        self.hash.hash(state);
+    }
+}
+
+/// Positions are serialized as their TPS string and komi. The move history is not included
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+struct SerializedPosition {
+    tps: String,
+    komi: i8,
+}
+
+#[cfg(feature = "serde")]
+impl<const S: usize> Serialize for Position<S> {
+    fn serialize<Ser: serde::Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+        SerializedPosition {
+            tps: self.to_fen(),
+            komi: self.komi,
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, const S: usize> Deserialize<'de> for Position<S> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let SerializedPosition { tps, komi } = SerializedPosition::deserialize(deserializer)?;
+        Self::from_fen(&tps)
+            .map(|position| position.with_komi(komi))
+            .map_err(serde::de::Error::custom)
     }
 }
 
@@ -417,6 +447,8 @@ impl<const S: usize> Default for Position<S> {
             moves: vec![],
             hash: zobrist_to_move::<S>(Color::White),
             hash_history: vec![],
+            flattens: 0,
+            komi: 0,
         }
     }
 }
@@ -464,7 +496,59 @@ impl<const S: usize> fmt::Debug for Position<S> {
     }
 }
 
+/// The error returned when parsing a TPS string. This is the same error type as the crate's other notation parsers use.
+pub type TpsError = pgn_traits::Error;
+
 impl<const S: usize> Position<S> {
+    /// The start position, with `komi` half-flats added to black's flat count if the game ends by flat count.
+    /// Negative komi is a bonus to white
+    pub fn start_position_with_komi(komi: i8) -> Self {
+        Position {
+            komi,
+            ..Self::default()
+        }
+    }
+
+    /// Set the komi of the position, for example for a position set up from TPS, which does not include komi
+    pub fn with_komi(mut self, komi: i8) -> Self {
+        self.komi = komi;
+        self
+    }
+
+    /// The komi of the game, in half-flats. See `start_position_with_komi`
+    pub fn komi(&self) -> i8 {
+        self.komi
+    }
+
+    /// The komi in flats, such as `2` or `2.5`, as written in the `Komi` tag of PTN files
+    pub fn komi_string(&self) -> String {
+        (self.komi as f32 / 2.0).to_string()
+    }
+
+    /// Parse komi written in flats, such as `2` or `2.5`, as in the `Komi` tag of PTN files.
+    /// Returns the komi in half-flats, or `None` if it is not a whole number of half-flats
+    pub fn parse_komi(input: &str) -> Option<i8> {
+        let half_flats = input.trim().parse::<f32>().ok()? * 2.0;
+        if half_flats.fract() != 0.0 || half_flats < i8::MIN as f32 || half_flats > i8::MAX as f32 {
+            return None;
+        }
+        Some(half_flats as i8)
+    }
+
+    /// Set up a position from a TPS (Tak Positional System) string, such as `x5/x5/x5/x5/x5 1 1`.
+    /// Rows must have exactly `S` columns, walls and capstones must be on top of their stacks, and the pieces must fit in the reserves.
+    /// The same as `from_fen`. TPS does not include komi, see `with_komi`
+    pub fn from_tps(tps: &str) -> Result<Self, TpsError> {
+        Self::from_fen(tps)
+    }
+
+    /// The canonical TPS string of the position, with runs of empty squares written as `xN`.
+    /// Parsing it with `from_tps` gives back the same position, except for its komi and move history.
+    /// The same as `to_fen`
+    pub fn to_tps(&self) -> String {
+        self.to_fen()
+    }
+
     pub fn white_reserves_left(&self) -> u8 {
         self.white_stones_left
     }
@@ -481,7 +565,70 @@ impl<const S: usize> Position<S> {
         self.black_caps_left
     }
 
-    #[cfg(test)]
+    /// Difference in material between white and black, counting the pieces on top of each stack.
+    /// Flatstones count fully, while walls and capstones, which do not score in a flat count, count less.
+    /// Positive values are good for white.
+    pub fn material_balance(&self) -> f32 {
+        const FLAT_VALUE: f32 = 1.0;
+        const WALL_VALUE: f32 = 0.5;
+        const CAP_VALUE: f32 = 0.75;
+
+        squares_iterator::<S>()
+            .filter_map(|square| self[square].top_stone())
+            .map(|piece| {
+                let value = match piece.role() {
+                    Flat => FLAT_VALUE,
+                    Wall => WALL_VALUE,
+                    Cap => CAP_VALUE,
+                };
+                match piece.color() {
+                    Color::White => value,
+                    Color::Black => -value,
+                }
+            })
+            .sum()
+    }
+
+    /// Whether a player has placed all their stones and capstones, which ends the game on flat count.
+    pub fn reserves_exhausted(&self, color: Color) -> bool {
+        match color {
+            Color::White => self.white_stones_left == 0 && self.white_caps_left == 0,
+            Color::Black => self.black_stones_left == 0 && self.black_caps_left == 0,
+        }
+    }
+
+    /// Whether every square on the board is occupied, which ends the game on flat count.
+    pub fn is_board_full(&self) -> bool {
+        squares_iterator::<S>().all(|square| !self[square].is_empty())
+    }
+
+    /// The kinds of moves available to the side to move, without generating the moves.
+    /// Placements need an empty square and stones in reserve, spreads need a stack controlled by the side to move.
+    /// Only placements are available in the first two plies
+    pub fn available_move_kinds(&self) -> MoveKinds {
+        let mut move_kinds = MoveKinds::NONE;
+        let side_to_move = self.side_to_move();
+
+        if (!self.reserves_exhausted(side_to_move) || self.half_moves_played() < 2)
+            && squares_iterator::<S>().any(|square| self[square].is_empty())
+        {
+            move_kinds |= MoveKinds::PLACEMENT;
+        }
+        if self.half_moves_played() >= 2
+            && squares_iterator::<S>().any(|square| {
+                self[square]
+                    .top_stone()
+                    .is_some_and(|piece| piece.color() == side_to_move)
+            })
+        {
+            move_kinds |= MoveKinds::SPREAD;
+        }
+        move_kinds
+    }
+
+    /// Zobrist hash of the position, which is updated incrementally as moves are played.
+    /// Positions with the same pieces and side to move have the same hash.
+    /// Editing the stacks directly with `IndexMut` does not update it
     pub fn zobrist_hash(&self) -> u64 {
         self.hash
     }
@@ -491,13 +638,57 @@ impl<const S: usize> Position<S> {
         self.half_moves_played
     }
 
+    /// Number of walls flattened by capstones in the moves played
+    pub fn flattens(&self) -> u16 {
+        self.flattens
+    }
+
     /// All the moves played in the game
     pub fn moves(&self) -> &Vec<Move> {
         &self.moves
     }
 
+    /// Parse a move in PTN notation, and play it if it is legal.
+    /// Returns the information needed to reverse the move.
+    pub fn do_san_move(&mut self, san: &str) -> Result<ReverseMove, pgn_traits::Error> {
+        let mv = Move::from_string::<S>(san)?;
+        if !self.is_legal(&mv) {
+            return Err(pgn_traits::Error::new(
+                pgn_traits::ErrorKind::IllegalMove,
+                format!("{} is not legal in the position", san),
+            ));
+        }
+        Ok(self.do_move(mv))
+    }
+
+    /// Parse a list of moves played from this position, each in either PlayTak notation (`P A1`, `M A1 A2 1`) or PTN (`a1`, `a1+`).
+    /// The notation is detected separately for each move, and every move is checked for legality.
+    pub fn parse_move_list(&self, tokens: &[&str]) -> Result<Vec<Move>, pgn_traits::Error> {
+        let mut position = self.clone();
+        let mut moves = Vec::with_capacity(tokens.len());
+        for token in tokens {
+            let token = token.trim();
+            let mv = if token.starts_with("P ") || token.starts_with("M ") {
+                Move::try_from_string_playtak::<S>(token)?
+            } else {
+                Move::from_string::<S>(token)?
+            };
+            if !position.is_legal(&mv) {
+                return Err(pgn_traits::Error::new(
+                    pgn_traits::ErrorKind::IllegalMove,
+                    format!("{} is not legal in the position", token),
+                ));
+            }
+            position.do_move(mv.clone());
+            moves.push(mv);
+        }
+        Ok(moves)
+    }
+
     pub fn null_move(&mut self) {
+        self.hash ^= zobrist_to_move::<S>(self.to_move);
         self.to_move = !self.to_move;
+        self.hash ^= zobrist_to_move::<S>(self.to_move);
     }
 
     pub(crate) fn zobrist_hash_from_scratch(&self) -> u64 {
@@ -519,7 +710,7 @@ impl<const S: usize> Position<S> {
             for i in 0..(stack.len() as usize + 6) / 8 {
                 hash ^= zobrist_stones_in_stack::<S>(
                     square,
-                    i as usize,
+                    i,
                     stack.bitboard.board as usize >> (i * 8) & 255,
                 )
             }
@@ -553,6 +744,13 @@ impl<const S: usize> Position<S> {
                 new_board[Square(y * S as u8 + x)] = self[Square((S as u8 - y - 1) * S as u8 + x)];
             }
         }
+        new_board.hash = new_board.zobrist_hash_from_scratch();
+        new_board.moves = self
+            .moves
+            .iter()
+            .map(|mv| self.transform_move(mv, Symmetry::FlipY))
+            .collect();
+        new_board.hash_history = self.transformed_hash_history(Self::flip_board_y);
         new_board
     }
 
@@ -563,6 +761,13 @@ impl<const S: usize> Position<S> {
                 new_board[Square(y * S as u8 + x)] = self[Square(y * S as u8 + (S as u8 - x - 1))];
             }
         }
+        new_board.hash = new_board.zobrist_hash_from_scratch();
+        new_board.moves = self
+            .moves
+            .iter()
+            .map(|mv| self.transform_move(mv, Symmetry::FlipX))
+            .collect();
+        new_board.hash_history = self.transformed_hash_history(Self::flip_board_x);
         new_board
     }
 
@@ -575,6 +780,13 @@ impl<const S: usize> Position<S> {
                 new_board[Square(y * S as u8 + x)] = self[Square(new_y * S as u8 + new_x)];
             }
         }
+        new_board.hash = new_board.zobrist_hash_from_scratch();
+        new_board.moves = self
+            .moves
+            .iter()
+            .map(|mv| self.transform_move(mv, Symmetry::Rotate90))
+            .collect();
+        new_board.hash_history = self.transformed_hash_history(Self::rotate_board);
         new_board
     }
 
@@ -595,21 +807,151 @@ impl<const S: usize> Position<S> {
             &mut new_board.black_caps_left,
         );
         new_board.to_move = !new_board.to_move;
+        new_board.komi = new_board.komi.saturating_neg();
+        new_board.hash = new_board.zobrist_hash_from_scratch();
+        new_board.hash_history = self.transformed_hash_history(Self::flip_colors);
         new_board
     }
 
+    /// The hashes of the previous positions, each transformed like the current position, so that repetitions are still detected.
+    /// Empty if the previous positions cannot be reconstructed from the moves
+    fn transformed_hash_history(&self, transform: fn(&Self) -> Self) -> Vec<u64> {
+        self.previous_positions().map_or(vec![], |positions| {
+            positions
+                .iter()
+                .map(|position| transform(position).hash)
+                .collect()
+        })
+    }
+
+    /// The positions in the hash history, oldest first, without their own history.
+    /// They are reconstructed by undoing the last moves, which are all stack movements since the history starts after the last placement.
+    /// Returns `None` if the moves do not lead to the stored hashes
+    fn previous_positions(&self) -> Option<Vec<Position<S>>> {
+        let mut position = self.clone();
+        let mut previous_positions = Vec::with_capacity(self.hash_history.len());
+        for &hash in self.hash_history.iter().rev() {
+            let Move::Move(square, direction, stack_movement) = position.moves.last()?.clone()
+            else {
+                return None;
+            };
+            let mut end_square = square;
+            let mut reversed_movement = StackMovement::new();
+            for i in (0..stack_movement.len()).rev() {
+                end_square = end_square.go_direction::<S>(direction)?;
+                reversed_movement.push(stack_movement.get(i as u8));
+            }
+            let mut previous_position = position.clone();
+            previous_position.reverse_move(ReverseMove::Move(
+                end_square,
+                direction.reverse(),
+                reversed_movement,
+                false,
+            ));
+            // The move may have flattened a wall, which cannot be seen from the move itself
+            if previous_position.hash != hash
+                && previous_position[end_square].top_stone().map(Piece::role) == Some(Flat)
+            {
+                previous_position = position.clone();
+                previous_position.reverse_move(ReverseMove::Move(
+                    end_square,
+                    direction.reverse(),
+                    reversed_movement,
+                    true,
+                ));
+            }
+            if previous_position.hash != hash {
+                return None;
+            }
+            position = previous_position;
+            let mut previous_position = position.clone();
+            previous_position.hash_history.clear();
+            previous_positions.push(previous_position);
+        }
+        previous_positions.reverse();
+        Some(previous_positions)
+    }
+
+    /// Returns the board transformed by the given symmetry
+    pub fn transform(&self, symmetry: Symmetry) -> Position<S> {
+        match symmetry {
+            Symmetry::Identity => self.clone(),
+            Symmetry::FlipX => self.flip_board_x(),
+            Symmetry::FlipY => self.flip_board_y(),
+            Symmetry::Rotate90 => self.rotate_board(),
+            Symmetry::Rotate180 => self.rotate_board().rotate_board(),
+            Symmetry::Rotate270 => self.rotate_board().rotate_board().rotate_board(),
+            Symmetry::RotateFlipX => self.rotate_board().flip_board_x(),
+            Symmetry::RotateFlipY => self.rotate_board().flip_board_y(),
+        }
+    }
+
+    /// Transform the board and its moves by the given symmetry in place. Gives the same board as `transform`, without cloning the position.
+    /// Unlike `transform`, the hashes of previous positions are kept, so that `apply_inverse_symmetry` fully restores the position.
+    /// Repetitions are not detected while the symmetry is applied.
+    pub fn apply_symmetry(&mut self, symmetry: Symmetry) {
+        // The cells are stored inline, so this copy does not allocate
+        let old_cells = self.cells.clone();
+        for square in utils::squares_iterator::<S>() {
+            self.cells[symmetry.transform_square::<S>(square)] = old_cells[square];
+        }
+        for i in 0..self.moves.len() {
+            self.moves[i] = self.transform_move(&self.moves[i], symmetry);
+        }
+        self.hash = self.zobrist_hash_from_scratch();
+    }
+
+    /// Undo `apply_symmetry` with the same symmetry, in place
+    pub fn apply_inverse_symmetry(&mut self, symmetry: Symmetry) {
+        self.apply_symmetry(symmetry.inverse())
+    }
+
+    /// Returns the move that corresponds to `mv` on the board transformed by `symmetry`.
+    /// Use `symmetry.inverse()` to map a move from the transformed board back to this one.
+    pub fn transform_move(&self, mv: &Move, symmetry: Symmetry) -> Move {
+        match mv {
+            Move::Place(role, square) => {
+                Move::Place(*role, symmetry.transform_square::<S>(*square))
+            }
+            Move::Move(square, direction, stack_movement) => Move::Move(
+                symmetry.transform_square::<S>(*square),
+                symmetry.transform_direction(*direction),
+                *stack_movement,
+            ),
+        }
+    }
+
     /// Returns all 8 symmetries of the board
     pub fn symmetries(&self) -> Vec<Position<S>> {
-        vec![
-            self.clone(),
-            self.flip_board_x(),
-            self.flip_board_y(),
-            self.rotate_board(),
-            self.rotate_board().rotate_board(),
-            self.rotate_board().rotate_board().rotate_board(),
-            self.rotate_board().flip_board_x(),
-            self.rotate_board().flip_board_y(),
-        ]
+        Symmetry::ALL
+            .iter()
+            .map(|symmetry| self.transform(*symmetry))
+            .collect()
+    }
+
+    /// The symmetry of the position with the lexicographically smallest TPS.
+    /// Positions that are rotations or mirror images of each other have the same canonical position
+    pub fn canonical(&self) -> Position<S> {
+        self.symmetries()
+            .into_iter()
+            .min_by_key(|symmetry| symmetry.to_fen())
+            .unwrap()
+    }
+
+    /// The static evaluation, averaged over all 8 symmetries of the board.
+    /// This cancels out any orientation-specific noise in the evaluation function.
+    pub fn evaluate_symmetrized(&self) -> f32 {
+        self.evaluate_symmetrized_with_params(Self::value_params())
+    }
+
+    /// Like `evaluate_symmetrized`, but with the given value parameters.
+    pub fn evaluate_symmetrized_with_params(&self, params: &[f32]) -> f32 {
+        let symmetries = self.symmetries();
+        symmetries
+            .iter()
+            .map(|position| position.static_eval_with_params(params))
+            .sum::<f32>()
+            / symmetries.len() as f32
     }
 
     /// Returns all 16 symmetries of the board, where swapping the colors is also a symmetry
@@ -620,6 +962,379 @@ impl<const S: usize> Position<S> {
             .collect()
     }
 
+    /// Check a move for common beginner mistakes, and return a warning if it matches one.
+    /// This is a cheap heuristic intended for hints, and does no search. The patterns are:
+    /// * Placing the capstone in the opening
+    /// * Letting the opponent win by placing a flat on an empty square
+    /// * Stacking more pieces on a square than can be carried
+    pub fn is_questionable_move(&self, mv: &Move) -> Option<&'static str> {
+        if let Move::Place(Role::Cap, _) = mv {
+            if self.half_moves_played() < 2 * S {
+                return Some(
+                    "Placing the capstone this early limits its flexibility later in the game",
+                );
+            }
+        }
+
+        let opponent = !self.side_to_move();
+        let opponent_placement_wins = |position: &Position<S>| {
+            position
+                .group_data()
+                .critical_squares(opponent)
+                .filter(|square| position[*square].is_empty())
+                .count()
+        };
+        let mut position = self.clone();
+        position.do_move(mv.clone());
+        if position.game_result().is_none()
+            && opponent_placement_wins(&position) > opponent_placement_wins(self)
+        {
+            return Some("This move lets the opponent complete a road");
+        }
+
+        if let Move::Move(square, direction, stack_movement) = mv {
+            let mut destination = *square;
+            for _ in 0..stack_movement.len() {
+                destination = destination.go_direction::<S>(*direction).unwrap();
+            }
+            if position[destination].len() as usize > S {
+                return Some("This stack is taller than the carry limit, so some of its pieces can never move");
+            }
+        }
+        None
+    }
+
+    /// Every move that threatens to win on the next move, usually by completing a road, paired with the opponent's moves that stop the threat.
+    /// A threat with no defenses cannot be stopped. Moves that win immediately are not included.
+    pub fn tak_threats_with_defenses(&self) -> Vec<(Move, Vec<Move>)> {
+        let our_win = match self.side_to_move() {
+            Color::White => GameResult::WhiteWin,
+            Color::Black => GameResult::BlackWin,
+        };
+        let mut position = self.clone();
+        let mut moves = vec![];
+        position.generate_moves(&mut moves);
+
+        moves
+            .into_iter()
+            .filter_map(|mv| {
+                let reverse_move = position.do_move(mv.clone());
+                let mut passed = position.clone();
+                passed.null_move();
+                let threat = if position.game_result().is_none() && passed.has_winning_move() {
+                    let mut defenses = vec![];
+                    position.generate_moves(&mut defenses);
+                    defenses.retain(|defense| {
+                        let mut defended = position.clone();
+                        defended.do_move(defense.clone());
+                        match defended.game_result() {
+                            Some(game_result) => game_result != our_win,
+                            None => !defended.has_winning_move(),
+                        }
+                    });
+                    Some((mv, defenses))
+                } else {
+                    None
+                };
+                position.reverse_move(reverse_move);
+                threat
+            })
+            .collect()
+    }
+
+    /// Squares where the opponent can create a tak threat on their next move.
+    /// For stack movements, every square the stack drops pieces on is included.
+    pub fn threatened_squares(&self) -> Vec<Square> {
+        let mut opponent_position = self.clone();
+        opponent_position.null_move();
+
+        let mut squares = vec![];
+        for (mv, _) in opponent_position.tak_threats_with_defenses() {
+            match mv {
+                Move::Place(_, square) => squares.push(square),
+                Move::Move(mut square, direction, stack_movement) => {
+                    for _ in 0..stack_movement.len() {
+                        square = square.go_direction::<S>(direction).unwrap();
+                        squares.push(square);
+                    }
+                }
+            }
+        }
+        squares.sort_by_key(|square| square.0);
+        squares.dedup();
+        squares
+    }
+
+    /// A heuristic estimate of how instructive the position is, for prioritizing training positions.
+    /// The sum of three terms, each between 0 and 1:
+    /// * The branching factor, which is 0.5 when there are `S * S` legal moves
+    /// * The number of tak threats available to either side
+    /// * The eval uncertainty, measured as the spread of the static evaluations after every legal move
+    ///
+    /// Returns 0 if the game is over.
+    pub fn complexity(&self) -> f32 {
+        if self.game_result().is_some() {
+            return 0.0;
+        }
+        let mut position = self.clone();
+        let mut moves = vec![];
+        position.generate_moves(&mut moves);
+        let branching = moves.len() as f32 / (moves.len() + S * S) as f32;
+
+        let mut opponent_position = self.clone();
+        opponent_position.null_move();
+        let num_threats = self.tak_threats_with_defenses().len()
+            + opponent_position.tak_threats_with_defenses().len();
+        let threats = num_threats as f32 / (num_threats + 1) as f32;
+
+        let child_scores: Vec<f32> = moves
+            .into_iter()
+            .map(|mv| {
+                let reverse_move = position.do_move(mv);
+                let score = match position.game_result() {
+                    Some(WhiteWin) => 1.0,
+                    Some(BlackWin) => 0.0,
+                    Some(Draw) => 0.5,
+                    None => search::cp_to_win_percentage(position.static_eval()),
+                };
+                position.reverse_move(reverse_move);
+                score
+            })
+            .collect();
+        let mean = child_scores.iter().sum::<f32>() / child_scores.len() as f32;
+        let variance = child_scores
+            .iter()
+            .map(|score| (score - mean).powi(2))
+            .sum::<f32>()
+            / child_scores.len() as f32;
+        // The standard deviation of values between 0 and 1 is at most 0.5
+        let uncertainty = 2.0 * variance.sqrt();
+
+        branching + threats + uncertainty
+    }
+
+    /// The value of the position as a search leaf, as a winning probability for the side to move.
+    /// Terminal positions get their exact result, with draws counting as 0.5. Other positions are scored by the static evaluation,
+    /// like a leaf node in the search on default settings
+    pub fn evaluate_leaf(&self) -> search::Score {
+        search::evaluate_leaf(self, &self.group_data(), &search::MctsSetting::default()).0
+    }
+
+    /// Returns the reduced set of moves that matter in a forced-win search, for the side to move in the given role.
+    /// The attacker considers winning moves, moves that create a tak threat, and road piece placements next to its own road pieces.
+    /// If the attacker can win immediately, only the winning moves are returned.
+    /// The defender considers its own winning moves, and moves that stop the attacker's immediate win.
+    /// If the attacker has no immediate win, the defender considers placements next to the attacker's road pieces instead.
+    pub fn road_relevant_moves(&self, role: AttackerDefender) -> Vec<Move> {
+        let us = self.side_to_move();
+        let (our_win, their_win) = match us {
+            Color::White => (WhiteWin, BlackWin),
+            Color::Black => (BlackWin, WhiteWin),
+        };
+        let next_to_road_piece = |square: Square, color: Color| {
+            square.neighbours::<S>().any(|neighbour| {
+                self[neighbour]
+                    .top_stone()
+                    .is_some_and(|piece| piece.color() == color && piece.is_road_piece())
+            })
+        };
+
+        let mut position = self.clone();
+        let mut moves = vec![];
+        position.generate_moves(&mut moves);
+
+        match role {
+            AttackerDefender::Attacker => {
+                // If we can already win, nothing else matters
+                let can_win = self.has_winning_move();
+                moves.retain(|mv| {
+                    let reverse_move = position.do_move(mv.clone());
+                    let is_relevant = match position.game_result() {
+                        Some(game_result) => game_result == our_win,
+                        None if can_win => false,
+                        None => {
+                            let extends_road = match mv {
+                                Move::Place(Flat | Cap, square) => next_to_road_piece(*square, us),
+                                _ => false,
+                            };
+                            let mut passed = position.clone();
+                            passed.null_move();
+                            extends_road || passed.has_winning_move()
+                        }
+                    };
+                    position.reverse_move(reverse_move);
+                    is_relevant
+                })
+            }
+            AttackerDefender::Defender => {
+                let mut passed = self.clone();
+                passed.null_move();
+                let is_threatened = passed.has_winning_move();
+                moves.retain(|mv| {
+                    let reverse_move = position.do_move(mv.clone());
+                    let is_relevant = match position.game_result() {
+                        Some(game_result) => game_result != their_win,
+                        None if is_threatened => !position.has_winning_move(),
+                        None => match mv {
+                            Move::Place(_, square) => next_to_road_piece(*square, !us),
+                            Move::Move(..) => false,
+                        },
+                    };
+                    position.reverse_move(reverse_move);
+                    is_relevant
+                })
+            }
+        }
+        moves
+    }
+
+    /// Whether the side to move has a move that wins the game immediately
+    fn has_winning_move(&self) -> bool {
+        let our_win = match self.side_to_move() {
+            Color::White => GameResult::WhiteWin,
+            Color::Black => GameResult::BlackWin,
+        };
+        let mut position = self.clone();
+        let mut moves = vec![];
+        position.generate_moves(&mut moves);
+        moves.into_iter().any(|mv| {
+            let reverse_move = position.do_move(mv);
+            let wins = position.game_result() == Some(our_win);
+            position.reverse_move(reverse_move);
+            wins
+        })
+    }
+
+    /// Set the reserves from the pieces on the board.
+    /// Returns an error if either side has more stones or capstones on the board than they start with.
+    fn place_reserve_check(&mut self) -> Result<(), pgn_traits::Error> {
+        let (mut white_stones, mut white_caps, mut black_stones, mut black_caps) = (0, 0, 0, 0);
+        for square in squares_iterator::<S>() {
+            for piece in self[square] {
+                match piece {
+                    WhiteFlat | WhiteWall => white_stones += 1,
+                    WhiteCap => white_caps += 1,
+                    BlackFlat | BlackWall => black_stones += 1,
+                    BlackCap => black_caps += 1,
+                }
+            }
+        }
+        let reserves_left = |color: &str, kind: &str, on_board: u8, starting: u8| {
+            starting.checked_sub(on_board).ok_or_else(|| {
+                pgn_traits::Error::new(
+                    pgn_traits::ErrorKind::IllegalPosition,
+                    format!(
+                        "{} has {} {} on the board, but starts with {} in {}s",
+                        color, on_board, kind, starting, S
+                    ),
+                )
+            })
+        };
+        self.white_stones_left =
+            reserves_left("White", "stones", white_stones, starting_stones::<S>())?;
+        self.white_caps_left =
+            reserves_left("White", "capstones", white_caps, starting_capstones::<S>())?;
+        self.black_stones_left =
+            reserves_left("Black", "stones", black_stones, starting_stones::<S>())?;
+        self.black_caps_left =
+            reserves_left("Black", "capstones", black_caps, starting_capstones::<S>())?;
+        Ok(())
+    }
+
+    /// Check that the position is internally consistent.
+    /// Returns a description of every violated invariant.
+    pub fn is_legal_position(&self) -> Result<(), Vec<String>> {
+        let mut errors = vec![];
+
+        for square in squares_iterator::<S>() {
+            let stack = self[square];
+            if stack.top_stone.is_some() != (stack.height > 0) {
+                errors.push(format!(
+                    "Stack on {} has height {}, but top stone {:?}",
+                    square.to_string::<S>(),
+                    stack.height,
+                    stack.top_stone
+                ));
+            }
+            if !(stack.bitboard & !BitBoard::lower_n_bits(stack.height.saturating_sub(1)))
+                .is_empty()
+            {
+                errors.push(format!(
+                    "Stack on {} has pieces above its height of {}",
+                    square.to_string::<S>(),
+                    stack.height
+                ));
+            }
+        }
+
+        let (mut white_stones, mut white_caps, mut black_stones, mut black_caps) = (0, 0, 0, 0);
+        for square in squares_iterator::<S>() {
+            for piece in self[square] {
+                match piece {
+                    WhiteFlat | WhiteWall => white_stones += 1,
+                    WhiteCap => white_caps += 1,
+                    BlackFlat | BlackWall => black_stones += 1,
+                    BlackCap => black_caps += 1,
+                }
+            }
+        }
+        let mut check_reserves = |color: &str, kind: &str, on_board: u8, left: u8, starting: u8| {
+            if on_board + left != starting {
+                errors.push(format!(
+                    "{} has {} {} on the board and {} in reserve, but starts with {} in {}s",
+                    color, on_board, kind, left, starting, S
+                ));
+            }
+        };
+        check_reserves(
+            "White",
+            "stones",
+            white_stones,
+            self.white_stones_left,
+            starting_stones::<S>(),
+        );
+        check_reserves(
+            "White",
+            "capstones",
+            white_caps,
+            self.white_caps_left,
+            starting_capstones::<S>(),
+        );
+        check_reserves(
+            "Black",
+            "stones",
+            black_stones,
+            self.black_stones_left,
+            starting_stones::<S>(),
+        );
+        check_reserves(
+            "Black",
+            "capstones",
+            black_caps,
+            self.black_caps_left,
+            starting_capstones::<S>(),
+        );
+
+        let expected_side_to_move = if self.half_moves_played.is_multiple_of(2) {
+            Color::White
+        } else {
+            Color::Black
+        };
+        if self.side_to_move() != expected_side_to_move {
+            errors.push(format!(
+                "{} to move after {} plies",
+                self.side_to_move(),
+                self.half_moves_played
+            ));
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
     fn count_all_pieces(&self) -> u8 {
         squares_iterator::<S>()
             .map(|square| self[square].len())
@@ -713,13 +1428,7 @@ impl<const S: usize> Position<S> {
         &self,
         group_data: &GroupData<S>,
     ) -> Option<GameResult> {
-        let repetitions = self
-            .hash_history
-            .iter()
-            .filter(|hash| **hash == self.hash)
-            .count();
-
-        if repetitions >= 2 {
+        if self.is_threefold_repetition() {
             return Some(GameResult::Draw);
         }
 
@@ -733,7 +1442,7 @@ impl<const S: usize> Position<S> {
                 .iter()
                 .enumerate()
                 .skip(1)
-                .find(|(_i, v)| (**v).0 == 0)
+                .find(|(_i, v)| v.0 == 0)
                 .map(|(i, _v)| i)
                 .unwrap_or(S * S + 1) as u8;
 
@@ -751,17 +1460,62 @@ impl<const S: usize> Position<S> {
             )
         }
 
-        if (self.white_stones_left == 0 && self.white_caps_left == 0)
-            || (self.black_stones_left == 0 && self.black_caps_left == 0)
-            || utils::squares_iterator::<S>().all(|square| !self[square].is_empty())
+        self.game_result_by_flat_count()
+    }
+
+    /// Same as `game_result`, but checks for roads with bitboard flood fills, instead of computing the full group data
+    fn game_result_from_bitboards(&self) -> Option<GameResult> {
+        if self.is_threefold_repetition() {
+            return Some(GameResult::Draw);
+        }
+
+        let mut white_road_pieces = BitBoard::empty();
+        let mut black_road_pieces = BitBoard::empty();
+        for square in utils::squares_iterator::<S>() {
+            match self[square].top_stone() {
+                Some(WhiteFlat | WhiteCap) => white_road_pieces = white_road_pieces.set(square.0),
+                Some(BlackFlat | BlackCap) => black_road_pieces = black_road_pieces.set(square.0),
+                _ => (),
+            }
+        }
+
+        // If both sides have a road, the side that just moved wins
+        match (
+            has_road::<S>(white_road_pieces),
+            has_road::<S>(black_road_pieces),
+        ) {
+            (true, true) => match self.side_to_move() {
+                Color::White => Some(BlackWin),
+                Color::Black => Some(WhiteWin),
+            },
+            (true, false) => Some(WhiteWin),
+            (false, true) => Some(BlackWin),
+            (false, false) => self.game_result_by_flat_count(),
+        }
+    }
+
+    fn is_threefold_repetition(&self) -> bool {
+        let repetitions = self
+            .hash_history
+            .iter()
+            .filter(|hash| **hash == self.hash)
+            .count();
+        repetitions >= 2
+    }
+
+    /// The result of the game if it has ended by filling the board, or by a player running out of pieces
+    fn game_result_by_flat_count(&self) -> Option<GameResult> {
+        if self.reserves_exhausted(Color::White)
+            || self.reserves_exhausted(Color::Black)
+            || self.is_board_full()
         {
-            // Count points
+            // Count points, in half-flats
             let mut white_points = 0;
-            let mut black_points = 0;
+            let mut black_points = self.komi as i32;
             for square in utils::squares_iterator::<S>() {
                 match self[square].top_stone() {
-                    Some(WhiteFlat) => white_points += 1,
-                    Some(BlackFlat) => black_points += 1,
+                    Some(WhiteFlat) => white_points += 2,
+                    Some(BlackFlat) => black_points += 2,
                     _ => (),
                 }
             }
@@ -788,8 +1542,7 @@ impl<const S: usize> Position<S> {
 
         // TODO: Include highest id?
         for id in 1..highest_component_id {
-            if (components.raw[0].iter().any(|&cell| cell == id)
-                && components.raw[S - 1].iter().any(|&cell| cell == id))
+            if (components.raw[0].contains(&id) && components.raw[S - 1].contains(&id))
                 || ((0..S).any(|y| components.raw[y][0] == id)
                     && (0..S).any(|y| components.raw[y][S - 1] == id))
             {
@@ -812,7 +1565,7 @@ impl<const S: usize> Position<S> {
         params: &[f32],
     ) -> f32 {
         let mut coefficients = vec![0.0; Self::value_params().len()];
-        value_eval::static_eval_game_phase(&self, group_data, &mut coefficients);
+        value_eval::static_eval_game_phase(self, group_data, &mut coefficients);
         coefficients.iter().zip(params).map(|(a, b)| a * b).sum()
     }
 }
@@ -831,12 +1584,15 @@ impl<const S: usize> PositionTrait for Position<S> {
 
     /// Adds all legal moves to the provided vector. Some notes on the interpretation of the rules:
     /// * Suicide moves are considered legal, and are generated like any other move.
-    /// This includes moves that complete a road for the opponent without creating an own road,
-    /// and moves that fill the board when that would result in an immediate loss.
+    ///   This includes moves that complete a road for the opponent without creating an own road,
+    ///   and moves that fill the board when that would result in an immediate loss.
     ///
     /// * Capstones are not counted towards a flat win, but all capstones must also be placed to trigger a flat win.
     ///
     /// * A game is considered a draw after a three-fold repetition of the same position.
+    ///
+    /// * The opening swap, where the first two plies place the opponent's color, depends only on the number of plies played.
+    ///   For positions loaded from TPS, this is derived from the move number, so mid-game positions generate regular placements.
     fn generate_moves(&self, moves: &mut Vec<Self::Move>) {
         match self.half_moves_played() {
             0 | 1 => {
@@ -928,6 +1684,10 @@ impl<const S: usize> PositionTrait for Position<S> {
                     pieces_left_behind.push(movement);
                 }
 
+                if flattens_stone {
+                    self.flattens += 1;
+                }
+
                 ReverseMove::Move(
                     from,
                     direction.reverse(),
@@ -996,6 +1756,7 @@ impl<const S: usize> PositionTrait for Position<S> {
                 }
 
                 if flattens_wall {
+                    self.flattens -= 1;
                     match self[from].top_stone().unwrap().color() {
                         Color::White => self[from].replace_top(WhiteWall),
                         Color::Black => self[from].replace_top(BlackWall),
@@ -1018,7 +1779,7 @@ impl<const S: usize> PositionTrait for Position<S> {
     }
 
     fn game_result(&self) -> Option<GameResult> {
-        self.game_result_with_group_data(&self.group_data())
+        self.game_result_from_bitboards()
     }
 }
 
@@ -1060,7 +1821,7 @@ impl<const S: usize> Iterator for MoveIterator<S> {
 
 impl<const S: usize> EvalPositionTrait for Position<S> {
     fn static_eval(&self) -> f32 {
-        self.static_eval_with_params(&Self::value_params())
+        self.static_eval_with_params(Self::value_params())
     }
 }
 
@@ -1089,7 +1850,7 @@ impl<const S: usize> TunableBoard for Position<S> {
         debug_assert!(self.game_result().is_none());
 
         let group_data = self.group_data();
-        value_eval::static_eval_game_phase(&self, &group_data, coefficients)
+        value_eval::static_eval_game_phase(self, &group_data, coefficients)
     }
 
     fn generate_moves_with_params(
@@ -1129,14 +1890,14 @@ impl<const S: usize> TunableBoard for Position<S> {
     ) {
         match self.side_to_move() {
             Color::White => policy_eval::coefficients_for_move_colortr::<WhiteTr, BlackTr, S>(
-                &self,
+                self,
                 coefficients,
                 mv,
                 group_data,
                 num_legal_moves,
             ),
             Color::Black => policy_eval::coefficients_for_move_colortr::<BlackTr, WhiteTr, S>(
-                &self,
+                self,
                 coefficients,
                 mv,
                 group_data,
@@ -1229,17 +1990,15 @@ impl<const S: usize> pgn_traits::PgnPosition for Position<S> {
         let mut position = Position::default();
         for square in utils::squares_iterator::<S>() {
             let (file, rank) = (square.file::<S>(), square.rank::<S>());
-            let stack = rows[rank as usize][file as usize];
-            for piece in stack.into_iter() {
-                match piece {
-                    WhiteFlat | WhiteWall => position.white_stones_left -= 1,
-                    WhiteCap => position.white_caps_left -= 1,
-                    BlackFlat | BlackWall => position.black_stones_left -= 1,
-                    BlackCap => position.black_caps_left -= 1,
-                }
-            }
-            position[square] = stack;
+            position[square] = rows[rank as usize][file as usize];
         }
+        position.place_reserve_check().map_err(|e| {
+            pgn_traits::Error::new_caused_by(
+                pgn_traits::ErrorKind::IllegalPosition,
+                format!("Illegal TPS string \"{}\"", fen),
+                e,
+            )
+        })?;
 
         match fen_words[1] {
             "1" => position.to_move = Color::White,
@@ -1253,6 +2012,12 @@ impl<const S: usize> pgn_traits::PgnPosition for Position<S> {
         }
 
         match fen_words[2].parse::<usize>() {
+            Ok(0) => {
+                return Err(pgn_traits::Error::new_parse_error(format!(
+                    "Error parsing TPS \"{}\": Move number must be at least 1",
+                    fen
+                )))
+            }
             Ok(n) => match position.side_to_move() {
                 Color::White => position.half_moves_played = (n - 1) * 2,
                 Color::Black => position.half_moves_played = (n - 1) * 2 + 1,
@@ -1309,13 +2074,22 @@ impl<const S: usize> pgn_traits::PgnPosition for Position<S> {
                             match row_str_iter.next() {
                                 Some('1') => stack.push(Piece::from_role_color(Flat, Color::White)),
                                 Some('2') => stack.push(Piece::from_role_color(Flat, Color::Black)),
-                                Some('S') => {
-                                    let piece = stack.pop().unwrap();
-                                    stack.push(Piece::from_role_color(Wall, piece.color()));
-                                }
-                                Some('C') => {
-                                    let piece = stack.pop().unwrap();
-                                    stack.push(Piece::from_role_color(Cap, piece.color()));
+                                Some(role_ch @ ('S' | 'C')) => {
+                                    let role = if role_ch == 'S' { Wall } else { Cap };
+                                    let piece = stack.pop().ok_or_else(|| {
+                                        pgn_traits::Error::new_parse_error(format!(
+                                            "Expected '1' or '2' before '{}' on row \"{}\"",
+                                            role_ch, row_str
+                                        ))
+                                    })?;
+                                    stack.push(Piece::from_role_color(role, piece.color()));
+                                    if let Some(&ch) = row_str_iter.peek().filter(|&&ch| ch != ',')
+                                    {
+                                        return Err(pgn_traits::Error::new_parse_error(format!(
+                                            "Unexpected '{}' after '{}' on row \"{}\", walls and capstones must be on top of their stack",
+                                            ch, role_ch, row_str
+                                        )));
+                                    }
                                 }
                                 Some(',') | None => {
                                     column_id += 1;
@@ -1338,6 +2112,12 @@ impl<const S: usize> pgn_traits::PgnPosition for Position<S> {
                     }
                 }
             }
+            if column_id as usize != S || row_str_iter.next().is_some() {
+                return Err(pgn_traits::Error::new_parse_error(format!(
+                    "Couldn't parse row \"{}\": too many pieces, expected {} columns",
+                    row_str, S
+                )));
+            }
             Ok(row)
         }
     }
@@ -1345,26 +2125,39 @@ impl<const S: usize> pgn_traits::PgnPosition for Position<S> {
     fn to_fen(&self) -> String {
         let mut f = String::new();
         for rank in 0..S {
+            let mut squares = Vec::with_capacity(S);
+            let mut empty_squares = 0;
             for file in 0..S {
                 let square = Square::from_rank_file::<S>(rank as u8, file as u8);
                 if self[square].is_empty() {
-                    f.push('x')
-                } else {
-                    for piece in self[square].into_iter() {
-                        match piece {
-                            WhiteFlat => f.push('1'),
-                            BlackFlat => f.push('2'),
-                            WhiteWall => f.push_str("1S"),
-                            BlackWall => f.push_str("2S"),
-                            WhiteCap => f.push_str("1C"),
-                            BlackCap => f.push_str("2C"),
-                        }
-                    }
+                    empty_squares += 1;
+                    continue;
                 }
-                if file < S - 1 {
-                    f.push(',');
+                match empty_squares {
+                    0 => (),
+                    1 => squares.push("x".to_string()),
+                    n => squares.push(format!("x{}", n)),
+                }
+                empty_squares = 0;
+                let mut stack = String::new();
+                for piece in self[square].into_iter() {
+                    match piece {
+                        WhiteFlat => stack.push('1'),
+                        BlackFlat => stack.push('2'),
+                        WhiteWall => stack.push_str("1S"),
+                        BlackWall => stack.push_str("2S"),
+                        WhiteCap => stack.push_str("1C"),
+                        BlackCap => stack.push_str("2C"),
+                    }
                 }
+                squares.push(stack);
             }
+            match empty_squares {
+                0 => (),
+                1 => squares.push("x".to_string()),
+                n => squares.push(format!("x{}", n)),
+            }
+            f.push_str(&squares.join(","));
             if rank < S - 1 {
                 f.push('/');
             }
@@ -1394,6 +2187,21 @@ impl<const S: usize> pgn_traits::PgnPosition for Position<S> {
     }
 }
 
+/// Whether the road pieces connect two opposite edges of the board
+pub(crate) fn has_road<const S: usize>(road_pieces: BitBoard) -> bool {
+    let last = S as u8 - 1;
+    !road_pieces
+        .rank::<S>(0)
+        .flood_fill::<S>(road_pieces)
+        .rank::<S>(last)
+        .is_empty()
+        || !road_pieces
+            .file::<S>(0)
+            .flood_fill::<S>(road_pieces)
+            .file::<S>(last)
+            .is_empty()
+}
+
 pub(crate) fn connected_components_graph<const S: usize>(
     road_pieces: BitBoard,
     components: &mut AbstractBoard<u8, S>,
@@ -5,7 +5,6 @@ use std::str::FromStr;
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
-use crate::position::utils;
 use crate::position::utils::Direction::{East, North, South, West};
 use crate::position::utils::Role::{Cap, Flat, Wall};
 use crate::position::utils::{Direction, Movement, Role, Square, StackMovement};
@@ -20,6 +19,71 @@ pub enum Move {
 }
 
 impl Move {
+    /// Construct a placement, checking that the square is on the board
+    pub fn place<const S: usize>(role: Role, square: Square) -> Result<Self, pgn_traits::Error> {
+        if square.0 as usize >= S * S {
+            return Err(illegal_move_error(format!(
+                "Square {} is outside the {}s board",
+                square.0, S
+            )));
+        }
+        Ok(Move::Place(role, square))
+    }
+
+    /// Construct a spread from `origin` towards `direction`, dropping `drops[i]` pieces on the `i+1`th square.
+    /// The drop pattern must be non-empty, drop at least one piece on every square, stay within the carry limit, and stay on the board.
+    /// Whether the spread is legal in a particular position is not checked
+    pub fn spread<const S: usize>(
+        origin: Square,
+        direction: Direction,
+        drops: &[u8],
+    ) -> Result<Self, pgn_traits::Error> {
+        if origin.0 as usize >= S * S {
+            return Err(illegal_move_error(format!(
+                "Square {} is outside the {}s board",
+                origin.0, S
+            )));
+        }
+        if drops.is_empty() {
+            return Err(illegal_move_error(
+                "A spread must drop pieces on at least one square".to_string(),
+            ));
+        }
+        if drops.contains(&0) {
+            return Err(illegal_move_error(format!(
+                "Spread {:?} drops zero pieces on a square",
+                drops
+            )));
+        }
+        let pieces_taken: usize = drops.iter().map(|drop| *drop as usize).sum();
+        if pieces_taken > S {
+            return Err(illegal_move_error(format!(
+                "Spread {:?} carries {} pieces, but the carry limit is {}",
+                drops, pieces_taken, S
+            )));
+        }
+        let mut square = origin;
+        for _ in drops {
+            square = square.go_direction::<S>(direction).ok_or_else(|| {
+                illegal_move_error(format!(
+                    "Spread {:?} from {} goes off the board",
+                    drops,
+                    origin.to_string::<S>()
+                ))
+            })?;
+        }
+
+        let mut pieces_held = pieces_taken as u8;
+        let mut movements = StackMovement::new();
+        for drop in drops {
+            movements.push(Movement {
+                pieces_to_take: pieces_held,
+            });
+            pieces_held -= drop;
+        }
+        Ok(Move::Move(origin, direction, movements))
+    }
+
     pub fn to_string<const S: usize>(&self) -> String {
         let mut string = String::new();
         match self {
@@ -175,32 +239,75 @@ impl Move {
         }
     }
 
+    /// Parse a move in PlayTak's notation, like `P A1 C` or `M A1 C1 1 2`.
+    /// Panics if the move string is invalid. Use `try_from_string_playtak` for untrusted input.
     pub fn from_string_playtak<const S: usize>(input: &str) -> Self {
+        Self::try_from_string_playtak::<S>(input).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Parse a move in PlayTak's notation, like `P A1 C` or `M A1 C1 1 2`.
+    /// The move is not checked for legality in any position.
+    pub fn try_from_string_playtak<const S: usize>(input: &str) -> Result<Self, pgn_traits::Error> {
+        let parse_error = |message: &str| {
+            pgn_traits::Error::new_parse_error(format!("{} in move {}", message, input))
+        };
+        let parse_square = |word: Option<&&str>| {
+            word.and_then(|word| Square::parse_square::<S>(&word.to_lowercase()).ok())
+                .ok_or_else(|| parse_error("Invalid square"))
+        };
         let words: Vec<&str> = input.split_whitespace().collect();
-        if words[0] == "P" {
-            let square = Square::parse_square::<S>(&words[1].to_lowercase()).unwrap();
-            let role = match words.get(2) {
-                Some(&"C") => Role::Cap,
-                Some(&"W") => Role::Wall,
-                None => Role::Flat,
-                Some(s) => panic!("Unknown role {} for move {}", s, input),
-            };
-            Move::Place(role, square)
-        } else if words[0] == "M" {
-            let start_square = utils::Square::parse_square::<S>(&words[1].to_lowercase()).unwrap();
-            let end_square = utils::Square::parse_square::<S>(&words[2].to_lowercase()).unwrap();
-            let pieces_dropped: Vec<u8> = words
-                .iter()
-                .skip(3)
-                .map(|s| u8::from_str(s).unwrap())
-                .collect();
+        match words.first() {
+            Some(&"P") => {
+                let square = parse_square(words.get(1))?;
+                let role = match words.get(2) {
+                    Some(&"C") => Role::Cap,
+                    Some(&"W") => Role::Wall,
+                    None => Role::Flat,
+                    Some(_) => return Err(parse_error("Unknown role")),
+                };
+                if words.len() > 3 {
+                    return Err(parse_error("Unexpected input"));
+                }
+                Ok(Move::Place(role, square))
+            }
+            Some(&"M") => {
+                let start_square = parse_square(words.get(1))?;
+                let end_square = parse_square(words.get(2))?;
+                let pieces_dropped: Vec<u8> = words
+                    .iter()
+                    .skip(3)
+                    .map(|s| match u8::from_str(s) {
+                        Ok(pieces) if pieces > 0 => Ok(pieces),
+                        _ => Err(parse_error("Invalid number of pieces")),
+                    })
+                    .collect::<Result<_, _>>()?;
+
+                let direction = match (
+                    start_square.rank::<S>().cmp(&end_square.rank::<S>()),
+                    start_square.file::<S>().cmp(&end_square.file::<S>()),
+                ) {
+                    (Ordering::Equal, Ordering::Less) => Direction::East,
+                    (Ordering::Equal, Ordering::Greater) => Direction::West,
+                    (Ordering::Less, Ordering::Equal) => Direction::South,
+                    (Ordering::Greater, Ordering::Equal) => Direction::North,
+                    _ => return Err(parse_error("Diagonal or zero-length movement")),
+                };
+                let distance = start_square
+                    .rank::<S>()
+                    .abs_diff(end_square.rank::<S>())
+                    .max(start_square.file::<S>().abs_diff(end_square.file::<S>()));
+                if pieces_dropped.len() != distance as usize {
+                    return Err(parse_error("Wrong number of drops"));
+                }
 
-            let num_pieces_taken: u8 = pieces_dropped.iter().sum();
+                let num_pieces_taken: u8 = pieces_dropped.iter().sum();
+                if num_pieces_taken as usize > S {
+                    return Err(parse_error("Too many pieces carried"));
+                }
 
-            let mut pieces_held = num_pieces_taken;
+                let mut pieces_held = num_pieces_taken;
 
-            let pieces_taken: StackMovement =
-                iter::once(num_pieces_taken)
+                let pieces_taken: StackMovement = iter::once(num_pieces_taken)
                     .chain(pieces_dropped.iter().take(pieces_dropped.len() - 1).map(
                         |pieces_to_drop| {
                             pieces_held -= pieces_to_drop;
@@ -210,20 +317,82 @@ impl Move {
                     .map(|pieces_to_take| Movement { pieces_to_take })
                     .collect();
 
-            let direction = match (
-                start_square.rank::<S>().cmp(&end_square.rank::<S>()),
-                start_square.file::<S>().cmp(&end_square.file::<S>()),
-            ) {
-                (Ordering::Equal, Ordering::Less) => Direction::East,
-                (Ordering::Equal, Ordering::Greater) => Direction::West,
-                (Ordering::Less, Ordering::Equal) => Direction::South,
-                (Ordering::Greater, Ordering::Equal) => Direction::North,
-                _ => panic!("Diagonal move string {}", input),
-            };
+                Ok(Move::Move(start_square, direction, pieces_taken))
+            }
+            _ => Err(parse_error("Expected P or M")),
+        }
+    }
+
+    /// Compact 2-byte encoding of the move, for storing large numbers of moves.
+    /// The lowest 6 bits are the square, and the next 2 bits are the role or direction.
+    /// The highest 8 bits are zero for placements. For stack moves, bit `n` is set if `n + 1` pieces are carried onto a square.
+    pub fn to_u16(&self) -> u16 {
+        match self {
+            Move::Place(role, square) => {
+                let role_bits = match role {
+                    Flat => 0,
+                    Wall => 1,
+                    Cap => 2,
+                };
+                square.0 as u16 | role_bits << 6
+            }
+            Move::Move(square, direction, stack_movement) => {
+                let direction_bits = match direction {
+                    North => 0,
+                    West => 1,
+                    East => 2,
+                    South => 3,
+                };
+                let carries = stack_movement
+                    .into_iter()
+                    .fold(0, |carries, Movement { pieces_to_take }| {
+                        carries | 1 << (pieces_to_take - 1)
+                    });
+                square.0 as u16 | direction_bits << 6 | carries << 8
+            }
+        }
+    }
 
-            Move::Move(start_square, direction, pieces_taken)
+    /// Decode a move from `to_u16`. Returns `None` if the data is not a valid encoding,
+    /// or if the square or the spread does not fit on the board.
+    pub fn from_u16<const S: usize>(data: u16) -> Option<Self> {
+        let square = Square((data & 0b11_1111) as u8);
+        if square.0 as usize >= S * S {
+            return None;
+        }
+        let kind_bits = (data >> 6) & 0b11;
+        let carries = (data >> 8) as u8;
+        if carries == 0 {
+            let role = match kind_bits {
+                0 => Flat,
+                1 => Wall,
+                2 => Cap,
+                _ => return None,
+            };
+            Some(Move::Place(role, square))
         } else {
-            unreachable!()
+            let direction = match kind_bits {
+                0 => North,
+                1 => West,
+                2 => East,
+                _ => South,
+            };
+            let pieces_taken = 8 - carries.leading_zeros() as usize;
+            if pieces_taken > S {
+                return None;
+            }
+            let mut destination = square;
+            for _ in 0..carries.count_ones() {
+                destination = destination.go_direction::<S>(direction)?;
+            }
+            let stack_movement = (0..8)
+                .rev()
+                .filter(|i| carries & 1 << i != 0)
+                .map(|i| Movement {
+                    pieces_to_take: i + 1,
+                })
+                .collect();
+            Some(Move::Move(square, direction, stack_movement))
         }
     }
 }
@@ -234,3 +403,7 @@ pub enum ReverseMove {
     Place(Square),
     Move(Square, Direction, StackMovement, bool),
 }
+
+fn illegal_move_error(message: String) -> pgn_traits::Error {
+    pgn_traits::Error::new(pgn_traits::ErrorKind::IllegalMove, message)
+}
@@ -21,7 +21,7 @@ pub struct Square(pub u8);
 impl Square {
     pub fn from_rank_file<const S: usize>(rank: u8, file: u8) -> Self {
         debug_assert!(rank < S as u8 && file < S as u8);
-        Square(rank * S as u8 + file as u8)
+        Square(rank * S as u8 + file)
     }
 
     pub fn rank<const S: usize>(self) -> u8 {
@@ -222,6 +222,7 @@ impl ops::Not for Piece {
 }
 
 /// The contents of a square on the board, consisting of zero or more pieces
+/// Nothing can be placed or moved onto a capstone or a wall, so only the top stone stores its role, and the pieces below it are always flats
 #[derive(Copy, Clone, PartialEq, Eq, Debug, Default, Hash)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Stack {
@@ -370,6 +371,136 @@ impl Direction {
     }
 }
 
+/// The two sides in a forced-win search: the side trying to build a road, and the side trying to stop it
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AttackerDefender {
+    Attacker,
+    Defender,
+}
+
+/// A set of kinds of moves, used to tell which kinds of moves are available in a position without generating them
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Hash)]
+pub struct MoveKinds(u8);
+
+impl MoveKinds {
+    pub const NONE: MoveKinds = MoveKinds(0);
+    /// Placing a stone from the reserves
+    pub const PLACEMENT: MoveKinds = MoveKinds(1);
+    /// Moving or spreading a stack
+    pub const SPREAD: MoveKinds = MoveKinds(2);
+
+    /// Whether every kind of move in `other` is also in this set
+    pub fn contains(self, other: MoveKinds) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl ops::BitOr for MoveKinds {
+    type Output = MoveKinds;
+
+    fn bitor(self, rhs: MoveKinds) -> MoveKinds {
+        MoveKinds(self.0 | rhs.0)
+    }
+}
+
+impl ops::BitOrAssign for MoveKinds {
+    fn bitor_assign(&mut self, rhs: MoveKinds) {
+        self.0 |= rhs.0
+    }
+}
+
+/// One of the 8 symmetries of the board, in the same order as `Position::symmetries`
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Symmetry {
+    Identity,
+    FlipX,
+    FlipY,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    /// Rotate 90 degrees, then flip along the x axis
+    RotateFlipX,
+    /// Rotate 90 degrees, then flip along the y axis
+    RotateFlipY,
+}
+
+impl Symmetry {
+    pub const ALL: [Symmetry; 8] = [
+        Symmetry::Identity,
+        Symmetry::FlipX,
+        Symmetry::FlipY,
+        Symmetry::Rotate90,
+        Symmetry::Rotate180,
+        Symmetry::Rotate270,
+        Symmetry::RotateFlipX,
+        Symmetry::RotateFlipY,
+    ];
+
+    /// The symmetry that undoes this one
+    pub fn inverse(self) -> Self {
+        match self {
+            Symmetry::Rotate90 => Symmetry::Rotate270,
+            Symmetry::Rotate270 => Symmetry::Rotate90,
+            symmetry => symmetry,
+        }
+    }
+
+    /// Where a square ends up after the symmetry is applied to the board
+    pub fn transform_square<const S: usize>(self, square: Square) -> Square {
+        let flip_x =
+            |sq: Square| Square::from_rank_file::<S>(sq.rank::<S>(), S as u8 - 1 - sq.file::<S>());
+        let flip_y =
+            |sq: Square| Square::from_rank_file::<S>(S as u8 - 1 - sq.rank::<S>(), sq.file::<S>());
+        let rotate =
+            |sq: Square| Square::from_rank_file::<S>(sq.file::<S>(), S as u8 - 1 - sq.rank::<S>());
+        match self {
+            Symmetry::Identity => square,
+            Symmetry::FlipX => flip_x(square),
+            Symmetry::FlipY => flip_y(square),
+            Symmetry::Rotate90 => rotate(square),
+            Symmetry::Rotate180 => rotate(rotate(square)),
+            Symmetry::Rotate270 => rotate(rotate(rotate(square))),
+            Symmetry::RotateFlipX => flip_x(rotate(square)),
+            Symmetry::RotateFlipY => flip_y(rotate(square)),
+        }
+    }
+
+    /// Which way a direction points after the symmetry is applied to the board
+    pub fn transform_direction(self, direction: Direction) -> Direction {
+        let flip_x = |dir: Direction| match dir {
+            West => East,
+            East => West,
+            dir => dir,
+        };
+        let flip_y = |dir: Direction| match dir {
+            North => South,
+            South => North,
+            dir => dir,
+        };
+        let rotate = |dir: Direction| match dir {
+            North => East,
+            East => South,
+            South => West,
+            West => North,
+        };
+        match self {
+            Symmetry::Identity => direction,
+            Symmetry::FlipX => flip_x(direction),
+            Symmetry::FlipY => flip_y(direction),
+            Symmetry::Rotate90 => rotate(direction),
+            Symmetry::Rotate180 => direction.reverse(),
+            Symmetry::Rotate270 => rotate(direction).reverse(),
+            Symmetry::RotateFlipX => flip_x(rotate(direction)),
+            Symmetry::RotateFlipY => flip_y(rotate(direction)),
+        }
+    }
+}
+
 /// One or more `Movement`s, storing how many pieces are dropped off at each step
 #[derive(Copy, Clone, Default, PartialEq, Eq, Debug)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use board_game_traits::GameResult;
 use board_game_traits::Position;
 
@@ -12,9 +14,149 @@ pub struct Game<B: Position> {
     pub tags: Vec<(String, String)>,
 }
 
+impl<B: Position + Clone> Game<B> {
+    /// Iterate over the positions of the game, each paired with the move played from it.
+    /// The final position, after the last move, is not included.
+    pub fn iter_positions(&self) -> impl Iterator<Item = (B, &PtnMove<B::Move>)> + '_ {
+        let mut position = self.start_position.clone();
+        self.moves.iter().map(move |ptn_move| {
+            let current_position = position.clone();
+            position.do_move(ptn_move.mv.clone());
+            (current_position, ptn_move)
+        })
+    }
+
+    /// The game with only its first `ply` moves.
+    /// If any moves are removed, the result is recomputed from the final position, and is `None` unless that position is decided.
+    pub fn truncate_at_ply(&self, ply: usize) -> Self {
+        self.subgame(0, ply)
+    }
+
+    /// The moves from `start_ply` up to `end_ply`, as a game starting from the position after `start_ply` moves.
+    /// The result is recomputed like in `truncate_at_ply`.
+    /// Panics if `start_ply` is greater than `end_ply`, or than the number of moves
+    pub fn subgame(&self, start_ply: usize, end_ply: usize) -> Self {
+        let end_ply = end_ply.min(self.moves.len());
+        assert!(
+            start_ply <= end_ply,
+            "Cannot start subgame at ply {}, it ends at ply {}",
+            start_ply,
+            end_ply
+        );
+        let mut position = self.start_position.clone();
+        for ptn_move in &self.moves[..start_ply] {
+            position.do_move(ptn_move.mv.clone());
+        }
+        let start_position = position.clone();
+        for ptn_move in &self.moves[start_ply..end_ply] {
+            position.do_move(ptn_move.mv.clone());
+        }
+        let game_result = if end_ply == self.moves.len() {
+            self.game_result
+        } else {
+            position.game_result()
+        };
+        // The result and start position tags may no longer be correct
+        let tags = self
+            .tags
+            .iter()
+            .filter(|(tag, _)| {
+                !(end_ply < self.moves.len() && tag.eq_ignore_ascii_case("Result")
+                    || start_ply > 0 && tag.eq_ignore_ascii_case("FEN"))
+            })
+            .cloned()
+            .collect();
+        Game {
+            start_position,
+            moves: self.moves[start_ply..end_ply].to_vec(),
+            game_result,
+            tags,
+        }
+    }
+}
+
 #[derive(Default, Debug, Clone, PartialEq)]
 pub struct PtnMove<Move> {
     pub mv: Move,
     pub annotations: Vec<&'static str>,
     pub comment: String,
+    /// The player's remaining time after the move, from a `[clock h:mm:ss]` or `[%clk h:mm:ss]` command in the comment.
+    /// The writer only outputs the comment, which still contains the command
+    pub clock: Option<Duration>,
+}
+
+/// Positions whose komi is written to, and read from, the `Komi` tag of PTN files
+pub trait KomiPosition: Sized {
+    /// The value of the `Komi` tag, or `None` if the game has no komi
+    fn komi_tag(&self) -> Option<String>;
+
+    /// The position with the komi from a `Komi` tag
+    fn with_komi_tag(self, value: &str) -> Result<Self, pgn_traits::Error>;
+}
+
+impl<const S: usize> KomiPosition for crate::position::Position<S> {
+    fn komi_tag(&self) -> Option<String> {
+        (self.komi() != 0).then(|| self.komi_string())
+    }
+
+    fn with_komi_tag(self, value: &str) -> Result<Self, pgn_traits::Error> {
+        let komi = Self::parse_komi(value).ok_or_else(|| {
+            pgn_traits::Error::new_parse_error(format!("Invalid komi \"{}\"", value))
+        })?;
+        Ok(self.with_komi(komi))
+    }
+}
+
+/// Thresholds for annotating moves with glyphs, based on how much a move changes the evaluation.
+/// Each threshold is a change in the moving side's winning probability, from before the move to after it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GlyphThresholds {
+    /// `!!`
+    pub brilliant: f32,
+    /// `!`
+    pub good: f32,
+    /// `?!`
+    pub dubious: f32,
+    /// `?`
+    pub mistake: f32,
+    /// `??`
+    pub blunder: f32,
+}
+
+impl Default for GlyphThresholds {
+    fn default() -> Self {
+        GlyphThresholds {
+            brilliant: 0.3,
+            good: 0.15,
+            dubious: -0.08,
+            mistake: -0.15,
+            blunder: -0.3,
+        }
+    }
+}
+
+impl GlyphThresholds {
+    /// The glyph for a move, given the moving side's winning probability before and after the move.
+    /// Returns `None` if the change is too small to annotate.
+    pub fn glyph(&self, before: f32, after: f32) -> Option<&'static str> {
+        let delta = after - before;
+        if delta >= self.brilliant {
+            Some("!!")
+        } else if delta >= self.good {
+            Some("!")
+        } else if delta <= self.blunder {
+            Some("??")
+        } else if delta <= self.mistake {
+            Some("?")
+        } else if delta <= self.dubious {
+            Some("?!")
+        } else {
+            None
+        }
+    }
+}
+
+/// The glyph for a move with the default thresholds, given the moving side's winning probability before and after the move.
+pub fn annotation_glyph(before: f32, after: f32) -> Option<&'static str> {
+    GlyphThresholds::default().glyph(before, after)
 }
@@ -1,12 +1,13 @@
-use crate::ptn::{Game, PtnMove};
+use crate::ptn::{Game, KomiPosition, PtnMove};
 use board_game_traits::GameResult;
 use pgn_traits::PgnPosition;
 use std::error;
 use std::error::Error;
 use std::fmt::Debug;
 use std::str::FromStr;
+use std::time::Duration;
 
-pub fn parse_ptn<B: PgnPosition + Debug + Clone>(
+pub fn parse_ptn<B: PgnPosition + KomiPosition + Debug + Clone>(
     input: &str,
 ) -> Result<Vec<Game<B>>, Box<dyn error::Error>> {
     let mut parser = ParserData { input };
@@ -26,7 +27,7 @@ pub fn parse_ptn<B: PgnPosition + Debug + Clone>(
     }
 }
 
-fn parse_game<B: PgnPosition + Debug + Clone>(
+fn parse_game<B: PgnPosition + KomiPosition + Debug + Clone>(
     input: &mut ParserData,
 ) -> Result<Game<B>, Box<dyn Error>> {
     let mut tags = vec![];
@@ -36,7 +37,13 @@ fn parse_game<B: PgnPosition + Debug + Clone>(
         input.skip_whitespaces();
         tags.push((tag.to_string(), value));
     }
-    let position = B::start_position();
+    let mut position = B::start_position();
+    if let Some((_, komi)) = tags
+        .iter()
+        .find(|(tag, _)| tag.eq_ignore_ascii_case("Komi"))
+    {
+        position = position.with_komi_tag(komi)?;
+    }
 
     let (moves, game_result) = parse_moves(input, position.clone())?;
 
@@ -92,10 +99,12 @@ fn parse_tag<'a>(input: &mut ParserData<'a>) -> Result<(&'a str, String), pgn_tr
     }
 }
 
+type MovesAndResult<Move> = (Vec<PtnMove<Move>>, Option<GameResult>);
+
 fn parse_moves<B: PgnPosition + Debug + Clone>(
     input: &mut ParserData,
     mut position: B,
-) -> Result<(Vec<PtnMove<B::Move>>, Option<GameResult>), Box<dyn Error>> {
+) -> Result<MovesAndResult<B::Move>, Box<dyn Error>> {
     let mut moves: Vec<PtnMove<B::Move>> = vec![];
     let mut _ply_counter = 0; // Last ply seen
     loop {
@@ -137,7 +146,7 @@ fn parse_moves<B: PgnPosition + Debug + Clone>(
                 annotations.insert(0, *annotation);
             }
 
-            match position.move_from_san(&move_string) {
+            match position.move_from_san(move_string) {
                 Ok(mv) => {
                     let mut legal_moves = vec![];
                     position.generate_moves(&mut legal_moves);
@@ -157,12 +166,14 @@ fn parse_moves<B: PgnPosition + Debug + Clone>(
                             mv,
                             annotations,
                             comment: comment.to_string(),
+                            clock: parse_clock(comment),
                         })
                     } else {
                         moves.push(PtnMove {
                             mv,
                             annotations,
                             comment: String::new(),
+                            clock: None,
                         });
                     }
                 }
@@ -177,6 +188,33 @@ fn parse_moves<B: PgnPosition + Debug + Clone>(
     }
 }
 
+/// Parse the remaining time from a `[clock h:mm:ss]` or `[%clk h:mm:ss]` command in a move comment.
+/// Hours and minutes are optional, and the seconds may have a fractional part.
+pub fn parse_clock(comment: &str) -> Option<Duration> {
+    comment.split('[').skip(1).find_map(|command| {
+        let (name, value) = command.split(']').next()?.trim().split_once(' ')?;
+        if name == "clock" || name == "%clk" {
+            parse_clock_time(value.trim())
+        } else {
+            None
+        }
+    })
+}
+
+fn parse_clock_time(time: &str) -> Option<Duration> {
+    let mut parts = time.rsplit(':');
+    let seconds = Duration::try_from_secs_f64(f64::from_str(parts.next()?).ok()?).ok()?;
+    let minutes = parts.next().map_or(Some(0), |s| u64::from_str(s).ok())?;
+    let hours = parts.next().map_or(Some(0), |s| u64::from_str(s).ok())?;
+    if parts.next().is_some() {
+        return None;
+    }
+    let whole_seconds = hours
+        .checked_mul(3600)?
+        .checked_add(minutes.checked_mul(60)?)?;
+    Duration::from_secs(whole_seconds).checked_add(seconds)
+}
+
 struct ParserData<'a> {
     input: &'a str,
 }
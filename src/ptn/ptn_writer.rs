@@ -1,4 +1,4 @@
-use crate::ptn::{Game, PtnMove};
+use crate::ptn::{Game, KomiPosition, PtnMove};
 use board_game_traits::{Color, GameResult};
 use pgn_traits::PgnPosition;
 use std::io;
@@ -6,7 +6,7 @@ use std::io::Write;
 
 const LINE_WIDTH: usize = 80;
 
-impl<B: PgnPosition + Clone> Game<B> {
+impl<B: PgnPosition + KomiPosition + Clone> Game<B> {
     pub fn game_to_ptn<W: Write>(&self, f: &mut W) -> Result<(), io::Error> {
         // Write the required tags first, in the correct order
         // Fill in default value if they are not available
@@ -39,12 +39,19 @@ impl<B: PgnPosition + Clone> Game<B> {
             }
         }
 
-        if self.start_position != B::start_position()
+        // Compare the TPS only, since komi is written in its own tag
+        if self.start_position.to_fen() != B::start_position().to_fen()
             && !tags.iter().any(|(tag, _)| tag.eq_ignore_ascii_case("FEN"))
         {
             writeln!(f, "[FEN \"{}\"", self.start_position.to_fen())?;
         }
 
+        if let Some(komi) = self.start_position.komi_tag() {
+            if !tags.iter().any(|(tag, _)| tag.eq_ignore_ascii_case("Komi")) {
+                writeln!(f, "[Komi \"{}\"]", komi)?;
+            }
+        }
+
         // Write any remaining tags
         for (tag, value) in tags.iter() {
             writeln!(f, "[{} \"{}\"]", tag, value)?;
@@ -58,20 +65,20 @@ impl<B: PgnPosition + Clone> Game<B> {
 
         for (i, PtnMove { mv, comment, .. }) in self.moves.iter().enumerate() {
             if i == 0 && position.side_to_move() == Color::Black {
-                buffer.push_str(&format!("1... {}", position.move_to_san(&mv)));
+                buffer.push_str(&format!("1... {}", position.move_to_san(mv)));
             } else if position.side_to_move() == Color::White {
                 buffer.push_str(&format!(
                     "{}. {}",
-                    (i + 1) / 2 + 1,
-                    position.move_to_san(&mv),
+                    i.div_ceil(2) + 1,
+                    position.move_to_san(mv),
                 ));
             } else {
-                buffer.push_str(&position.move_to_san(&mv));
+                buffer.push_str(&position.move_to_san(mv));
             }
 
             if !comment.is_empty() {
                 buffer.push_str(" {");
-                buffer.push_str(&comment);
+                buffer.push_str(comment);
                 buffer.push('}');
             }
 
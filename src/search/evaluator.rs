@@ -0,0 +1,100 @@
+use board_game_traits::{Color, Position as PositionTrait};
+
+use crate::position::{GroupData, Move, Position, TunableBoard};
+use crate::search::{cp_to_win_percentage, Score};
+
+/// Evaluation used by the search when expanding new nodes
+pub trait Evaluator<const S: usize> {
+    /// Winning probability of the side to move, according to the static evaluation
+    fn value(&self, position: &Position<S>, group_data: &GroupData<S>) -> Score;
+
+    /// Generate all legal moves, with a heuristic probability of each move being played.
+    /// The probabilities are not normalized.
+    fn policy(
+        &self,
+        position: &Position<S>,
+        group_data: &GroupData<S>,
+        moves: &mut Vec<(Move, Score)>,
+    );
+}
+
+/// Averages the evaluations of several parameter sets, to smooth out the weaknesses of each individual set.
+#[derive(Clone, PartialEq, Debug)]
+pub struct EnsembleEvaluator<const S: usize> {
+    param_sets: Vec<(Vec<f32>, Vec<f32>)>,
+}
+
+impl<const S: usize> EnsembleEvaluator<S> {
+    /// Create an ensemble from a list of `(value_params, policy_params)`.
+    /// Panics if the list is empty, or if any set has the wrong number of parameters for the board size.
+    pub fn new(param_sets: Vec<(Vec<f32>, Vec<f32>)>) -> Self {
+        assert!(!param_sets.is_empty(), "Ensemble must have parameters");
+        for (value_params, policy_params) in param_sets.iter() {
+            assert_eq!(
+                value_params.len(),
+                <Position<S>>::value_params().len(),
+                "Wrong number of value parameters for {}s",
+                S
+            );
+            assert_eq!(
+                policy_params.len(),
+                <Position<S>>::policy_params().len(),
+                "Wrong number of policy parameters for {}s",
+                S
+            );
+        }
+        EnsembleEvaluator { param_sets }
+    }
+}
+
+impl<const S: usize> Evaluator<S> for EnsembleEvaluator<S> {
+    fn value(&self, position: &Position<S>, group_data: &GroupData<S>) -> Score {
+        let white_score_sum: Score = self
+            .param_sets
+            .iter()
+            .map(|(value_params, _)| {
+                cp_to_win_percentage(
+                    position.static_eval_with_params_and_data(group_data, value_params),
+                )
+            })
+            .sum();
+        let white_score = white_score_sum / self.param_sets.len() as Score;
+        match position.side_to_move() {
+            Color::White => white_score,
+            Color::Black => 1.0 - white_score,
+        }
+    }
+
+    fn policy(
+        &self,
+        position: &Position<S>,
+        group_data: &GroupData<S>,
+        moves: &mut Vec<(Move, Score)>,
+    ) {
+        debug_assert!(moves.is_empty());
+        let mut simple_moves = vec![];
+        let mut set_moves = vec![];
+        let mut coefficients = vec![0.0; <Position<S>>::policy_params().len()];
+
+        for (i, (_, policy_params)) in self.param_sets.iter().enumerate() {
+            position.generate_moves_with_params(
+                policy_params,
+                group_data,
+                &mut simple_moves,
+                &mut set_moves,
+                &mut coefficients,
+            );
+            if i == 0 {
+                moves.append(&mut set_moves);
+            } else {
+                // Moves are always generated in the same order
+                for ((_, score), (_, set_score)) in moves.iter_mut().zip(set_moves.drain(..)) {
+                    *score += set_score;
+                }
+            }
+        }
+        for (_, score) in moves.iter_mut() {
+            *score /= self.param_sets.len() as Score;
+        }
+    }
+}
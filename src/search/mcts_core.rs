@@ -1,21 +1,30 @@
-use std::ops;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::{fmt, mem, ops, ptr, thread};
 
 use board_game_traits::{Color, GameResult, Position as PositionTrait};
 use rand::distributions::Distribution;
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 
 use crate::evaluation::parameters;
-use crate::position::Move;
 /// This module contains the core of the MCTS search algorithm
 use crate::position::{GroupData, Position, TunableBoard};
-use crate::search::{cp_to_win_percentage, MctsSetting, Score};
+use crate::position::{Move, Role};
+use crate::search::{cp_to_win_percentage, Evaluator, MctsSetting, Score};
 
 /// A Monte Carlo Search Tree, containing every node that has been seen in search.
 #[derive(Clone, PartialEq, Debug)]
 pub struct Tree {
     pub children: Box<[TreeEdge]>,
     pub total_action_value: f64,
+    /// Sum of the draw probabilities backed up through this node. Draws are symmetric, so this is the same for both sides
+    pub total_draws: f64,
     pub is_terminal: bool,
+    /// Number of virtual losses applied to the edge leading to this node, by searches that have not been backed up yet
+    pub virtual_losses: u32,
+    /// Guards the children in a parallel search, see `SharedTree`
+    pub lock: NodeLock,
 }
 
 #[derive(Clone, PartialEq, Debug)]
@@ -25,6 +34,9 @@ pub struct TreeEdge {
     pub mean_action_value: Score,
     pub visits: u64,
     pub heuristic_score: Score,
+    /// The game result, if it has been proven by the search, and the number of plies until the game ends.
+    /// Like `mean_action_value`, this is from the perspective of the side to move after `mv`
+    pub proven_result: Option<(GameResultForUs, u16)>,
 }
 
 /// Temporary vectors that are continually re-used during search to avoid unnecessary allocations
@@ -71,10 +83,15 @@ impl TreeEdge {
             mean_action_value,
             visits: 0,
             heuristic_score,
+            proven_result: None,
         }
     }
 
     /// Perform one iteration of monte carlo tree search.
+    /// `depth` is incremented for every edge the search descends below this one.
+    /// `nodes` is the number of nodes in the tree, and is incremented if a new node is expanded.
+    /// Once it reaches the settings' maximum, the search only revisits existing nodes.
+    /// Returns the score of the iteration, and its probability of being a draw.
     ///
     /// Moves done on the board are not reversed.
     pub fn select<const S: usize>(
@@ -82,27 +99,32 @@ impl TreeEdge {
         position: &mut Position<S>,
         settings: &MctsSetting<S>,
         temp_vectors: &mut TempVectors,
-    ) -> Score {
+        depth: &mut u16,
+        nodes: &mut u64,
+    ) -> (Score, Score) {
         if self.visits == 0 {
+            *nodes += 1;
             self.expand(position, settings, temp_vectors)
         } else if self.child.as_ref().unwrap().is_terminal {
-            self.visits += 1;
-            self.child.as_mut().unwrap().total_action_value += self.mean_action_value as f64;
-            self.mean_action_value
+            self.revisit_leaf()
         } else {
             let node = self.child.as_mut().unwrap();
-            debug_assert_eq!(
-                self.visits,
-                node.children.iter().map(|edge| edge.visits).sum::<u64>() + 1,
+            // Pruned or excluded root moves may leave more visits on the root edge than on its children.
+            // In a tree with a maximum size, leaves are also revisited once the tree is full
+            debug_assert!(
+                self.visits == node.children.iter().map(|edge| edge.visits).sum::<u64>() + 1
+                    || (*depth == 0 || settings.max_nodes().is_some())
+                        && self.visits > node.children.iter().map(|edge| edge.visits).sum::<u64>(),
                 "{} visits, {} total action value, {} mean action value",
                 self.visits,
                 node.total_action_value,
                 self.mean_action_value
             );
+            let can_expand = settings.can_expand(*nodes);
             // Only generate child moves on the 2nd visit
-            if self.visits == 1 {
+            if node.children.is_empty() && can_expand {
                 let group_data = position.group_data();
-                node.init_children(&position, &group_data, settings, temp_vectors);
+                node.init_children(position, &group_data, settings, temp_vectors);
             }
 
             let visits_sqrt = (self.visits as Score).sqrt();
@@ -111,37 +133,66 @@ impl TreeEdge {
                     (1.0 + self.visits as Score + settings.c_puct_base()) / settings.c_puct_base(),
                 );
 
-            assert_ne!(
-                node.children.len(),
-                0,
+            assert!(
+                !node.children.is_empty() || !can_expand,
                 "No legal moves in position\n{:?}",
                 position
             );
 
+            let fpu_value = settings.fpu_value(self.mean_action_value);
+
             let mut best_exploration_value = 0.0;
-            let mut best_child_node_index = 0;
+            let mut best_child_node_index = None;
 
             for (i, edge) in node.children.iter().enumerate() {
-                let child_exploration_value = edge.exploration_value(visits_sqrt, dynamic_cpuct);
+                // Unvisited children cannot be expanded in a full tree
+                if edge.visits == 0 && !can_expand {
+                    continue;
+                }
+                let child_exploration_value =
+                    edge.exploration_value(visits_sqrt, dynamic_cpuct, fpu_value);
                 if child_exploration_value >= best_exploration_value {
-                    best_child_node_index = i;
+                    best_child_node_index = Some(i);
                     best_exploration_value = child_exploration_value;
                 }
             }
 
+            let best_child_node_index = match best_child_node_index {
+                Some(index) => index,
+                // The tree is full, and this node has no children to search. Back up its value like a terminal node
+                None => return self.revisit_leaf(),
+            };
+
             let child_edge = node.children.get_mut(best_child_node_index).unwrap();
 
             position.do_move(child_edge.mv.clone());
-            let result = 1.0 - child_edge.select::<S>(position, settings, temp_vectors);
+            *depth += 1;
+            let (child_result, draw) =
+                child_edge.select::<S>(position, settings, temp_vectors, depth, nodes);
+            let result = 1.0 - child_result;
+            if child_edge.proven_result.is_some() {
+                self.proven_result = node.proven_result();
+            }
             self.visits += 1;
 
             node.total_action_value += result as f64;
+            node.total_draws += draw as f64;
 
             self.mean_action_value = (node.total_action_value / self.visits as f64) as f32;
-            result
+            (result, draw)
         }
     }
 
+    /// Visit a terminal node, or a leaf in a full tree, again, backing up its existing statistics
+    fn revisit_leaf(&mut self) -> (Score, Score) {
+        let node = self.child.as_mut().unwrap();
+        let draw = (node.total_draws / self.visits as f64) as Score;
+        self.visits += 1;
+        node.total_action_value += self.mean_action_value as f64;
+        node.total_draws += draw as f64;
+        (self.mean_action_value, draw)
+    }
+
     // Never inline, for profiling purposes
     #[inline(never)]
     fn expand<const S: usize>(
@@ -149,7 +200,7 @@ impl TreeEdge {
         position: &mut Position<S>,
         settings: &MctsSetting<S>,
         temp_vectors: &mut TempVectors,
-    ) -> Score {
+    ) -> (Score, Score) {
         debug_assert!(self.child.is_none());
         self.child = Some(Box::new(Tree::new_node()));
         let child = self.child.as_mut().unwrap();
@@ -160,20 +211,37 @@ impl TreeEdge {
         child.total_action_value = eval as f64;
         self.mean_action_value = eval;
         child.is_terminal = is_terminal;
-        eval
+        let game_result = if is_terminal {
+            terminal_result(position)
+        } else {
+            None
+        };
+        let draw = draw_probability(game_result);
+        child.total_draws = draw as f64;
+        self.proven_result = game_result.map(|game_result| (game_result, 0));
+        (eval, draw)
     }
 
+    /// The value used to select which child to search next, from the parent's perspective.
+    /// If `fpu_value` is set, it is used as the action value of unvisited children
     #[inline]
-    pub fn exploration_value(&self, parent_visits_sqrt: Score, cpuct: Score) -> Score {
-        (1.0 - self.mean_action_value)
+    pub fn exploration_value(
+        &self,
+        parent_visits_sqrt: Score,
+        cpuct: Score,
+        fpu_value: Option<Score>,
+    ) -> Score {
+        let action_value = match fpu_value {
+            Some(fpu_value) if self.visits == 0 => fpu_value,
+            _ => 1.0 - self.mean_action_value,
+        };
+        action_value
             + cpuct * self.heuristic_score * parent_visits_sqrt / (1 + self.visits) as Score
     }
 }
 
 impl Tree {
     /// Do not initialize children in the expansion phase, for better performance
-    /// Never inline, for profiling purposes
-    #[inline(never)]
     fn init_children<const S: usize>(
         &mut self,
         position: &Position<S>,
@@ -181,13 +249,38 @@ impl Tree {
         settings: &MctsSetting<S>,
         temp_vectors: &mut TempVectors,
     ) {
-        position.generate_moves_with_params(
-            &settings.policy_params,
-            group_data,
-            &mut temp_vectors.simple_moves,
-            &mut temp_vectors.moves,
-            &mut temp_vectors.policy_scores,
-        );
+        self.children = Self::new_children(position, group_data, settings, temp_vectors);
+    }
+
+    /// Generate the child edges of the node for `position`
+    /// Never inline, for profiling purposes
+    #[inline(never)]
+    fn new_children<const S: usize>(
+        position: &Position<S>,
+        group_data: &GroupData<S>,
+        settings: &MctsSetting<S>,
+        temp_vectors: &mut TempVectors,
+    ) -> Box<[TreeEdge]> {
+        if let Some(ensemble) = &settings.ensemble {
+            ensemble.policy(position, group_data, &mut temp_vectors.moves);
+        } else {
+            position.generate_moves_with_params(
+                &settings.policy_params,
+                group_data,
+                &mut temp_vectors.simple_moves,
+                &mut temp_vectors.moves,
+                &mut temp_vectors.policy_scores,
+            );
+        }
+        let is_flat_placement = |mv: &Move| matches!(mv, Move::Place(Role::Flat, _));
+        if position.half_moves_played() < settings.flat_opening_plies
+            && temp_vectors
+                .moves
+                .iter()
+                .any(|(mv, _)| is_flat_placement(mv))
+        {
+            temp_vectors.moves.retain(|(mv, _)| is_flat_placement(mv));
+        }
         let mut children_vec = Vec::with_capacity(temp_vectors.moves.len());
         let policy_sum: f32 = temp_vectors.moves.iter().map(|(_, score)| *score).sum();
         let inv_sum = 1.0 / policy_sum;
@@ -198,14 +291,64 @@ impl Tree {
                 settings.initial_mean_action_value(),
             ));
         }
-        self.children = children_vec.into_boxed_slice();
+        children_vec.into_boxed_slice()
+    }
+
+    /// The game result of this node from the perspective of the side to move, if it is proven by its children.
+    /// A node is won if any child is lost for the opponent, in which case the shortest win is returned.
+    /// Otherwise, it is only proven if every child is proven, preferring the shortest draw, then the longest loss
+    pub fn proven_result(&self) -> Option<(GameResultForUs, u16)> {
+        children_proven_result(&self.children)
     }
 
     fn new_node() -> Self {
         Tree {
             children: Box::new([]),
             total_action_value: 0.0,
+            total_draws: 0.0,
             is_terminal: false,
+            virtual_losses: 0,
+            lock: NodeLock::default(),
+        }
+    }
+
+    /// The number of nodes in this subtree, including this one
+    pub fn num_nodes(&self) -> u64 {
+        1 + self
+            .children
+            .iter()
+            .filter_map(|edge| edge.child.as_ref())
+            .map(|child| child.num_nodes())
+            .sum::<u64>()
+    }
+
+    /// Total heap and inline memory used by this node and all its descendants, in bytes
+    pub fn mem_usage(&self) -> usize {
+        mem::size_of::<Tree>()
+            + self
+                .children
+                .iter()
+                .map(|edge| {
+                    mem::size_of::<TreeEdge>()
+                        + edge.child.as_ref().map_or(0, |child| child.mem_usage())
+                })
+                .sum::<usize>()
+    }
+
+    /// Add `bonus` to the heuristic scores of wall and capstone placements, and renormalize the scores to sum to 1
+    pub fn apply_wall_cap_bonus(&mut self, bonus: Score) {
+        for child in self.children.iter_mut() {
+            if matches!(child.mv, Move::Place(Role::Wall | Role::Cap, _)) {
+                child.heuristic_score += bonus;
+            }
+        }
+        let sum: Score = self
+            .children
+            .iter()
+            .map(|child| child.heuristic_score)
+            .sum();
+        for child in self.children.iter_mut() {
+            child.heuristic_score /= sum;
         }
     }
 
@@ -213,10 +356,9 @@ impl Tree {
     /// The noise is given `epsilon` weight.
     /// `alpha` is used to generate the noise, lower values generate more varied noise.
     /// Values above 1 are less noisy, and tend towards uniform outputs
-    pub fn apply_dirichlet(&mut self, epsilon: f32, alpha: f32) {
-        let mut rng = rand::thread_rng();
+    pub fn apply_dirichlet<R: Rng>(&mut self, epsilon: f32, alpha: f32, rng: &mut R) {
         let dirichlet = rand_distr::Dirichlet::new_with_size(alpha, self.children.len()).unwrap();
-        let noise_vec = dirichlet.sample(&mut rng);
+        let noise_vec = dirichlet.sample(rng);
         for (child_prior, eta) in self
             .children
             .iter_mut()
@@ -228,6 +370,373 @@ impl Tree {
     }
 }
 
+/// A spinlock guarding the children of a node in a parallel search.
+/// The sequential search has exclusive access to the tree, and never takes it
+#[derive(Default)]
+pub struct NodeLock(AtomicBool);
+
+impl NodeLock {
+    fn lock(&self) -> NodeLockGuard<'_> {
+        while self
+            .0
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            thread::yield_now();
+        }
+        NodeLockGuard(self)
+    }
+}
+
+/// The lock is not part of the node's data, so a cloned node starts unlocked, and all locks compare equal
+impl Clone for NodeLock {
+    fn clone(&self) -> Self {
+        NodeLock::default()
+    }
+}
+
+impl PartialEq for NodeLock {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl fmt::Debug for NodeLock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("NodeLock")
+    }
+}
+
+struct NodeLockGuard<'a>(&'a NodeLock);
+
+impl Drop for NodeLockGuard<'_> {
+    fn drop(&mut self) {
+        self.0 .0.store(false, Ordering::Release);
+    }
+}
+
+/// The outcome of descending the tree in a parallel search
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Descent {
+    /// Reached an unexpanded node, which must be evaluated before backing up
+    Expand,
+    /// Reached a terminal node, which can be backed up immediately
+    Terminal,
+    /// Reached a node that another thread is currently expanding. No virtual losses were applied
+    Collision,
+    /// Reached a leaf that cannot be expanded, because the tree has reached its maximum size.
+    /// It is backed up like a terminal node
+    Full,
+}
+
+/// An edge on a thread's path down a `SharedTree`, and the node whose children it belongs to.
+/// The root edge has no parent node
+#[derive(Clone, Copy, Debug)]
+pub struct PathStep {
+    edge: *mut TreeEdge,
+    parent: Option<*mut Tree>,
+}
+
+/// A search tree shared by the threads of a parallel search.
+///
+/// Every node's lock guards its children: the statistics of each child edge, and of the node the edge leads to.
+/// The root edge and the root node's statistics are guarded by a separate lock.
+/// Each thread only holds one lock at a time, and never creates references to a whole node,
+/// so other threads can update the node's statistics and its children under different locks.
+pub struct SharedTree<'a> {
+    root: *mut TreeEdge,
+    root_lock: NodeLock,
+    _tree: PhantomData<&'a mut TreeEdge>,
+}
+
+// Safety: The tree is only accessed while holding the lock guarding the accessed fields
+unsafe impl Send for SharedTree<'_> {}
+unsafe impl Sync for SharedTree<'_> {}
+
+impl<'a> SharedTree<'a> {
+    pub fn new(root: &'a mut TreeEdge) -> Self {
+        SharedTree {
+            root,
+            root_lock: NodeLock::default(),
+            _tree: PhantomData,
+        }
+    }
+
+    /// The lock guarding the statistics of `step.edge`
+    fn lock(&self, step: PathStep) -> NodeLockGuard<'_> {
+        match step.parent {
+            // Safety: Nodes are never removed while the tree is shared
+            Some(parent) => unsafe { (*ptr::addr_of!((*parent).lock)).lock() },
+            None => self.root_lock.lock(),
+        }
+    }
+
+    /// Select a path down the tree, without evaluating the leaf.
+    /// `virtual_loss` lost visits are applied to every edge on the path, to steer other threads elsewhere.
+    /// The path is written to `path`, and the moves are done on `position`.
+    ///
+    /// If `can_expand` is false, the tree is full, and the search only revisits existing nodes.
+    ///
+    /// Unless a collision is returned, the path must be passed to `backup` afterwards.
+    pub fn select<const S: usize>(
+        &self,
+        position: &mut Position<S>,
+        settings: &MctsSetting<S>,
+        temp_vectors: &mut TempVectors,
+        path: &mut Vec<PathStep>,
+        virtual_loss: u32,
+        can_expand: bool,
+    ) -> Descent {
+        path.clear();
+        let mut step = PathStep {
+            edge: self.root,
+            parent: None,
+        };
+        let mut guard = self.lock(step);
+        loop {
+            // Safety: `guard` guards the statistics of `step.edge` and its child node
+            let (visits, mean_action_value, node) = unsafe {
+                let edge = step.edge;
+                let visits = (*edge).visits;
+                let mean_action_value = (*edge).mean_action_value;
+                match (*edge)
+                    .child
+                    .as_mut()
+                    .map(|child| ptr::addr_of_mut!(**child))
+                {
+                    // A leaf with visits, but no child, has a virtual loss from another thread
+                    None if visits > 0 => {
+                        drop(guard);
+                        self.remove_virtual_losses(path, virtual_loss);
+                        return Descent::Collision;
+                    }
+                    None => {
+                        (*edge).visits += virtual_loss as u64;
+                        // Until it is expanded, an unexpanded node is a certain loss for the parent
+                        (*edge).mean_action_value = 1.0;
+                        path.push(step);
+                        return Descent::Expand;
+                    }
+                    Some(node) => {
+                        (*edge).visits += virtual_loss as u64;
+                        (*node).virtual_losses += virtual_loss;
+                        update_mean_action_value(edge, node);
+                        path.push(step);
+                        if (*node).is_terminal {
+                            return Descent::Terminal;
+                        }
+                        (visits, mean_action_value, node)
+                    }
+                }
+            };
+            drop(guard);
+
+            // Safety: The node's lock guards its children
+            unsafe {
+                let children = ptr::addr_of_mut!((*node).children);
+                guard = (*ptr::addr_of!((*node).lock)).lock();
+                // Only generate child moves on the 2nd visit. Generate them without holding the lock
+                if (&*children).is_empty() && can_expand {
+                    drop(guard);
+                    let group_data = position.group_data();
+                    let new_children =
+                        Tree::new_children(position, &group_data, settings, temp_vectors);
+                    guard = (*ptr::addr_of!((*node).lock)).lock();
+                    // Another thread may have generated them in the meantime
+                    if (&*children).is_empty() {
+                        *children = new_children;
+                    }
+                }
+
+                let visits_sqrt = (visits as Score).sqrt();
+                let dynamic_cpuct = settings.c_puct_init()
+                    + Score::ln(
+                        (1.0 + visits as Score + settings.c_puct_base()) / settings.c_puct_base(),
+                    );
+
+                let fpu_value = settings.fpu_value(mean_action_value);
+
+                let mut best_exploration_value = 0.0;
+                let mut best_child_node_index = None;
+
+                for (i, edge) in (*children).iter().enumerate() {
+                    // Unvisited children cannot be expanded in a full tree
+                    if edge.visits == 0 && !can_expand {
+                        continue;
+                    }
+                    let child_exploration_value =
+                        edge.exploration_value(visits_sqrt, dynamic_cpuct, fpu_value);
+                    if child_exploration_value >= best_exploration_value {
+                        best_child_node_index = Some(i);
+                        best_exploration_value = child_exploration_value;
+                    }
+                }
+
+                match best_child_node_index {
+                    None => return Descent::Full,
+                    Some(index) => {
+                        let edge = ptr::addr_of_mut!((*children)[index]);
+                        position.do_move((*edge).mv.clone());
+                        step = PathStep {
+                            edge,
+                            parent: Some(node),
+                        };
+                    }
+                }
+            }
+        }
+    }
+
+    /// Remove the virtual losses from a path that will not be backed up
+    fn remove_virtual_losses(&self, path: &[PathStep], virtual_loss: u32) {
+        for &step in path.iter().rev() {
+            let _guard = self.lock(step);
+            // Safety: `_guard` guards the statistics of `step.edge` and its child node.
+            // The search stops at the first unexpanded edge, so every edge on the path has a child node
+            unsafe {
+                let edge = step.edge;
+                let node = (*edge)
+                    .child
+                    .as_mut()
+                    .map(|child| ptr::addr_of_mut!(**child));
+                let node = node.unwrap();
+                (*edge).visits -= virtual_loss as u64;
+                (*node).virtual_losses -= virtual_loss;
+                update_mean_action_value(edge, node);
+            }
+        }
+    }
+
+    /// Back up the result of a search down `path`, and remove its virtual losses.
+    /// `eval` is the evaluation of the leaf, and its game result if it is terminal. It must be set if the search returned `Descent::Expand`.
+    /// Returns the backed up score, and its probability of being a draw.
+    pub fn backup(
+        &self,
+        path: &[PathStep],
+        eval: Option<(Score, Option<GameResultForUs>)>,
+        virtual_loss: u32,
+    ) -> (Score, Score) {
+        let (&leaf, rest) = path.split_last().unwrap();
+        let guard = self.lock(leaf);
+        // Safety: `guard` guards the statistics of `leaf.edge` and its child node
+        let (mut result, draw) = unsafe {
+            let edge = leaf.edge;
+            let node = (*edge)
+                .child
+                .as_mut()
+                .map(|child| ptr::addr_of_mut!(**child));
+            (*edge).visits -= virtual_loss as u64;
+            match (node, eval) {
+                (None, Some((eval, game_result))) => {
+                    let mut child = Tree::new_node();
+                    let draw = draw_probability(game_result);
+                    child.total_action_value = eval as f64;
+                    child.total_draws = draw as f64;
+                    child.is_terminal = game_result.is_some();
+                    (*edge).child = Some(Box::new(child));
+                    (*edge).visits = 1;
+                    (*edge).mean_action_value = eval;
+                    (*edge).proven_result = game_result.map(|game_result| (game_result, 0));
+                    (eval, draw)
+                }
+                (None, None) => panic!("An expanded leaf must be backed up with an evaluation"),
+                // A terminal node, or a leaf in a full tree
+                (Some(node), _) => {
+                    (*node).virtual_losses -= virtual_loss;
+                    let real_visits = (*edge).visits - (*node).virtual_losses as u64;
+                    let result = ((*node).total_action_value / real_visits as f64) as Score;
+                    let draw = ((*node).total_draws / real_visits as f64) as Score;
+                    (*edge).visits += 1;
+                    (*node).total_action_value += result as f64;
+                    (*node).total_draws += draw as f64;
+                    update_mean_action_value(edge, node);
+                    (result, draw)
+                }
+            }
+        };
+        let mut child_proven_result = proven_result_update(leaf);
+        drop(guard);
+
+        for &step in rest.iter().rev() {
+            let _guard = self.lock(step);
+            // Safety: `_guard` guards the statistics of `step.edge` and its child node
+            unsafe {
+                let edge = step.edge;
+                let node = (*edge)
+                    .child
+                    .as_mut()
+                    .map(|child| ptr::addr_of_mut!(**child));
+                let node = node.unwrap();
+                (*edge).visits -= virtual_loss as u64;
+                (*node).virtual_losses -= virtual_loss;
+
+                result = 1.0 - result;
+                if let Some(proven_result) = child_proven_result {
+                    (*edge).proven_result = proven_result;
+                }
+                (*edge).visits += 1;
+                (*node).total_action_value += result as f64;
+                (*node).total_draws += draw as f64;
+                update_mean_action_value(edge, node);
+            }
+            child_proven_result = proven_result_update(step);
+        }
+        (result, draw)
+    }
+}
+
+/// If `step.edge` is proven, the parent edge's new proven result, from the children of `step.parent`.
+/// Must be called while holding the lock guarding `step.edge`
+fn proven_result_update(step: PathStep) -> Option<Option<(GameResultForUs, u16)>> {
+    // Safety: The caller holds the lock guarding the parent's children
+    unsafe {
+        match step.parent {
+            Some(parent) if (*step.edge).proven_result.is_some() => {
+                Some(children_proven_result(&*ptr::addr_of!((*parent).children)))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Set the mean action value from the child's total action value, counting each virtual loss as a loss for the parent.
+/// Must be called while holding the lock guarding `edge`
+unsafe fn update_mean_action_value(edge: *mut TreeEdge, node: *mut Tree) {
+    (*edge).mean_action_value = (((*node).total_action_value + (*node).virtual_losses as f64)
+        / (*edge).visits as f64) as Score;
+}
+
+/// The proven game result of a node with these children, see `Tree::proven_result`
+fn children_proven_result(children: &[TreeEdge]) -> Option<(GameResultForUs, u16)> {
+    if children.is_empty() {
+        return None;
+    }
+    let mut shortest_win: Option<u16> = None;
+    let mut shortest_draw: Option<u16> = None;
+    let mut longest_loss: u16 = 0;
+    let mut all_proven = true;
+
+    for edge in children.iter() {
+        match edge.proven_result {
+            Some((GameResultForUs::Loss, plies)) => {
+                shortest_win = Some(shortest_win.map_or(plies + 1, |win| win.min(plies + 1)))
+            }
+            Some((GameResultForUs::Draw, plies)) => {
+                shortest_draw = Some(shortest_draw.map_or(plies + 1, |draw| draw.min(plies + 1)))
+            }
+            Some((GameResultForUs::Win, plies)) => longest_loss = longest_loss.max(plies + 1),
+            None => all_proven = false,
+        }
+    }
+
+    match (shortest_win, shortest_draw) {
+        (Some(plies), _) => Some((GameResultForUs::Win, plies)),
+        _ if !all_proven => None,
+        (None, Some(plies)) => Some((GameResultForUs::Draw, plies)),
+        (None, None) => Some((GameResultForUs::Loss, longest_loss)),
+    }
+}
+
 /// Do a mcts rollout up to `depth` plies, before doing a static evaluation.
 /// Depth is 0 on default settings, in which case it immediately does a static evaluation
 /// Higher depths are mainly used for playing with reduced difficulty
@@ -241,24 +750,8 @@ pub fn rollout<const S: usize>(
 ) -> (Score, bool) {
     let group_data = position.group_data();
 
-    if let Some(game_result) = position.game_result_with_group_data(&group_data) {
-        let game_result_for_us = match (game_result, position.side_to_move()) {
-            (GameResult::Draw, _) => GameResultForUs::Draw,
-            (GameResult::WhiteWin, Color::Black) => GameResultForUs::Loss, // The side to move has lost
-            (GameResult::BlackWin, Color::White) => GameResultForUs::Loss, // The side to move has lost
-            (GameResult::WhiteWin, Color::White) => GameResultForUs::Win, // The side to move has lost
-            (GameResult::BlackWin, Color::Black) => GameResultForUs::Win, // The side to move has lost
-        };
-
-        (game_result_for_us.score(), true)
-    } else if depth == 0 {
-        let static_eval = cp_to_win_percentage(
-            position.static_eval_with_params_and_data(&group_data, &settings.value_params),
-        );
-        match position.side_to_move() {
-            Color::White => (static_eval, false),
-            Color::Black => (1.0 - static_eval, false),
-        }
+    if depth == 0 || position.game_result_with_group_data(&group_data).is_some() {
+        evaluate_leaf(position, &group_data, settings)
     } else {
         position.generate_moves_with_probabilities(
             &group_data,
@@ -267,9 +760,16 @@ pub fn rollout<const S: usize>(
             &mut temp_vectors.policy_scores,
         );
 
-        let mut rng = rand::thread_rng();
-
-        let best_move = best_move(&mut rng, settings.rollout_temperature, &temp_vectors.moves);
+        let best_move = if settings.deterministic() {
+            let mut rng = StdRng::seed_from_u64(position.zobrist_hash());
+            best_move(&mut rng, settings.rollout_temperature, &temp_vectors.moves)
+        } else {
+            best_move(
+                &mut rand::thread_rng(),
+                settings.rollout_temperature,
+                &temp_vectors.moves,
+            )
+        };
         position.do_move(best_move);
 
         temp_vectors.moves.clear();
@@ -278,6 +778,55 @@ pub fn rollout<const S: usize>(
     }
 }
 
+/// The value of a leaf node in the search, as a winning probability for the side to move, and whether the leaf is terminal.
+/// Terminal positions get their exact result, with draws adjusted for contempt. Other positions are scored by the static evaluation
+pub fn evaluate_leaf<const S: usize>(
+    position: &Position<S>,
+    group_data: &GroupData<S>,
+    settings: &MctsSetting<S>,
+) -> (Score, bool) {
+    if let Some(game_result) = position.game_result_with_group_data(group_data) {
+        let game_result_for_us = GameResultForUs::new(game_result, position.side_to_move());
+
+        if game_result_for_us == GameResultForUs::Draw {
+            (settings.draw_score(position.side_to_move()), true)
+        } else {
+            (game_result_for_us.score(), true)
+        }
+    } else {
+        if let Some(ensemble) = &settings.ensemble {
+            return (ensemble.value(position, group_data), false);
+        }
+        let static_eval = if settings.symmetrized_eval {
+            cp_to_win_percentage(position.evaluate_symmetrized_with_params(&settings.value_params))
+        } else {
+            cp_to_win_percentage(
+                position.static_eval_with_params_and_data(group_data, &settings.value_params),
+            )
+        };
+        match position.side_to_move() {
+            Color::White => (static_eval, false),
+            Color::Black => (1.0 - static_eval, false),
+        }
+    }
+}
+
+/// The draw probability backed up from a leaf. Only terminal draws are counted, as the evaluation does not predict draws
+fn draw_probability(game_result: Option<GameResultForUs>) -> Score {
+    if game_result == Some(GameResultForUs::Draw) {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+/// The game result of a terminal position, from the perspective of the side to move
+pub fn terminal_result<const S: usize>(position: &Position<S>) -> Option<GameResultForUs> {
+    position
+        .game_result()
+        .map(|game_result| GameResultForUs::new(game_result, position.side_to_move()))
+}
+
 /// A game result from one side's perspective
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum GameResultForUs {
@@ -299,6 +848,30 @@ impl ops::Not for GameResultForUs {
 }
 
 impl GameResultForUs {
+    /// The result of the game from `side`'s perspective
+    pub fn new(game_result: GameResult, side: Color) -> Self {
+        match (game_result, side) {
+            (GameResult::Draw, _) => GameResultForUs::Draw,
+            (GameResult::WhiteWin, Color::Black) => GameResultForUs::Loss,
+            (GameResult::BlackWin, Color::White) => GameResultForUs::Loss,
+            (GameResult::WhiteWin, Color::White) => GameResultForUs::Win,
+            (GameResult::BlackWin, Color::Black) => GameResultForUs::Win,
+        }
+    }
+
+    /// The result of the game, where `self` is `side`'s perspective
+    pub fn to_game_result(self, side: Color) -> GameResult {
+        match (self, side) {
+            (GameResultForUs::Draw, _) => GameResult::Draw,
+            (GameResultForUs::Win, Color::White) | (GameResultForUs::Loss, Color::Black) => {
+                GameResult::WhiteWin
+            }
+            (GameResultForUs::Win, Color::Black) | (GameResultForUs::Loss, Color::White) => {
+                GameResult::BlackWin
+            }
+        }
+    }
+
     fn score(self) -> Score {
         match self {
             GameResultForUs::Win => 1.0,
@@ -339,20 +912,59 @@ impl<'a> Iterator for Pv<'a> {
 /// tending towards the highest-scoring moves, but with a random component
 /// If temperature is low (e.g. 0.1), it tends to choose the highest-scoring move
 /// If temperature is 1.0, it chooses a move proportional to its score
+/// If temperature is 0.0, it always chooses the highest-scoring move
 pub fn best_move<R: Rng>(rng: &mut R, temperature: f64, move_scores: &[(Move, Score)]) -> Move {
-    let mut move_probabilities = vec![];
-    let mut cumulative_prob = 0.0;
+    let move_probabilities = apply_temperature(temperature, move_scores);
 
-    for (mv, individual_prob) in move_scores.iter() {
-        cumulative_prob += (*individual_prob as f64).powf(1.0 / temperature);
-        move_probabilities.push((mv, cumulative_prob));
-    }
-
-    let p = rng.gen_range(0.0..cumulative_prob);
-    for (mv, cumulative_prob) in move_probabilities {
+    let p = rng.gen_range(0.0..1.0);
+    let mut cumulative_prob = 0.0;
+    for (mv, individual_prob) in move_probabilities.iter() {
+        cumulative_prob += *individual_prob;
         if cumulative_prob > p {
             return mv.clone();
         }
     }
-    unreachable!()
+    // Rounding errors may leave the cumulative probability slightly below 1.0
+    move_probabilities
+        .iter()
+        .rev()
+        .find(|(_, prob)| *prob > 0.0)
+        .unwrap()
+        .0
+        .clone()
+}
+
+/// Raise every score to the power of `1 / temperature`, and normalize them to sum to 1.0.
+/// A temperature of 1.0 keeps the scores proportional, and lower temperatures favour the highest-scoring moves.
+/// A temperature of 0.0 gives the highest-scoring move all the probability
+pub fn apply_temperature(temperature: f64, move_scores: &[(Move, Score)]) -> Vec<(Move, Score)> {
+    let max_score = move_scores
+        .iter()
+        .map(|(_, score)| *score)
+        .fold(0.0, Score::max) as f64;
+    assert!(max_score > 0.0, "No move has a positive score");
+
+    let weights: Vec<f64> = if temperature <= 0.0 {
+        let (best_index, _) = move_scores
+            .iter()
+            .enumerate()
+            .find(|(_, (_, score))| *score as f64 == max_score)
+            .unwrap();
+        (0..move_scores.len())
+            .map(|i| if i == best_index { 1.0 } else { 0.0 })
+            .collect()
+    } else {
+        // Divide by the highest score first, so that low temperatures cannot underflow every weight to zero
+        move_scores
+            .iter()
+            .map(|(_, score)| (*score as f64 / max_score).powf(1.0 / temperature))
+            .collect()
+    };
+    let weight_sum: f64 = weights.iter().sum();
+
+    move_scores
+        .iter()
+        .zip(weights)
+        .map(|((mv, _), weight)| (mv.clone(), (weight / weight_sum) as Score))
+        .collect()
 }
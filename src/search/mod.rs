@@ -2,29 +2,59 @@
 //!
 //! This implementation does not use full Monte Carlo rollouts, relying on a heuristic evaluation when expanding new nodes instead.
 
-use std::{mem, time};
+use std::sync::atomic::{AtomicBool, AtomicU16, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::{mem, thread, time};
+
+use board_game_traits::{Color, GameResult, Position as PositionTrait};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use rayon::prelude::*;
 
 use crate::position::Move;
 use crate::position::{Position, TunableBoard};
 use crate::position::{Role, Square};
-pub use crate::search::mcts_core::best_move;
-use crate::search::mcts_core::{TempVectors, Tree};
+use crate::ptn::{Game, PtnMove};
+pub use crate::search::evaluator::{EnsembleEvaluator, Evaluator};
+pub(crate) use crate::search::mcts_core::evaluate_leaf;
+pub use crate::search::mcts_core::{apply_temperature, best_move, GameResultForUs};
+use crate::search::mcts_core::{Descent, SharedTree, TempVectors, Tree};
 
 use self::mcts_core::{Pv, TreeEdge};
 
 /// This module contains the public-facing convenience API for the search.
 /// The implementation itself in in mcts_core.
+mod evaluator;
 mod mcts_core;
+mod tree_file;
 
 #[derive(Clone, PartialEq, Debug)]
 pub struct MctsSetting<const S: usize> {
     value_params: Vec<f32>,
     policy_params: Vec<f32>,
-    search_params: Vec<Score>,
+    c_puct_init: Score,
+    c_puct_base: Score,
+    initial_mean_action_value: Score,
+    virtual_loss: u32,
+    fpu: Option<Score>,
+    max_nodes: Option<u64>,
     dirichlet: Option<f32>,
+    dirichlet_seed: Option<u64>,
+    dirichlet_decay: f32,
+    wall_cap_bonus: Option<Score>,
     excluded_moves: Vec<Move>,
     rollout_depth: u16,
     rollout_temperature: f64,
+    ensemble: Option<EnsembleEvaluator<S>>,
+    symmetrized_eval: bool,
+    contempt: Score,
+    /// The side the contempt applies to. This is the side to move at the root of the search.
+    contempt_color: Color,
+    weak_move_probability: f64,
+    weak_move_max_rank: usize,
+    flat_opening_plies: usize,
+    deterministic: bool,
+    min_visit_fraction: Option<f32>,
 }
 
 impl<const S: usize> Default for MctsSetting<S> {
@@ -32,36 +62,164 @@ impl<const S: usize> Default for MctsSetting<S> {
         MctsSetting {
             value_params: Vec::from(<Position<S>>::value_params()),
             policy_params: Vec::from(<Position<S>>::policy_params()),
-            search_params: vec![1.43, 2800.0, 0.61],
+            c_puct_init: 1.43,
+            c_puct_base: 2800.0,
+            initial_mean_action_value: 0.61,
+            virtual_loss: 3,
+            fpu: None,
+            max_nodes: None,
             dirichlet: None,
+            dirichlet_seed: None,
+            dirichlet_decay: 1.0,
+            wall_cap_bonus: None,
             excluded_moves: vec![],
             rollout_depth: 0,
             rollout_temperature: 0.25,
+            ensemble: None,
+            symmetrized_eval: false,
+            contempt: 0.0,
+            contempt_color: Color::White,
+            weak_move_probability: 0.0,
+            weak_move_max_rank: 1,
+            flat_opening_plies: 0,
+            deterministic: false,
+            min_visit_fraction: None,
         }
     }
 }
 
 impl<const N: usize> MctsSetting<N> {
-    pub fn add_value_params(mut self, value_params: Vec<f32>) -> Self {
+    /// Set the parameters of the value function.
+    /// Panics if the number of parameters is wrong for the board size.
+    pub fn with_value_params(mut self, value_params: Vec<f32>) -> Self {
+        assert_eq!(
+            value_params.len(),
+            <Position<N>>::value_params().len(),
+            "Wrong number of value parameters for {}s",
+            N
+        );
         self.value_params = value_params;
         self
     }
 
-    pub fn add_policy_params(mut self, policy_params: Vec<f32>) -> Self {
+    /// Set the parameters of the policy function.
+    /// Panics if the number of parameters is wrong for the board size.
+    pub fn with_policy_params(mut self, policy_params: Vec<f32>) -> Self {
+        assert_eq!(
+            policy_params.len(),
+            <Position<N>>::policy_params().len(),
+            "Wrong number of policy parameters for {}s",
+            N
+        );
         self.policy_params = policy_params;
         self
     }
 
+    /// Set both the value and policy parameters. Equivalent to chaining `with_value_params` and `with_policy_params`.
+    pub fn with_eval_params(self, value_params: Vec<f32>, policy_params: Vec<f32>) -> Self {
+        self.with_value_params(value_params)
+            .with_policy_params(policy_params)
+    }
+
+    pub fn add_value_params(self, value_params: Vec<f32>) -> Self {
+        self.with_value_params(value_params)
+    }
+
+    pub fn add_policy_params(self, policy_params: Vec<f32>) -> Self {
+        self.with_policy_params(policy_params)
+    }
+
+    /// Set the search parameters positionally, in the order `c_puct_init`, `c_puct_base`, `initial_mean_action_value` and `virtual_loss`.
+    /// Parameters that are not given keep their current values
+    #[deprecated(
+        note = "Use `with_c_puct`, `with_initial_mean_action_value` and `with_virtual_loss` instead"
+    )]
     pub fn add_search_params(mut self, search_params: Vec<f32>) -> Self {
-        self.search_params = search_params;
+        if let [c_puct_init, c_puct_base, ..] = search_params[..] {
+            self = self.with_c_puct(c_puct_init, c_puct_base);
+        }
+        if let Some(initial_mean_action_value) = search_params.get(2) {
+            self.initial_mean_action_value = *initial_mean_action_value;
+        }
+        if let Some(virtual_loss) = search_params.get(3) {
+            self.virtual_loss = (virtual_loss.round() as u32).max(1);
+        }
+        self
+    }
+
+    /// Set the exploration constant of the search. It starts at `init`, and grows logarithmically with the node's visits, doubling after roughly `base` visits
+    pub fn with_c_puct(mut self, init: Score, base: Score) -> Self {
+        self.c_puct_init = init;
+        self.c_puct_base = base;
+        self
+    }
+
+    /// The initial value of unvisited nodes, from the perspective of the side to move in the node
+    pub fn with_initial_mean_action_value(mut self, initial_mean_action_value: Score) -> Self {
+        self.initial_mean_action_value = initial_mean_action_value;
+        self
+    }
+
+    /// Use first-play urgency: Score unvisited children as the parent's value minus `reduction`, instead of by `initial_mean_action_value`.
+    /// Larger reductions make the search less eager to try new moves
+    pub fn with_fpu(mut self, reduction: Score) -> Self {
+        self.fpu = Some(reduction);
+        self
+    }
+
+    /// Limit the search tree to `max_nodes` nodes, to bound its memory usage on long searches.
+    /// Once the tree is full, the search stops expanding new leaves, and only revisits nodes that are already in the tree.
+    /// Each node uses roughly `node_mem_usage()` bytes, plus `edge_mem_usage()` bytes for each of its legal moves
+    pub fn with_max_nodes(mut self, max_nodes: u64) -> Self {
+        assert!(
+            max_nodes > 0,
+            "The tree must have room for at least the root"
+        );
+        self.max_nodes = Some(max_nodes);
+        self
+    }
+
+    /// Set the number of lost visits applied by each thread in a parallel search. Must be at least 1
+    pub fn with_virtual_loss(mut self, virtual_loss: u32) -> Self {
+        assert!(virtual_loss > 0, "Virtual loss must be at least 1");
+        self.virtual_loss = virtual_loss;
         self
     }
 
     pub fn add_dirichlet(mut self, alpha: f32) -> Self {
         self.dirichlet = Some(alpha);
+        self.dirichlet_seed = None;
         self
     }
 
+    /// Same as `add_dirichlet`, but the noise is generated from `seed`, instead of from the thread's rng.
+    /// Searches with the same seed and settings get the same noise, and are reproducible
+    pub fn add_dirichlet_seeded(mut self, alpha: f32, seed: u64) -> Self {
+        self.dirichlet = Some(alpha);
+        self.dirichlet_seed = Some(seed);
+        self
+    }
+
+    /// Reduce the weight of the Dirichlet noise by a factor of `decay` for every ply played, for more diverse openings without weakening the endgame.
+    /// Defaults to 1.0, which gives the same noise throughout the game
+    pub fn with_dirichlet_decay(mut self, decay: f32) -> Self {
+        self.dirichlet_decay = decay;
+        self
+    }
+
+    /// Add `bonus` to the policy of every wall and capstone placement at the root, before renormalizing.
+    /// These moves are rarely played, and this can be used in self-play to generate more diverse training data for them
+    pub fn add_wall_cap_bonus(mut self, bonus: Score) -> Self {
+        assert!(bonus >= 0.0, "Wall and cap bonus cannot be negative");
+        self.wall_cap_bonus = Some(bonus);
+        self
+    }
+
+    /// The weight of the Dirichlet noise in the root's policy, after `half_moves_played` plies
+    pub fn dirichlet_epsilon(&self, half_moves_played: usize) -> f32 {
+        0.25 * self.dirichlet_decay.powi(half_moves_played as i32)
+    }
+
     pub fn exclude_moves(mut self, excluded_moves: Vec<Move>) -> Self {
         self.excluded_moves = excluded_moves;
         self
@@ -80,16 +238,127 @@ impl<const N: usize> MctsSetting<N> {
         self
     }
 
+    /// Evaluate new nodes with an ensemble of parameter sets, instead of the value and policy parameters
+    pub fn with_ensemble(mut self, ensemble: EnsembleEvaluator<N>) -> Self {
+        self.ensemble = Some(ensemble);
+        self
+    }
+
+    /// Average the static evaluation over all symmetries of the board. Slower, but less noisy.
+    /// Ignored if an ensemble is set
+    pub fn with_symmetrized_eval(mut self, symmetrized_eval: bool) -> Self {
+        self.symmetrized_eval = symmetrized_eval;
+        self
+    }
+
+    /// Score draws as `0.5 - contempt` for the side to move at the root, instead of 0.5.
+    /// Higher values make the engine avoid draws. Panics if `contempt` is not between 0.0 and 0.5.
+    pub fn with_contempt(mut self, contempt: Score) -> Self {
+        assert!(
+            (0.0..=0.5).contains(&contempt),
+            "Contempt must be between 0.0 and 0.5, was {}",
+            contempt
+        );
+        self.contempt = contempt;
+        self
+    }
+
+    /// In self-play games, play a weaker move with probability `probability`, to create more varied games.
+    /// The weaker move is chosen uniformly among the 2nd to the `max_rank`th best moves.
+    /// Only used when selecting moves in self-play, not by the search itself.
+    pub fn with_weak_moves(mut self, probability: f64, max_rank: usize) -> Self {
+        self.weak_move_probability = probability;
+        self.weak_move_max_rank = max_rank;
+        self
+    }
+
+    /// Make single-threaded searches fully reproducible, for tests and benchmarks.
+    /// Disables Dirichlet noise, even if it is set before or after this, and seeds the rollouts from the position they start in.
+    /// Ties between children are always broken by move generation order, so the same settings give bit-identical trees.
+    /// Parallel searches are still not reproducible, because the order threads back up their results in is not.
+    pub fn with_deterministic_select(mut self) -> Self {
+        self.deterministic = true;
+        self
+    }
+
+    pub fn deterministic(&self) -> bool {
+        self.deterministic
+    }
+
+    /// Choose the best move by value instead of by visits, among the root moves with at least `fraction` as many visits as the most visited move.
+    /// This avoids playing a move with few visits and a lucky score in low-node searches.
+    /// By default, the most visited move is always chosen
+    pub fn with_min_visit_fraction(mut self, fraction: f32) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&fraction),
+            "Minimum visit fraction must be between 0 and 1, was {}",
+            fraction
+        );
+        self.min_visit_fraction = Some(fraction);
+        self
+    }
+
+    /// Only consider flat placements for the first `plies` plies of the game, for a faster and more human-like opening.
+    /// Other moves are still considered if there are no flat placements. Defaults to 0
+    pub fn with_flat_opening(mut self, plies: usize) -> Self {
+        self.flat_opening_plies = plies;
+        self
+    }
+
+    pub fn weak_move_probability(&self) -> f64 {
+        self.weak_move_probability
+    }
+
+    pub fn weak_move_max_rank(&self) -> usize {
+        self.weak_move_max_rank
+    }
+
+    /// The score of a drawn terminal node, from the perspective of `side_to_move`
+    pub(crate) fn draw_score(&self, side_to_move: Color) -> Score {
+        if side_to_move == self.contempt_color {
+            0.5 - self.contempt
+        } else {
+            0.5 + self.contempt
+        }
+    }
+
     pub fn c_puct_init(&self) -> Score {
-        self.search_params[0]
+        self.c_puct_init
     }
 
     pub fn c_puct_base(&self) -> Score {
-        self.search_params[1]
+        self.c_puct_base
     }
 
     pub fn initial_mean_action_value(&self) -> Score {
-        self.search_params[2]
+        self.initial_mean_action_value
+    }
+
+    pub fn max_nodes(&self) -> Option<u64> {
+        self.max_nodes
+    }
+
+    /// Whether a tree with `allocated_nodes` nodes has room for another one
+    pub(crate) fn can_expand(&self, allocated_nodes: u64) -> bool {
+        self.max_nodes
+            .is_none_or(|max_nodes| allocated_nodes < max_nodes)
+    }
+
+    /// The first-play urgency reduction, if set
+    pub fn fpu(&self) -> Option<Score> {
+        self.fpu
+    }
+
+    /// The action value of unvisited children of a node with value `parent_value`, if first-play urgency is used.
+    /// Both values are from the perspective of the side to move in the node
+    pub(crate) fn fpu_value(&self, parent_value: Score) -> Option<Score> {
+        self.fpu
+            .map(|reduction| (parent_value - reduction).max(0.0))
+    }
+
+    /// The number of lost visits added to every edge on a thread's path in a parallel search, until the thread backs up its result
+    pub fn virtual_loss(&self) -> u32 {
+        self.virtual_loss
     }
 }
 
@@ -98,12 +367,21 @@ pub type Score = f32;
 
 /// Abstract representation of a Monte Carlo Search Tree.
 /// Gives more fine-grained control of the search process compared to using the `mcts` function.
+///
+/// The tree is `Send`, so it can be moved to a background thread to ponder on the opponent's time,
+/// by calling `select()` in a loop until the opponent moves.
+/// Once the tree is moved back, `advance_root` continues the search from the opponent's move,
+/// keeping the pondering results if the move was explored.
 #[derive(Clone, PartialEq, Debug)]
 pub struct MonteCarloTree<const S: usize> {
     edge: TreeEdge, // A virtual edge to the first node, with fake move and heuristic score
     position: Position<S>,
     settings: MctsSetting<S>,
     temp_vectors: TempVectors,
+    /// The deepest any search iteration has reached, counted in plies from the root
+    max_depth: u16,
+    /// The number of nodes allocated in the tree
+    allocated_nodes: u64,
 }
 
 impl<const S: usize> MonteCarloTree<S> {
@@ -115,10 +393,13 @@ impl<const S: usize> MonteCarloTree<S> {
                 mean_action_value: 0.0,
                 visits: 0,
                 heuristic_score: 0.0,
+                proven_result: None,
             },
             position,
             settings: MctsSetting::default(),
             temp_vectors: TempVectors::new::<S>(),
+            max_depth: 0,
+            allocated_nodes: 0,
         }
     }
 
@@ -131,16 +412,37 @@ impl<const S: usize> MonteCarloTree<S> {
                 mean_action_value: 0.0,
                 visits: 0,
                 heuristic_score: 0.0,
+                proven_result: None,
             },
             position,
             settings: settings.clone(),
             temp_vectors: TempVectors::new::<S>(),
+            max_depth: 0,
+            allocated_nodes: 0,
         };
 
-        if let Some(alpha) = tree.settings.dirichlet {
+        tree.settings.contempt_color = tree.position.side_to_move();
+
+        if let (Some(alpha), false) = (tree.settings.dirichlet, tree.settings.deterministic) {
+            tree.select();
+            tree.select();
+            let epsilon = tree
+                .settings
+                .dirichlet_epsilon(tree.position.half_moves_played());
+            let root = tree.edge.child.as_mut().unwrap();
+            match tree.settings.dirichlet_seed {
+                Some(seed) => {
+                    root.apply_dirichlet(epsilon, alpha, &mut StdRng::seed_from_u64(seed))
+                }
+                None => root.apply_dirichlet(epsilon, alpha, &mut rand::thread_rng()),
+            }
+        }
+
+        if let Some(bonus) = tree.settings.wall_cap_bonus {
             tree.select();
             tree.select();
-            (*tree.edge.child.as_mut().unwrap()).apply_dirichlet(0.25, alpha);
+            let root = tree.edge.child.as_mut().unwrap();
+            root.apply_wall_cap_bonus(bonus);
         }
 
         if !tree.settings.excluded_moves.is_empty() {
@@ -156,7 +458,10 @@ impl<const S: usize> MonteCarloTree<S> {
                 .filter(|edge| !settings.excluded_moves.contains(&edge.mv))
                 .cloned()
                 .collect();
-            tree.edge.child.as_mut().unwrap().children = filtered_edges.into_boxed_slice();
+            let root = tree.edge.child.as_mut().unwrap();
+            root.children = filtered_edges.into_boxed_slice();
+            tree.edge.proven_result = root.proven_result();
+            tree.allocated_nodes = root.num_nodes();
         }
 
         tree
@@ -164,35 +469,290 @@ impl<const S: usize> MonteCarloTree<S> {
 
     /// Run one iteration of MCTS
     pub fn select(&mut self) -> f32 {
-        self.edge.select::<S>(
+        let mut depth = 0;
+        let (score, _) = self.edge.select::<S>(
             &mut self.position.clone(),
             &self.settings,
             &mut self.temp_vectors,
-        )
+            &mut depth,
+            &mut self.allocated_nodes,
+        );
+        self.max_depth = self.max_depth.max(depth);
+        score
+    }
+
+    /// Run `iterations` iterations of MCTS on `threads` threads, all searching this tree.
+    /// Each thread applies a virtual loss to the edges it is searching, so that the threads tend to explore different lines.
+    /// Every node has its own lock, so threads only wait for each other when they visit the same node at the same time.
+    /// The virtual losses are removed once the search finishes, but the result is not reproducible between runs.
+    pub fn select_parallel(&mut self, iterations: u64, threads: usize) {
+        let tree = SharedTree::new(&mut self.edge);
+        let iterations_started = AtomicU64::new(0);
+        let max_depth = AtomicU16::new(self.max_depth);
+        let allocated_nodes = AtomicU64::new(self.allocated_nodes);
+        let position = &self.position;
+        let settings = &self.settings;
+        let virtual_loss = settings.virtual_loss();
+
+        (0..threads.max(1)).into_par_iter().for_each(|_| {
+            let mut temp_vectors = TempVectors::new::<S>();
+            let mut path = vec![];
+            while iterations_started.fetch_add(1, Ordering::Relaxed) < iterations {
+                loop {
+                    let mut position = position.clone();
+                    // Threads expanding at the same time may overshoot the maximum by a few nodes
+                    let can_expand = settings.can_expand(allocated_nodes.load(Ordering::Relaxed));
+                    let descent = tree.select(
+                        &mut position,
+                        settings,
+                        &mut temp_vectors,
+                        &mut path,
+                        virtual_loss,
+                        can_expand,
+                    );
+                    let eval = match descent {
+                        Descent::Collision => {
+                            thread::yield_now();
+                            continue;
+                        }
+                        Descent::Terminal | Descent::Full => None,
+                        Descent::Expand => {
+                            allocated_nodes.fetch_add(1, Ordering::Relaxed);
+                            let (eval, is_terminal) = mcts_core::rollout(
+                                &mut position,
+                                settings,
+                                settings.rollout_depth,
+                                &mut temp_vectors,
+                            );
+                            let game_result = if is_terminal {
+                                mcts_core::terminal_result(&position)
+                            } else {
+                                None
+                            };
+                            Some((eval, game_result))
+                        }
+                    };
+                    tree.backup(&path, eval, virtual_loss);
+                    // The path includes the root edge
+                    max_depth.fetch_max(path.len() as u16 - 1, Ordering::Relaxed);
+                    break;
+                }
+            }
+        });
+        self.max_depth = max_depth.into_inner();
+        self.allocated_nodes = allocated_nodes.into_inner();
     }
 
     /// Returns the best move, and its score (as winning probability) from the perspective of the side to move
     /// Panics if no search iterations have been run
     pub fn best_move(&self) -> (Move, f32) {
-        self.edge
-            .child
-            .as_ref()
-            .unwrap()
-            .children
+        let edge = self.best_edge();
+        (edge.mv.clone(), 1.0 - edge.mean_action_value)
+    }
+
+    /// The most visited root move, or the highest scoring move with enough visits if a minimum visit fraction is set
+    fn best_edge(&self) -> &TreeEdge {
+        let children = &self.edge.child.as_ref().unwrap().children;
+        let most_visited = children
             .iter()
             .max_by_key(|edge| edge.visits)
-            .map(|edge| (edge.mv.clone(), 1.0 - edge.mean_action_value))
-            .unwrap_or_else(|| panic!("Couldn't find best move"))
+            .unwrap_or_else(|| panic!("Couldn't find best move"));
+        match self.settings.min_visit_fraction {
+            None => most_visited,
+            Some(fraction) => {
+                let min_visits = most_visited.visits as f32 * fraction;
+                children
+                    .iter()
+                    .filter(|edge| edge.visits > 0 && edge.visits as f32 >= min_visits)
+                    .min_by(|edge1, edge2| {
+                        edge1
+                            .mean_action_value
+                            .partial_cmp(&edge2.mean_action_value)
+                            .unwrap()
+                            .then_with(|| edge2.visits.cmp(&edge1.visits))
+                    })
+                    .unwrap_or(most_visited)
+            }
+        }
+    }
+
+    /// Returns the best move, and its win, draw and loss probabilities from the perspective of the side to move.
+    /// The scalar score from `best_move` is `win + 0.5 * draw`, with draws adjusted for contempt.
+    /// Only terminal draws found by the search are counted as draws.
+    /// Panics if no search iterations have been run
+    pub fn best_move_wdl(&self) -> (Move, [f32; 3]) {
+        let edge = self.best_edge();
+        let score = 1.0 - edge.mean_action_value;
+        let draw = edge
+            .child
+            .as_ref()
+            .map(|child| (child.total_draws / edge.visits as f64) as f32)
+            .unwrap_or_default();
+        let win = (score - self.settings.draw_score(self.position.side_to_move()) * draw).max(0.0);
+        let loss = (1.0 - win - draw).max(0.0);
+        (edge.mv.clone(), [win, draw, loss])
+    }
+
+    /// Returns the best move, and its score (as winning probability) from White's perspective
+    /// Panics if no search iterations have been run
+    pub fn best_move_white_perspective(&self) -> (Move, Score) {
+        let (best_move, score) = self.best_move();
+        match self.position.side_to_move() {
+            Color::White => (best_move, score),
+            Color::Black => (best_move, 1.0 - score),
+        }
     }
 
     fn children(&self) -> &[TreeEdge] {
         &self.edge.child.as_ref().unwrap().children
     }
 
+    /// Returns every move at the root, with its score for the side to move and its number of visits
+    /// Panics if no search iterations have been run
+    pub fn root_moves(&self) -> Vec<(Move, Score, u64)> {
+        self.children()
+            .iter()
+            .map(|edge| (edge.mv.clone(), 1.0 - edge.mean_action_value, edge.visits))
+            .collect()
+    }
+
+    /// Returns the `k` most visited root moves, with their scores for the side to move and their number of visits.
+    /// The moves are sorted by visits, most visited first.
+    /// Panics if no search iterations have been run
+    pub fn top_moves(&self, k: usize) -> Vec<(Move, Score, u64)> {
+        let mut moves = self.root_moves();
+        moves.sort_by_key(|(_, _, visits)| std::cmp::Reverse(*visits));
+        moves.truncate(k);
+        moves
+    }
+
+    /// Returns the fraction of the root's visits that went to each move, and the winning probability of the root position for the side to move.
+    /// This is the training target for the policy and value parameters.
+    /// Panics if no search iterations have been run
+    pub fn training_target(&self) -> (Vec<(Move, f32)>, Score) {
+        let child_visits: u64 = self.children().iter().map(|edge| edge.visits).sum();
+        let move_scores = self
+            .children()
+            .iter()
+            .map(|edge| (edge.mv.clone(), edge.visits as f32 / child_visits as f32))
+            .collect();
+        (move_scores, self.mean_action_value())
+    }
+
+    /// Returns every root move that has been visited, with its fraction of the root's visits, as a policy target for supervised training.
+    /// If `include_unvisited` is set, every legal move in the root position is returned in move generation order instead,
+    /// with 0.0 for moves that were not visited, or not searched at all, like excluded moves.
+    /// Panics if no search iterations have been run
+    pub fn visit_distribution(&self, include_unvisited: bool) -> Vec<(Move, f32)> {
+        let child_visits: u64 = self.children().iter().map(|edge| edge.visits).sum();
+        let visit_share = |edge: &TreeEdge| edge.visits as f32 / child_visits.max(1) as f32;
+        if include_unvisited {
+            let mut legal_moves = vec![];
+            self.position.generate_moves(&mut legal_moves);
+            legal_moves
+                .into_iter()
+                .map(|mv| {
+                    let share = self
+                        .children()
+                        .iter()
+                        .find(|edge| edge.mv == mv)
+                        .map_or(0.0, visit_share);
+                    (mv, share)
+                })
+                .collect()
+        } else {
+            self.children()
+                .iter()
+                .filter(|edge| edge.visits > 0)
+                .map(|edge| (edge.mv.clone(), visit_share(edge)))
+                .collect()
+        }
+    }
+
+    /// Remove every root move except `mv`, freeing their subtrees.
+    /// The root is kept, and the statistics of `mv` and its subtree are preserved.
+    /// Panics if no search iterations have been run, or if `mv` is not a root move
+    pub fn prune_to_move(&mut self, mv: &Move) {
+        let root = self.edge.child.as_mut().unwrap();
+        let index = root
+            .children
+            .iter()
+            .position(|edge| edge.mv == *mv)
+            .unwrap_or_else(|| panic!("{} is not a root move", mv.to_string::<S>()));
+        let kept_edge = mem::take(&mut root.children).into_vec().swap_remove(index);
+        root.children = vec![kept_edge].into_boxed_slice();
+        self.edge.proven_result = root.proven_result();
+        self.allocated_nodes = root.num_nodes();
+    }
+
+    /// Play `mv` at the root, and make its subtree the new root of the tree. All other subtrees are discarded.
+    /// The visits and values of the new root's subtree are preserved, so that the search can continue where it left off.
+    /// If `mv` was never expanded by the search, the tree is replaced by a fresh tree.
+    /// The contempt still applies to the side to move at the original root.
+    /// `mv` must be a legal move in the root position
+    pub fn advance_root(&mut self, mv: &Move) {
+        let child_edge = self.edge.child.as_mut().and_then(|root| {
+            let index = root.children.iter().position(|edge| edge.mv == *mv)?;
+            let edge = mem::take(&mut root.children).into_vec().swap_remove(index);
+            Some(edge).filter(|edge| edge.child.is_some())
+        });
+        let mut position = self.position.clone();
+        position.do_move(mv.clone());
+
+        match child_edge {
+            Some(edge) => {
+                self.edge = edge;
+                self.position = position;
+                self.max_depth = self.max_depth.saturating_sub(1);
+                self.allocated_nodes = self.edge.child.as_ref().unwrap().num_nodes();
+            }
+            None => {
+                let contempt_color = self.settings.contempt_color;
+                *self = MonteCarloTree::with_settings(position, self.settings.clone());
+                self.settings.contempt_color = contempt_color;
+            }
+        }
+    }
+
+    /// Memory used by the search tree, in bytes
+    pub fn mem_usage(&self) -> usize {
+        mem::size_of::<TreeEdge>()
+            + self
+                .edge
+                .child
+                .as_ref()
+                .map_or(0, |child| child.mem_usage())
+    }
+
     pub fn pv(&self) -> impl Iterator<Item = Move> + '_ {
         Pv::new(self.edge.child.as_ref().unwrap())
     }
 
+    /// The principal variation as a game starting from the root position, for example to save it as PTN.
+    /// The game has a result if the principal variation ends the game.
+    /// Panics if no search iterations have been run
+    pub fn pv_as_game(&self) -> Game<Position<S>> {
+        let mut position = self.position.clone();
+        let moves = self
+            .pv()
+            .map(|mv| {
+                position.do_move(mv.clone());
+                PtnMove {
+                    mv,
+                    annotations: vec![],
+                    comment: String::new(),
+                    clock: None,
+                }
+            })
+            .collect();
+        Game {
+            start_position: self.position.clone(),
+            moves,
+            game_result: position.game_result(),
+            tags: vec![],
+        }
+    }
+
     /// Print human-readable information of the search's progress.
     pub fn print_info(&self) {
         let mut best_children: Vec<&TreeEdge> = self.children().iter().collect();
@@ -209,7 +769,7 @@ impl<const S: usize> MonteCarloTree<S> {
             println!(
                 "Move {}: {} visits, {:.2}% mean action value, {:.2}% static score, {:.3} exploration value, pv {}",
                 edge.mv.to_string::<S>(), edge.visits, edge.mean_action_value * 100.0, edge.heuristic_score * 100.0,
-                edge.exploration_value((self.visits() as Score).sqrt(), dynamic_cpuct),
+                edge.exploration_value((self.visits() as Score).sqrt(), dynamic_cpuct, self.settings.fpu_value(self.edge.mean_action_value)),
                 Pv::new(edge.child.as_ref().unwrap()).map(|mv| mv.to_string::<S>() + " ").collect::<String>()
             )
         });
@@ -219,20 +779,207 @@ impl<const S: usize> MonteCarloTree<S> {
         self.edge.visits
     }
 
+    /// The deepest any search iteration has reached, in plies from the root
+    pub fn max_depth(&self) -> u16 {
+        self.max_depth
+    }
+
+    /// The number of nodes allocated in the tree. Capped by `MctsSetting::with_max_nodes`
+    pub fn allocated_nodes(&self) -> u64 {
+        self.allocated_nodes
+    }
+
+    /// The game result, if the search has proven it, and the number of plies until the game ends.
+    /// Wins are proven with the shortest distance found, losses with the longest
+    pub fn proven_result(&self) -> Option<(GameResult, u16)> {
+        self.edge.proven_result.map(|(game_result, plies)| {
+            (
+                game_result.to_game_result(self.position.side_to_move()),
+                plies,
+            )
+        })
+    }
+
     pub fn mean_action_value(&self) -> Score {
         self.edge.mean_action_value
     }
 }
 
+/// The minimum number of nodes the search needs to produce a move.
+/// The first node only expands the root, and the second one visits a child.
+pub const MIN_MCTS_NODES: u64 = 2;
+
 /// The simplest way to use the mcts module. Run Monte Carlo Tree Search for `nodes` nodes, returning the best move, and its estimated winning probability for the side to move.
+/// At least `MIN_MCTS_NODES` nodes are always searched, even if `nodes` is lower.
 pub fn mcts<const S: usize>(position: Position<S>, nodes: u64) -> (Move, Score) {
+    let (mv, score, _) = mcts_with_node_count(position, nodes);
+    (mv, score)
+}
+
+/// Like `mcts`, but also returns the number of nodes actually searched.
+/// This is `nodes`, or `MIN_MCTS_NODES` if fewer nodes were requested.
+pub fn mcts_with_node_count<const S: usize>(
+    position: Position<S>,
+    nodes: u64,
+) -> (Move, Score, u64) {
     let mut tree = MonteCarloTree::new(position);
 
-    for _ in 0..nodes.max(2) {
+    let nodes = nodes.max(MIN_MCTS_NODES);
+    for _ in 0..nodes {
         tree.select();
     }
     let (mv, score) = tree.best_move();
-    (mv, score)
+    (mv, score, nodes)
+}
+
+/// Statistics about the efficiency of a search
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct SearchStats {
+    /// Total visits at the root of the tree
+    pub visits: u64,
+    pub elapsed: time::Duration,
+    pub nodes_per_second: f64,
+    /// The deepest any search iteration reached, in plies from the root
+    pub max_depth: u16,
+}
+
+/// Run Monte Carlo Tree Search for `nodes` nodes, like `mcts`, and also return statistics about the search.
+pub fn mcts_with_stats<const S: usize>(
+    position: Position<S>,
+    nodes: u64,
+) -> (Move, Score, SearchStats) {
+    let start_time = time::Instant::now();
+    let mut tree = MonteCarloTree::new(position);
+    for _ in 0..nodes.max(MIN_MCTS_NODES) {
+        tree.select();
+    }
+    let (mv, score) = tree.best_move();
+    let elapsed = start_time.elapsed();
+    let stats = SearchStats {
+        visits: tree.visits(),
+        elapsed,
+        nodes_per_second: tree.visits() as f64 / elapsed.as_secs_f64(),
+        max_depth: tree.max_depth(),
+    };
+    (mv, score, stats)
+}
+
+/// The number of search iterations between each call to the callback in `mcts_with_callback`
+pub const CALLBACK_INTERVAL: u64 = 1000;
+
+/// Run Monte Carlo Tree Search for `nodes` nodes, like `mcts`, calling `callback` with the tree every `CALLBACK_INTERVAL` nodes.
+/// The callback is also called once the search finishes, if it was not called on the last node.
+/// This lets the caller show the progress of the search, for example with `visits`, `best_move` and `pv`.
+pub fn mcts_with_callback<const S: usize, F: FnMut(&MonteCarloTree<S>)>(
+    position: Position<S>,
+    nodes: u64,
+    mut callback: F,
+) -> (Move, Score) {
+    let mut tree = MonteCarloTree::new(position);
+    let nodes = nodes.max(MIN_MCTS_NODES);
+    for i in 1..=nodes {
+        tree.select();
+        if i % CALLBACK_INTERVAL == 0 || i == nodes {
+            callback(&tree);
+        }
+    }
+    tree.best_move()
+}
+
+/// Run Monte Carlo Tree Search in `threads` independent trees in parallel, searching `nodes / threads` nodes in each.
+/// The visits of each root move are summed across all trees, and the move with the most visits in total is returned,
+/// along with its visit-weighted winning probability for the side to move.
+/// Unlike `mcts`, the result is not reproducible between runs.
+pub fn mcts_parallel<const S: usize>(
+    position: Position<S>,
+    nodes: u64,
+    threads: usize,
+) -> (Move, Score) {
+    let nodes_per_tree = (nodes / threads.max(1) as u64).max(MIN_MCTS_NODES);
+    let root_moves: Vec<Vec<(Move, Score, u64)>> = (0..threads.max(1))
+        .into_par_iter()
+        .map(|_| {
+            let mut tree = MonteCarloTree::new(position.clone());
+            for _ in 0..nodes_per_tree {
+                tree.select();
+            }
+            tree.root_moves()
+        })
+        .collect();
+
+    // Total visits and visit-weighted score sum of each move
+    let mut combined_moves: Vec<(Move, f64, u64)> = vec![];
+    for (mv, score, visits) in root_moves.into_iter().flatten() {
+        match combined_moves
+            .iter_mut()
+            .find(|(combined_move, _, _)| *combined_move == mv)
+        {
+            Some((_, score_sum, total_visits)) => {
+                *score_sum += score as f64 * visits as f64;
+                *total_visits += visits;
+            }
+            None => combined_moves.push((mv, score as f64 * visits as f64, visits)),
+        }
+    }
+
+    let (mv, score_sum, visits) = combined_moves
+        .into_iter()
+        .max_by_key(|(_, _, visits)| *visits)
+        .unwrap_or_else(|| panic!("Couldn't find best move"));
+    (mv, (score_sum / visits as f64) as Score)
+}
+
+/// Run Monte Carlo Tree Search on `threads` threads sharing a single tree, for `nodes` nodes in total.
+/// Uses less memory than `mcts_parallel`, because the threads do not duplicate each other's work.
+/// Unlike `mcts`, the result is not reproducible between runs.
+pub fn mcts_tree_parallel<const S: usize>(
+    position: Position<S>,
+    nodes: u64,
+    threads: usize,
+) -> (Move, Score) {
+    let mut tree = MonteCarloTree::new(position);
+    tree.select_parallel(nodes.max(MIN_MCTS_NODES), threads);
+    tree.best_move()
+}
+
+/// Run Monte Carlo Tree Search for at most `max_nodes` nodes, but stop early once the best move has stayed the same for `stable_checkpoints` consecutive checkpoints.
+/// A checkpoint is taken every `checkpoint_nodes` nodes. Useful for analysis, where obvious positions do not need the full node count.
+/// Returns the best move, its estimated winning probability for the side to move, and the number of nodes searched.
+pub fn mcts_until_stable<const S: usize>(
+    position: Position<S>,
+    max_nodes: u64,
+    checkpoint_nodes: u64,
+    stable_checkpoints: u32,
+) -> (Move, Score, u64) {
+    let mut tree = MonteCarloTree::new(position);
+    let max_nodes = max_nodes.max(MIN_MCTS_NODES);
+    let checkpoint_nodes = checkpoint_nodes.max(1);
+
+    let mut nodes = 0;
+    let mut last_best_move = None;
+    let mut checkpoints_unchanged = 0;
+
+    while nodes < max_nodes {
+        for _ in 0..checkpoint_nodes.min(max_nodes - nodes) {
+            tree.select();
+            nodes += 1;
+        }
+        if nodes < MIN_MCTS_NODES {
+            continue;
+        }
+        let (best_move, _) = tree.best_move();
+        if last_best_move.as_ref() == Some(&best_move) {
+            checkpoints_unchanged += 1;
+            if checkpoints_unchanged >= stable_checkpoints {
+                break;
+            }
+        } else {
+            checkpoints_unchanged = 0;
+            last_best_move = Some(best_move);
+        }
+    }
+    let (mv, score) = tree.best_move();
+    (mv, score, nodes)
 }
 
 /// Play a move, calculating for a maximum duration.
@@ -243,26 +990,79 @@ pub fn play_move_time<const S: usize>(
     max_time: time::Duration,
     settings: MctsSetting<S>,
 ) -> (Move, Score) {
-    let nodes_per_iteration = if settings.rollout_depth == 0 {
+    play_move_time_with_stop(board, max_time, settings, Arc::new(AtomicBool::new(false)))
+}
+
+/// Like `play_move_time`, but also returns the best move found so far once `stop` is set.
+/// The flag is checked between each batch of search iterations, so at least one batch is always searched.
+pub fn play_move_time_with_stop<const S: usize>(
+    board: Position<S>,
+    max_time: time::Duration,
+    settings: MctsSetting<S>,
+    stop: Arc<AtomicBool>,
+) -> (Move, Score) {
+    search_with_limits(board, Some(max_time), None, settings, stop)
+}
+
+/// Play a move, searching until either `max_time` has passed or the tree has `max_nodes` visits, whichever comes first.
+/// Like `play_move_time`, the search may stop early if one move is clearly best.
+/// Returns an error if neither limit is set.
+pub fn play_move_limits<const S: usize>(
+    board: Position<S>,
+    max_time: Option<time::Duration>,
+    max_nodes: Option<u64>,
+    settings: MctsSetting<S>,
+) -> Result<(Move, Score), String> {
+    if max_time.is_none() && max_nodes.is_none() {
+        return Err("Cannot search without a time or node limit".to_string());
+    }
+    Ok(search_with_limits(
+        board,
+        max_time,
+        max_nodes,
+        settings,
+        Arc::new(AtomicBool::new(false)),
+    ))
+}
+
+fn search_with_limits<const S: usize>(
+    board: Position<S>,
+    max_time: Option<time::Duration>,
+    max_nodes: Option<u64>,
+    settings: MctsSetting<S>,
+    stop: Arc<AtomicBool>,
+) -> (Move, Score) {
+    let nodes_per_iteration: u64 = if settings.rollout_depth == 0 {
         200
     } else if settings.rollout_depth < 10 {
         40
     } else {
         20
     };
+    let max_nodes = max_nodes.map(|nodes| nodes.max(MIN_MCTS_NODES));
     let mut tree = MonteCarloTree::with_settings(board, settings);
     let start_time = time::Instant::now();
 
     for i in 1.. {
-        for _ in 0..i * nodes_per_iteration {
+        let iteration_nodes = match max_nodes {
+            Some(max_nodes) => {
+                (i * nodes_per_iteration).min(max_nodes.saturating_sub(tree.visits()))
+            }
+            None => i * nodes_per_iteration,
+        };
+        for _ in 0..iteration_nodes {
             tree.select();
         }
 
         let (best_move, best_score) = tree.best_move();
 
-        if max_time < (time::Duration::from_millis(10))
-            || start_time.elapsed() > max_time - (time::Duration::from_millis(10))
-            || tree.children().len() == 1
+        let out_of_time = max_time.is_some_and(|max_time| {
+            max_time < (time::Duration::from_millis(10))
+                || start_time.elapsed() > max_time - (time::Duration::from_millis(10))
+        });
+        let out_of_nodes = max_nodes.is_some_and(|max_nodes| tree.visits() >= max_nodes);
+
+        if out_of_time || out_of_nodes || stop.load(Ordering::Relaxed) || tree.children().len() == 1
         {
             return tree.best_move();
         }
@@ -272,9 +1072,15 @@ pub fn play_move_time<const S: usize>(
         child_refs.reverse();
 
         let node_ratio = child_refs[1].visits as f32 / child_refs[0].visits as f32;
-        let time_ratio = start_time.elapsed().as_secs_f32() / max_time.as_secs_f32();
+        // How much of the search budget has been used, by whichever limit is closest
+        let time_ratio = max_time.map_or(0.0, |max_time| {
+            start_time.elapsed().as_secs_f32() / max_time.as_secs_f32()
+        });
+        let nodes_ratio =
+            max_nodes.map_or(0.0, |max_nodes| tree.visits() as f32 / max_nodes as f32);
+        let budget_ratio = time_ratio.max(nodes_ratio);
 
-        if time_ratio.powf(2.0) > node_ratio / 2.0 {
+        if budget_ratio.powf(2.0) > node_ratio / 2.0 {
             // Do not stop if any other child nodes have better action value
             if tree
                 .children()
@@ -296,16 +1102,32 @@ pub fn mcts_training<const S: usize>(
     nodes: u64,
     settings: MctsSetting<S>,
 ) -> Vec<(Move, Score)> {
+    mcts_training_with_root_value(position, nodes, settings).0
+}
+
+/// Like `mcts_training`, but with the visit fractions raised to the power of `1 / temperature` and renormalized.
+/// Use a temperature above 1.0 for more diverse self-play moves, or 0.0 to give the most visited move all the probability.
+pub fn mcts_training_with_temperature<const S: usize>(
+    position: Position<S>,
+    nodes: u64,
+    settings: MctsSetting<S>,
+    temperature: f64,
+) -> Vec<(Move, Score)> {
+    apply_temperature(temperature, &mcts_training(position, nodes, settings))
+}
+
+/// Like `mcts_training`, but also returns the winning probability of the root position, from the perspective of the side to move.
+pub fn mcts_training_with_root_value<const S: usize>(
+    position: Position<S>,
+    nodes: u64,
+    settings: MctsSetting<S>,
+) -> (Vec<(Move, Score)>, Score) {
     let mut tree = MonteCarloTree::with_settings(position, settings);
 
     for _ in 0..nodes {
         tree.select();
     }
-    let child_visits: u64 = tree.children().iter().map(|edge| edge.visits).sum();
-    tree.children()
-        .iter()
-        .map(|edge| (edge.mv.clone(), edge.visits as f32 / child_visits as f32))
-        .collect()
+    tree.training_target()
 }
 
 /// Convert a static evaluation in centipawns to a winning probability between 0.0 and 1.0.
@@ -313,6 +1135,18 @@ pub fn cp_to_win_percentage(cp: f32) -> Score {
     1.0 / (1.0 + Score::exp(-cp as Score))
 }
 
+/// The inverse of `cp_to_win_percentage`. Returns infinity for a winning probability of 0.0 or 1.0.
+pub fn win_percentage_to_cp(win_percentage: Score) -> f32 {
+    -Score::ln(1.0 / win_percentage - 1.0)
+}
+
+/// Like `cp_to_win_percentage`, but with a steeper curve when there are few empty squares left.
+/// Late in the game, the same static evaluation is a much stronger predictor of the result.
+pub fn cp_to_win_percentage_phased(cp: f32, empty_squares: usize) -> Score {
+    let steepness = 1.0 + 4.0 / (1.0 + empty_squares as Score);
+    1.0 / (1.0 + Score::exp(-cp as Score * steepness))
+}
+
 // Utility for testing
 pub fn edge_mem_usage() -> usize {
     mem::size_of::<TreeEdge>()
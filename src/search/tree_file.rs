@@ -0,0 +1,246 @@
+//! Binary format for saving a search tree, so that the search can be resumed later or on another machine.
+//!
+//! All integers are little-endian. The file starts with the magic bytes `TKMT`, a version byte, the board size and the komi,
+//! followed by the root position, and the search's maximum depth as a `u16`.
+//! The root position is stored as a start position in TPS as a length-prefixed string, followed by the number of moves played from it as a `u16`,
+//! and the moves in the encoding of `Move::to_u16`. The move history is used by the policy evaluation and the repetition check,
+//! so it is included if the position was reached from the start position. Otherwise, the root position itself is the start position.
+//! The tree is then stored depth-first, starting from the virtual edge to the root. Each edge is stored as:
+//! * The move, in the encoding of `Move::to_u16`
+//! * The visits as a `u64`, and the mean action value and heuristic score as `f32`s
+//! * The proven result as a single byte, followed by the number of plies as a `u16` if the result is proven
+//! * A byte that is set if the edge has been expanded. If so, it is followed by the node's total action value and total draws as `f64`s,
+//!   a byte that is set if the node is terminal, and the number of children as a `u16`, followed by the children
+//!
+//! The search settings are not stored, and must be provided when loading the tree.
+
+use std::io;
+use std::io::{Read, Write};
+
+use board_game_traits::Position as PositionTrait;
+use pgn_traits::PgnPosition;
+
+use crate::binary_io::{invalid_data, read_bytes, read_move, read_u16, read_u8};
+use crate::position::{Move, Position};
+use crate::search::mcts_core::{GameResultForUs, NodeLock, TempVectors, Tree, TreeEdge};
+use crate::search::{MctsSetting, MonteCarloTree};
+
+const MAGIC: &[u8; 4] = b"TKMT";
+const VERSION: u8 = 2;
+
+impl<const S: usize> MonteCarloTree<S> {
+    /// Write the tree and its root position to `writer`.
+    pub fn save<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[VERSION, S as u8])?;
+        writer.write_all(&self.position.komi().to_le_bytes())?;
+        let (start_position, moves) = self.start_position_and_moves();
+        let tps = start_position.to_fen();
+        writer.write_all(&(tps.len() as u16).to_le_bytes())?;
+        writer.write_all(tps.as_bytes())?;
+        if moves.len() > u16::MAX as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} moves is too many to store", moves.len()),
+            ));
+        }
+        writer.write_all(&(moves.len() as u16).to_le_bytes())?;
+        for mv in moves {
+            writer.write_all(&mv.to_u16().to_le_bytes())?;
+        }
+        writer.write_all(&self.max_depth.to_le_bytes())?;
+        write_edge(&mut writer, &self.edge)?;
+        writer.flush()
+    }
+
+    /// Read a tree written by `save`, and continue the search with the given settings.
+    pub fn load<R: Read>(mut reader: R, settings: MctsSetting<S>) -> io::Result<Self> {
+        let mut header = [0; 6];
+        reader.read_exact(&mut header)?;
+        if &header[0..4] != MAGIC || header[4] != VERSION {
+            return Err(invalid_data("Not a search tree file".to_string()));
+        }
+        if header[5] as usize != S {
+            return Err(invalid_data(format!(
+                "Search tree is for {}s, expected {}s",
+                header[5], S
+            )));
+        }
+        let komi = i8::from_le_bytes(read_bytes(&mut reader)?);
+        let tps_length = read_u16(&mut reader)?;
+        let mut tps_bytes = vec![0; tps_length as usize];
+        reader.read_exact(&mut tps_bytes)?;
+        let tps = String::from_utf8(tps_bytes).map_err(|err| invalid_data(err.to_string()))?;
+        let mut position: Position<S> = Position::from_fen(&tps)
+            .map_err(|err| invalid_data(err.to_string()))?
+            .with_komi(komi);
+        let num_moves = read_u16(&mut reader)?;
+        let mut legal_moves = vec![];
+        for _ in 0..num_moves {
+            let mv = read_move::<_, S>(&mut reader)?;
+            position.generate_moves(&mut legal_moves);
+            if !legal_moves.contains(&mv) {
+                return Err(invalid_data(format!(
+                    "Illegal move {} in {}",
+                    mv.to_string::<S>(),
+                    position.to_fen()
+                )));
+            }
+            legal_moves.clear();
+            position.do_move(mv);
+        }
+        let max_depth = read_u16(&mut reader)?;
+        let edge = read_edge::<_, S>(&mut reader)?;
+
+        // Check that the tree was searched from this position
+        if let Some(root) = edge.child.as_ref() {
+            check_tree_moves(root, &mut position)?;
+        }
+
+        let allocated_nodes = edge.child.as_ref().map_or(0, |root| root.num_nodes());
+        let mut tree = MonteCarloTree {
+            edge,
+            position,
+            settings,
+            temp_vectors: TempVectors::new::<S>(),
+            max_depth,
+            allocated_nodes,
+        };
+        tree.settings.contempt_color = tree.position.side_to_move();
+        Ok(tree)
+    }
+
+    /// The position to store, and the moves played from it to reach the root.
+    /// If the root was not reached from the start position, for example because it was set up from TPS, the move history is not stored.
+    fn start_position_and_moves(&self) -> (Position<S>, &[Move]) {
+        let moves = self.position.moves();
+        if moves.len() == self.position.half_moves_played() {
+            let mut position = Position::start_position_with_komi(self.position.komi());
+            let mut legal_moves = vec![];
+            for mv in moves {
+                position.generate_moves(&mut legal_moves);
+                if !legal_moves.contains(mv) {
+                    break;
+                }
+                legal_moves.clear();
+                position.do_move(mv.clone());
+            }
+            if position.moves().len() == moves.len() && position == self.position {
+                return (
+                    Position::start_position_with_komi(self.position.komi()),
+                    moves,
+                );
+            }
+        }
+        (self.position.clone(), &[])
+    }
+}
+
+fn write_edge<W: Write>(writer: &mut W, edge: &TreeEdge) -> io::Result<()> {
+    writer.write_all(&edge.mv.to_u16().to_le_bytes())?;
+    writer.write_all(&edge.visits.to_le_bytes())?;
+    writer.write_all(&edge.mean_action_value.to_le_bytes())?;
+    writer.write_all(&edge.heuristic_score.to_le_bytes())?;
+    match edge.proven_result {
+        None => writer.write_all(&[0])?,
+        Some((game_result, plies)) => {
+            let result_byte: u8 = match game_result {
+                GameResultForUs::Win => 1,
+                GameResultForUs::Loss => 2,
+                GameResultForUs::Draw => 3,
+            };
+            writer.write_all(&[result_byte])?;
+            writer.write_all(&plies.to_le_bytes())?;
+        }
+    }
+    match edge.child.as_ref() {
+        None => writer.write_all(&[0]),
+        Some(node) => {
+            debug_assert_eq!(node.virtual_losses, 0);
+            writer.write_all(&[1])?;
+            writer.write_all(&node.total_action_value.to_le_bytes())?;
+            writer.write_all(&node.total_draws.to_le_bytes())?;
+            writer.write_all(&[node.is_terminal as u8])?;
+            if node.children.len() > u16::MAX as usize {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{} children is too many to store", node.children.len()),
+                ));
+            }
+            writer.write_all(&(node.children.len() as u16).to_le_bytes())?;
+            for child_edge in node.children.iter() {
+                write_edge(writer, child_edge)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Check that every move in the tree is legal in its parent's position.
+fn check_tree_moves<const S: usize>(tree: &Tree, position: &mut Position<S>) -> io::Result<()> {
+    let mut legal_moves = vec![];
+    position.generate_moves(&mut legal_moves);
+    for child_edge in tree.children.iter() {
+        if !legal_moves.contains(&child_edge.mv) {
+            return Err(invalid_data(format!(
+                "Move {} in the search tree is illegal in {}",
+                child_edge.mv.to_string::<S>(),
+                position.to_fen()
+            )));
+        }
+        if let Some(child) = child_edge.child.as_ref() {
+            let reverse_move = position.do_move(child_edge.mv.clone());
+            check_tree_moves(child, position)?;
+            position.reverse_move(reverse_move);
+        }
+    }
+    Ok(())
+}
+
+fn read_edge<R: Read, const S: usize>(reader: &mut R) -> io::Result<TreeEdge> {
+    let mv = read_move::<_, S>(reader)?;
+    let visits = u64::from_le_bytes(read_bytes(reader)?);
+    let mean_action_value = f32::from_le_bytes(read_bytes(reader)?);
+    let heuristic_score = f32::from_le_bytes(read_bytes(reader)?);
+    let proven_result = match read_u8(reader)? {
+        0 => None,
+        byte => {
+            let game_result = match byte {
+                1 => GameResultForUs::Win,
+                2 => GameResultForUs::Loss,
+                3 => GameResultForUs::Draw,
+                _ => return Err(invalid_data(format!("Invalid proven result {}", byte))),
+            };
+            Some((game_result, read_u16(reader)?))
+        }
+    };
+    let child = match read_u8(reader)? {
+        0 => None,
+        1 => {
+            let total_action_value = f64::from_le_bytes(read_bytes(reader)?);
+            let total_draws = f64::from_le_bytes(read_bytes(reader)?);
+            let is_terminal = read_u8(reader)? != 0;
+            let num_children = read_u16(reader)?;
+            let children = (0..num_children)
+                .map(|_| read_edge::<_, S>(reader))
+                .collect::<io::Result<Vec<_>>>()?;
+            Some(Box::new(Tree {
+                children: children.into_boxed_slice(),
+                total_action_value,
+                total_draws,
+                is_terminal,
+                virtual_losses: 0,
+                lock: NodeLock::default(),
+            }))
+        }
+        byte => return Err(invalid_data(format!("Invalid child marker {}", byte))),
+    };
+    Ok(TreeEdge {
+        child,
+        mv,
+        mean_action_value,
+        visits,
+        heuristic_score,
+        proven_result,
+    })
+}
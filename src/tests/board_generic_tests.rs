@@ -3,7 +3,7 @@ use pgn_traits::PgnPosition;
 use rand::seq::SliceRandom;
 
 use crate::position::Move;
-use crate::position::{squares_iterator, Role, Square};
+use crate::position::{squares_iterator, Role, Square, Symmetry};
 use crate::position::{GroupEdgeConnection, Position};
 use crate::tests::do_moves_and_check_validity;
 
@@ -42,6 +42,7 @@ fn play_random_games_prop<const S: usize>() {
                 position.moves()
             );
             assert_eq!(position, position.flip_colors().flip_colors());
+            assert_eq!(position.is_legal_position(), Ok(()));
 
             assert_eq!(Position::from_fen(&position.to_fen()).unwrap(), position);
 
@@ -64,6 +65,7 @@ fn play_random_games_prop<const S: usize>() {
 
             moves.clear();
             position.generate_moves(&mut moves);
+            assert!(moves.iter().all(|mv| position.is_legal(mv)));
             let mv = moves
                 .choose(&mut rng)
                 .unwrap_or_else(|| panic!("No legal moves on board\n{:?}", position))
@@ -77,6 +79,12 @@ fn play_random_games_prop<const S: usize>() {
             assert_ne!(hash_from_scratch, position.zobrist_hash_from_scratch());
 
             let result = position.game_result();
+            assert_eq!(
+                result,
+                position.game_result_with_group_data(&position.group_data()),
+                "Bitboard road detection disagrees with group data on\n{:?}",
+                position
+            );
             for rotation in position.symmetries_with_swapped_colors() {
                 if position.side_to_move() == rotation.side_to_move() {
                     assert_eq!(rotation.game_result(), result);
@@ -260,3 +268,109 @@ fn square_rank_file_prop<const S: usize>() {
         }
     }
 }
+
+#[test]
+fn transform_move_4s_test() {
+    transform_move_prop::<4>()
+}
+
+#[test]
+fn transform_move_5s_test() {
+    transform_move_prop::<5>()
+}
+
+#[test]
+fn transform_move_6s_test() {
+    transform_move_prop::<6>()
+}
+
+fn transform_move_prop<const S: usize>() {
+    let mut rng = rand::thread_rng();
+    for _ in 0..20 {
+        let mut position = <Position<S>>::default();
+        let mut moves = vec![];
+        while position.game_result().is_none() && position.half_moves_played() < 100 {
+            moves.clear();
+            position.generate_moves(&mut moves);
+
+            for symmetry in Symmetry::ALL.iter() {
+                let transformed_position = position.transform(*symmetry);
+                assert_eq!(transformed_position.transform(symmetry.inverse()), position);
+                for mv in moves.iter() {
+                    let transformed_move = position.transform_move(mv, *symmetry);
+                    assert_eq!(
+                        transformed_position.transform_move(&transformed_move, symmetry.inverse()),
+                        *mv
+                    );
+
+                    let mut position_after_move = position.clone();
+                    position_after_move.do_move(mv.clone());
+
+                    let mut transformed_position_after_move = transformed_position.clone();
+                    transformed_position_after_move.do_move(transformed_move);
+
+                    assert_eq!(
+                        transformed_position_after_move.transform(symmetry.inverse()),
+                        position_after_move,
+                        "Applying {} under {:?} gave a different position",
+                        mv.to_string::<S>(),
+                        symmetry
+                    );
+                }
+            }
+
+            let mv = moves.choose(&mut rng).unwrap().clone();
+            position.do_move(mv);
+        }
+    }
+}
+
+#[test]
+fn apply_symmetry_4s_test() {
+    apply_symmetry_prop::<4>()
+}
+
+#[test]
+fn apply_symmetry_5s_test() {
+    apply_symmetry_prop::<5>()
+}
+
+#[test]
+fn apply_symmetry_6s_test() {
+    apply_symmetry_prop::<6>()
+}
+
+fn apply_symmetry_prop<const S: usize>() {
+    let mut rng = rand::thread_rng();
+    for _ in 0..10 {
+        let mut position = <Position<S>>::default();
+        let mut moves = vec![];
+        while position.game_result().is_none() && position.half_moves_played() < 60 {
+            for symmetry in Symmetry::ALL.iter() {
+                let mut transformed_position = position.clone();
+                transformed_position.apply_symmetry(*symmetry);
+                assert_eq!(transformed_position, position.transform(*symmetry));
+                assert_eq!(
+                    transformed_position.moves(),
+                    position.transform(*symmetry).moves()
+                );
+
+                transformed_position.apply_inverse_symmetry(*symmetry);
+                assert_eq!(transformed_position, position);
+                assert_eq!(transformed_position.moves(), position.moves());
+
+                // Involutions restore the original board when applied twice
+                if symmetry.inverse() == *symmetry {
+                    transformed_position.apply_symmetry(*symmetry);
+                    transformed_position.apply_symmetry(*symmetry);
+                    assert_eq!(transformed_position, position, "{:?}", symmetry);
+                }
+            }
+
+            moves.clear();
+            position.generate_moves(&mut moves);
+            let mv = moves.choose(&mut rng).unwrap().clone();
+            position.do_move(mv);
+        }
+    }
+}
@@ -1,4 +1,6 @@
-use board_game_traits::{Color, Position as PositionTrait};
+use std::collections::HashSet;
+
+use board_game_traits::{Color, EvalPosition, Position as PositionTrait};
 use board_game_traits::{GameResult, GameResult::*};
 use pgn_traits::PgnPosition;
 
@@ -7,7 +9,9 @@ use crate::position::Direction::*;
 use crate::position::Move;
 use crate::position::Piece::{BlackCap, BlackFlat, WhiteFlat, WhiteWall};
 use crate::position::Position;
-use crate::position::{squares_iterator, Piece, Role, Square, Stack};
+use crate::position::{
+    squares_iterator, AttackerDefender, MoveKinds, Movement, Piece, Role, Square, Stack, Symmetry,
+};
 use crate::tests::do_moves_and_check_validity;
 
 #[test]
@@ -305,13 +309,141 @@ fn games_ends_when_board_is_full_test() {
         &(move_strings.iter().map(AsRef::as_ref).collect::<Vec<_>>()),
     );
     assert!(position.game_result().is_none());
+    assert!(!position.is_board_full());
     position.do_move(position.move_from_san("a5").unwrap());
+    assert!(position.is_board_full());
+    assert!(!position.reserves_exhausted(Color::White));
+    assert!(!position.reserves_exhausted(Color::Black));
     assert_eq!(
         position.game_result(),
         Some(WhiteWin),
         "Board is full, game should have ended:\n{:?}",
         position
     );
+
+    let mut position = <Position<4>>::from_fen("1,2,1,2/2,1,2,1/1,2,1,2/2,1,2,x 2 8").unwrap();
+    assert!(!position.is_board_full());
+    assert_eq!(position.game_result(), None);
+    do_moves_and_check_validity(&mut position, &["d1"]);
+    assert!(position.is_board_full());
+    assert_eq!(position.game_result(), Some(BlackWin));
+}
+
+#[test]
+fn evaluate_leaf_test() {
+    let mut position = <Position<5>>::start_position();
+    do_moves_and_check_validity(&mut position, &["a1", "e5", "c3", "d3", "c4"]);
+    let score = position.evaluate_leaf();
+    assert!(score > 0.2 && score < 0.8, "Score was {}", score);
+    assert!((position.flip_colors().evaluate_leaf() - score).abs() < 0.001);
+
+    do_moves_and_check_validity(
+        &mut position,
+        &["b3", "c2", "b2", "a3", "b1", "c5", "b4", "c1"],
+    );
+    assert_eq!(position.game_result(), Some(WhiteWin));
+    // The side to move has lost, so the side that just moved has a score of 1.0
+    assert_eq!(position.evaluate_leaf(), 0.0);
+}
+
+#[test]
+fn canonical_position_test() {
+    let mut position = <Position<5>>::start_position();
+    do_moves_and_check_validity(&mut position, &["a1", "e5", "c3", "d3", "c4", "b2"]);
+    let canonical_position = position.canonical();
+    assert!(position.symmetries().contains(&canonical_position));
+    for symmetry in position.symmetries() {
+        assert_eq!(symmetry.canonical(), canonical_position);
+    }
+
+    let mut other_position = <Position<5>>::start_position();
+    do_moves_and_check_validity(&mut other_position, &["a1", "e5", "c3", "d3", "c4", "b4"]);
+    assert_ne!(other_position.canonical(), canonical_position);
+}
+
+#[test]
+fn is_legal_matches_move_generation_test() {
+    let mut tall_stacks =
+        <Position<5>>::from_fen("x,1S,1,1S,x/x2,2,2,1S/x2,2,x2/x2,2,x2/2C,x,2,x2 1 7").unwrap();
+    let positions = [
+        <Position<5>>::start_position(),
+        <Position<5>>::from_fen("x4,1/x5/x5/x5/x5 2 1").unwrap(),
+        tall_stacks.clone(),
+        {
+            tall_stacks.null_move();
+            tall_stacks
+        },
+        <Position<5>>::from_fen("x5/x5/x5/x2,1C,x2/1,1,2S,1,1 1 6").unwrap(),
+        <Position<5>>::from_fen("2,x4/12221C,2S,x,1S,2C/x5/x5/x5 1 12").unwrap(),
+        <Position<5>>::from_fen("2,x4/12221C,2S,x,1S,2C/x5/x5/x5 2 12").unwrap(),
+    ];
+    for position in positions.iter() {
+        let mut legal_moves = vec![];
+        position.generate_moves(&mut legal_moves);
+        for data in 0..=u16::MAX {
+            if let Some(mv) = Move::from_u16::<5>(data) {
+                assert_eq!(
+                    position.is_legal(&mv),
+                    legal_moves.contains(&mv),
+                    "{} on {}",
+                    mv.to_string::<5>(),
+                    position.to_fen()
+                );
+            }
+        }
+    }
+
+    // Carrying the same number of pieces twice, which can't be encoded with `Move::to_u16`
+    let position = <Position<5>>::from_fen("x5/x5/x5/x2,1C,x2/1,1,2S,1,1 1 6").unwrap();
+    let stack_movement = [1, 1]
+        .iter()
+        .map(|&pieces_to_take| Movement { pieces_to_take })
+        .collect();
+    let mv = Move::Move(
+        Square::parse_square::<5>("c2").unwrap(),
+        East,
+        stack_movement,
+    );
+    assert!(!position.is_legal(&mv));
+}
+
+#[test]
+fn komi_flat_count_test() {
+    let move_strings: Vec<String> = squares_iterator::<5>()
+        .map(|sq| sq.to_string::<5>())
+        .collect();
+    for (komi, result) in [
+        (-1, WhiteWin),
+        (0, WhiteWin),
+        (1, WhiteWin),
+        (2, Draw),
+        (3, BlackWin),
+    ] {
+        let move_strings: Vec<&str> = move_strings.iter().map(AsRef::as_ref).collect();
+        let mut position = <Position<5>>::start_position_with_komi(komi);
+        do_moves_and_check_validity(&mut position, &move_strings[..24]);
+        let fen = position.to_fen();
+        do_moves_and_check_validity(&mut position, &move_strings[24..]);
+        assert_eq!(position.komi(), komi);
+        assert_eq!(position.game_result(), Some(result), "Komi {}", komi);
+
+        let mv = position.moves().last().unwrap().clone();
+        let mut position = <Position<5>>::from_fen(&fen).unwrap().with_komi(komi);
+        let reverse_move = position.do_move(mv);
+        position.reverse_move(reverse_move);
+        assert_eq!(position.komi(), komi);
+        assert_eq!(position.flip_colors().komi(), -komi);
+    }
+}
+
+#[test]
+fn komi_static_eval_test() {
+    let mut position = <Position<5>>::start_position();
+    do_moves_and_check_validity(&mut position, &["a1", "e5", "c3", "d3", "c4"]);
+    let komi_position = <Position<5>>::from_fen(&position.to_fen())
+        .unwrap()
+        .with_komi(4);
+    assert!(komi_position.static_eval() < position.static_eval());
 }
 
 #[test]
@@ -397,6 +529,26 @@ fn repetitions_are_draws_test() {
     assert_eq!(position.game_result(), None);
 }
 
+#[test]
+fn repetitions_are_draws_in_all_symmetries_test() {
+    let mut position = <Position<5>>::start_position();
+    // Flatten a wall first, to check that the history is kept through it
+    do_moves_and_check_validity(&mut position, &["a1", "e5", "Ca3", "Sb3", "a3>"]);
+
+    let cycle_move_strings = ["a1+", "e5-", "a2-", "e4+"];
+    do_moves_and_check_validity(&mut position, &cycle_move_strings);
+    do_moves_and_check_validity(&mut position, &cycle_move_strings[0..3]);
+    for symmetry in position.symmetries_with_swapped_colors() {
+        assert_eq!(symmetry.game_result(), None);
+    }
+
+    do_moves_and_check_validity(&mut position, &cycle_move_strings[3..]);
+    assert_eq!(position.game_result(), Some(GameResult::Draw));
+    for symmetry in position.symmetries_with_swapped_colors() {
+        assert_eq!(symmetry.game_result(), Some(GameResult::Draw));
+    }
+}
+
 #[test]
 fn fake_repetitions_are_not_draws_test() {
     let mut position = <Position<6>>::start_position();
@@ -423,3 +575,519 @@ fn parse_tps_test() {
     let tps_string = "x2,1,x,1/x5/x5/x5/2,x4 2 2";
     assert_eq!(<Position<5>>::from_fen(tps_string).unwrap(), position);
 }
+
+#[test]
+fn write_canonical_tps_test() {
+    let mut position = <Position<5>>::start_position();
+    assert_eq!(position.to_fen(), "x5/x5/x5/x5/x5 1 1");
+
+    do_moves_and_check_validity(
+        &mut position,
+        &["a1", "e5", "c5", "b5", "Cd2", "Sb4", "c5<"],
+    );
+    let tps = "x,21,x2,1/x,2S,x3/x5/x3,1C,x/2,x4 2 4";
+    assert_eq!(position.to_fen(), tps);
+    assert_eq!(position.to_tps(), tps);
+    assert_eq!(<Position<5>>::from_fen(tps).unwrap(), position);
+    assert_eq!(
+        <Position<5>>::from_tps(&position.to_tps()).unwrap(),
+        position
+    );
+
+    let non_canonical_tps = "x,21,x,x,1/x,2S,x,x,x/x,x,x,x,x/x3,1C,x/2,x4 2 4";
+    assert_eq!(
+        <Position<5>>::from_fen(non_canonical_tps).unwrap().to_fen(),
+        tps
+    );
+}
+
+#[cfg(all(feature = "serde", feature = "serde_json"))]
+#[test]
+fn serde_position_test() {
+    let mut position = <Position<5>>::start_position();
+    do_moves_and_check_validity(&mut position, &["a1", "e5", "c3", "Sd3", "Cc4"]);
+
+    let json = serde_json::to_string(&position).unwrap();
+    assert_eq!(
+        json,
+        format!("{{\"tps\":\"{}\",\"komi\":0}}", position.to_fen())
+    );
+    let deserialized_position: Position<5> = serde_json::from_str(&json).unwrap();
+    assert_eq!(deserialized_position, position);
+    assert_eq!(serde_json::to_string(&deserialized_position).unwrap(), json);
+
+    let komi_position = position.clone().with_komi(4);
+    let komi_json = serde_json::to_string(&komi_position).unwrap();
+    let deserialized_komi_position: Position<5> = serde_json::from_str(&komi_json).unwrap();
+    assert_eq!(deserialized_komi_position.komi(), 4);
+    assert_eq!(deserialized_komi_position, komi_position);
+    assert_ne!(deserialized_komi_position, position);
+
+    let mv = position.move_from_san("c3+").unwrap();
+    let move_json = serde_json::to_string(&mv).unwrap();
+    let deserialized_move: Move = serde_json::from_str(&move_json).unwrap();
+    assert_eq!(deserialized_move, mv);
+    assert_eq!(
+        serde_json::to_string(&deserialized_move).unwrap(),
+        move_json
+    );
+
+    assert!(
+        serde_json::from_str::<Position<5>>("{\"tps\":\"x5/x5/x5/x5/x5 1\",\"komi\":0}").is_err()
+    );
+    assert!(serde_json::from_str::<Position<6>>(&json).is_err());
+}
+
+#[test]
+fn mid_game_tps_places_own_color_test() {
+    // A composed position, with only a few stones on the board
+    let mut position = <Position<5>>::from_fen("x5/x5/x2,1,x2/x5/x5 2 10").unwrap();
+    let mut moves = vec![];
+    position.generate_moves(&mut moves);
+
+    assert!(moves.contains(&position.move_from_san("Sa1").unwrap()));
+    assert!(moves.contains(&position.move_from_san("Ca1").unwrap()));
+
+    position.do_move(position.move_from_san("a1").unwrap());
+    assert_eq!(
+        position[Square::parse_square::<5>("a1").unwrap()].top_stone(),
+        Some(BlackFlat)
+    );
+
+    // A position from the first move still uses the opening swap
+    let mut position = <Position<5>>::from_fen("x5/x5/x2,1,x2/x5/x5 2 1").unwrap();
+    position.do_move(position.move_from_san("a1").unwrap());
+    assert_eq!(
+        position[Square::parse_square::<5>("a1").unwrap()].top_stone(),
+        Some(WhiteFlat)
+    );
+}
+
+#[test]
+fn game_ends_when_reserves_are_exhausted_test() {
+    // White has a single stone left in reserve
+    let mut position = <Position<4>>::from_fen("111111111111111,x3/x4/x4/2,x3 2 20").unwrap();
+    assert_eq!(position.white_reserves_left(), 1);
+    assert!(!position.reserves_exhausted(Color::White));
+
+    do_moves_and_check_validity(&mut position, &["Sd1"]);
+    assert_eq!(position.game_result(), None);
+
+    do_moves_and_check_validity(&mut position, &["c1"]);
+    assert!(position.reserves_exhausted(Color::White));
+    assert!(!position.reserves_exhausted(Color::Black));
+    assert_eq!(position.game_result(), Some(WhiteWin));
+}
+
+#[test]
+fn available_move_kinds_test() {
+    let mut position = <Position<5>>::default();
+    assert_eq!(position.available_move_kinds(), MoveKinds::PLACEMENT);
+
+    do_moves_and_check_validity(&mut position, &["a1"]);
+    assert_eq!(position.available_move_kinds(), MoveKinds::PLACEMENT);
+
+    do_moves_and_check_validity(&mut position, &["e5", "b1"]);
+    let move_kinds = position.available_move_kinds();
+    assert!(move_kinds.contains(MoveKinds::PLACEMENT));
+    assert!(move_kinds.contains(MoveKinds::SPREAD));
+
+    // White to move, with every stone on the board
+    let position = <Position<4>>::from_fen("1111111111111111,x3/x4/x4/2,x3 1 20").unwrap();
+    assert!(position.reserves_exhausted(Color::White));
+    assert_eq!(position.available_move_kinds(), MoveKinds::SPREAD);
+}
+
+#[test]
+fn validated_move_constructors_test() {
+    let c3 = Square::parse_square::<5>("c3").unwrap();
+    assert_eq!(
+        Move::spread::<5>(c3, East, &[2, 1]).unwrap(),
+        Move::from_string::<5>("3c3>21").unwrap()
+    );
+    assert_eq!(
+        Move::spread::<5>(c3, North, &[1]).unwrap(),
+        Move::from_string::<5>("c3+").unwrap()
+    );
+    assert_eq!(
+        Move::place::<5>(Role::Cap, c3).unwrap(),
+        Move::from_string::<5>("Cc3").unwrap()
+    );
+
+    // Zero drops, no drops, over the carry limit and off the board
+    assert!(Move::spread::<5>(c3, East, &[1, 0, 1]).is_err());
+    assert!(Move::spread::<5>(c3, East, &[]).is_err());
+    assert!(Move::spread::<5>(c3, North, &[3, 3]).is_err());
+    assert!(Move::spread::<5>(c3, West, &[1, 1, 1]).is_err());
+    assert!(Move::place::<5>(Role::Flat, Square(25)).is_err());
+}
+
+#[test]
+fn move_u16_encoding_round_trip_test() {
+    let mut position = <Position<5>>::default();
+    do_moves_and_check_validity(
+        &mut position,
+        &[
+            "c2", "c3", "d3", "b3", "c4", "1c2+", "1d3<", "1b3>", "1c4-", "Cc2", "a1", "1c2+", "a2",
+        ],
+    );
+    let mut moves = vec![];
+    position.generate_moves(&mut moves);
+    assert!(moves.contains(&position.move_from_san("5c3>32").unwrap()));
+
+    for mv in moves {
+        assert_eq!(
+            Move::from_u16::<5>(mv.to_u16()),
+            Some(mv.clone()),
+            "{:?}",
+            mv
+        );
+    }
+    assert_eq!(Move::from_u16::<5>(3 << 6), None);
+    // Square out of range
+    assert_eq!(Move::from_u16::<5>(25), None);
+    assert!(Move::from_u16::<6>(25).is_some());
+    // Spread off the edge of the board
+    assert_eq!(Move::from_u16::<5>(4 | 2 << 6 | 1 << 8), None);
+    assert!(Move::from_u16::<5>(4 | 1 << 6 | 1 << 8).is_some());
+    // Carrying more pieces than the carry limit
+    assert_eq!(Move::from_u16::<5>(12 | 1 << 13), None);
+    assert!(Move::from_u16::<5>(12 | 1 << 12).is_some());
+}
+
+#[test]
+fn questionable_early_capstone_test() {
+    let mut position = <Position<5>>::default();
+    do_moves_and_check_validity(&mut position, &["a1", "e5", "c3"]);
+
+    let warning = position.is_questionable_move(&position.move_from_san("Cc4").unwrap());
+    assert!(warning.unwrap().contains("capstone"));
+    assert_eq!(
+        position.is_questionable_move(&position.move_from_san("c4").unwrap()),
+        None
+    );
+}
+
+#[test]
+fn questionable_road_opening_move_test() {
+    let position = <Position<5>>::from_fen("x5/x5/x5/x5/1,1,1,1,2 2 5").unwrap();
+
+    let warning = position.is_questionable_move(&position.move_from_san("e1+").unwrap());
+    assert!(warning.unwrap().contains("road"));
+    assert_eq!(
+        position.is_questionable_move(&position.move_from_san("e2").unwrap()),
+        None
+    );
+}
+
+#[test]
+fn tps_with_too_many_capstones_test() {
+    let result = <Position<5>>::from_fen("x5/x5/x,1C,x,1C,x/x5/x,2,x,2,x 1 3");
+    let error = result.unwrap_err();
+    assert!(
+        error.to_string().contains("White has 2 capstones"),
+        "{}",
+        error
+    );
+
+    let result = <Position<5>>::from_fen("x5/x5/x,1C,x,2C,x/x5/x,2,x,1,x 1 3");
+    assert!(result.is_ok());
+}
+
+#[test]
+fn tps_with_too_many_flats_test() {
+    let result = <Position<4>>::from_fen("1111111111111111,x3/x4/x4/x4 2 9");
+    assert!(result.is_ok());
+    let result = <Position<4>>::from_fen("11111111111111111,x3/x4/x4/x4 2 9");
+    assert!(result
+        .unwrap_err()
+        .to_string()
+        .contains("White has 17 stones"));
+}
+
+#[test]
+fn tps_with_wrong_number_of_columns_test() {
+    assert!(<Position<5>>::from_fen("x5/x5/x5/x5/x5 1 1").is_ok());
+    assert!(<Position<5>>::from_fen("x5/x5/x4,1,1/x5/x5 1 2").is_err());
+    assert!(<Position<5>>::from_fen("x5/x5/x6/x5/x5 1 1").is_err());
+    assert!(<Position<5>>::from_fen("x5/x5/x,1,x2/x5/x5 2 1").is_err());
+}
+
+#[test]
+fn malformed_tps_is_error_test() {
+    assert!(<Position<5>>::from_tps("x5/x5/x,S,x3/x5/x5 1 2").is_err());
+    assert!(<Position<5>>::from_tps("x5/x5/x5/x5/x5 1 0").is_err());
+}
+
+#[test]
+fn tps_with_pieces_on_top_of_walls_or_capstones_is_error_test() {
+    assert!(<Position<5>>::from_tps("x5/x5/x,12S,x3/x5/x5 1 4").is_ok());
+    assert!(<Position<5>>::from_tps("x5/x5/x,1S2,x3/x5/x5 1 4").is_err());
+    assert!(<Position<5>>::from_tps("x5/x5/x,1C1,x3/x5/x5 1 4").is_err());
+    assert!(<Position<5>>::from_tps("x5/x5/x,1SC,x3/x5/x5 1 4").is_err());
+    assert!(<Position<5>>::from_tps("x5/x5/x,1S1S,x3/x5/x5 1 4").is_err());
+}
+
+#[test]
+fn tak_threats_with_defenses_test() {
+    let position = <Position<5>>::from_fen("2,2,x3/x3,1,x/1,1,1,x2/x3,1,x/x5 1 4").unwrap();
+    let threats = position.tak_threats_with_defenses();
+
+    // d3 threatens to win on e2, e3 and e4, which black cannot all block
+    let (_, defenses) = threats
+        .iter()
+        .find(|(mv, _)| *mv == position.move_from_san("d3").unwrap())
+        .unwrap();
+    assert!(defenses.is_empty());
+
+    let (_, defenses) = threats
+        .iter()
+        .find(|(mv, _)| *mv == position.move_from_san("e3").unwrap())
+        .unwrap();
+    // A flat on d3 can be captured by d4-, but a wall cannot
+    assert!(!defenses.contains(&position.move_from_san("d3").unwrap()));
+    assert!(defenses.contains(&position.move_from_san("Sd3").unwrap()));
+}
+
+#[test]
+fn threatened_squares_test() {
+    // Black threatens to complete the c-file by capturing c5, either by stacking d4 onto c4 first,
+    // or by capturing c5 with c4 first and refilling c4 with d4 afterwards
+    let position =
+        <Position<5>>::from_fen("x,1S,1,1S,x/x2,2,2,1S/x2,2,x2/x2,2,x2/2C,x,2,x2 1 7").unwrap();
+    assert_eq!(
+        position.threatened_squares(),
+        vec![
+            Square::parse_square::<5>("c5").unwrap(),
+            Square::parse_square::<5>("c4").unwrap()
+        ]
+    );
+
+    assert!(<Position<5>>::start_position()
+        .threatened_squares()
+        .is_empty());
+}
+
+#[test]
+fn complexity_test() {
+    let quiet_position = <Position<5>>::start_position();
+    let mut tactical_position =
+        <Position<5>>::from_fen("x,1S,1,1S,x/x2,2,2,1S/x2,2,x2/x2,2,x2/2C,x,2,x2 1 7").unwrap();
+    assert!(tactical_position.complexity() > quiet_position.complexity());
+
+    let quiet_complexity = quiet_position.complexity();
+    assert!(quiet_complexity > 0.0 && quiet_complexity < 3.0);
+
+    tactical_position.do_move(tactical_position.move_from_san("a5").unwrap());
+    tactical_position.do_move(tactical_position.move_from_san("d4<").unwrap());
+    tactical_position.do_move(tactical_position.move_from_san("a4").unwrap());
+    tactical_position.do_move(tactical_position.move_from_san("c4+").unwrap());
+    assert!(tactical_position.game_result().is_some());
+    assert_eq!(tactical_position.complexity(), 0.0);
+}
+
+#[test]
+fn road_relevant_attacker_moves_test() {
+    // White wins by completing the third rank on e3
+    let position = <Position<5>>::from_fen("2,2,x3/x5/1,1,1,1,x/x5/x5 1 4").unwrap();
+    assert_eq!(
+        position.road_relevant_moves(AttackerDefender::Attacker),
+        vec![
+            position.move_from_san("e3").unwrap(),
+            position.move_from_san("Ce3").unwrap()
+        ]
+    );
+
+    // Without d3, white has no immediate win
+    let position = <Position<5>>::from_fen("2,2,x3/x5/1,1,1,x2/x5/x5 1 4").unwrap();
+    let moves = position.road_relevant_moves(AttackerDefender::Attacker);
+
+    // Threatens to win on e3
+    assert!(moves.contains(&position.move_from_san("d3").unwrap()));
+    // Extends the road towards the first rank
+    assert!(moves.contains(&position.move_from_san("b2").unwrap()));
+    for irrelevant_move in ["a1", "e1", "Sa1", "Sb2"] {
+        assert!(
+            !moves.contains(&position.move_from_san(irrelevant_move).unwrap()),
+            "{} should not be relevant",
+            irrelevant_move
+        );
+    }
+
+    let mut all_moves = vec![];
+    position.generate_moves(&mut all_moves);
+    assert!(moves.len() < all_moves.len() / 2);
+}
+
+#[test]
+fn road_relevant_defender_moves_test() {
+    // Black must block e3, or lose immediately
+    let position = <Position<5>>::from_fen("2,2,x3/x5/1,1,1,1,x/x5/x5 2 4").unwrap();
+    let moves = position.road_relevant_moves(AttackerDefender::Defender);
+
+    for defense in ["e3", "Se3", "Ce3"] {
+        assert!(moves.contains(&position.move_from_san(defense).unwrap()));
+    }
+    assert!(!moves.contains(&position.move_from_san("a1").unwrap()));
+    assert!(!moves.contains(&position.move_from_san("c4").unwrap()));
+}
+
+#[test]
+fn flattens_test() {
+    let mut position = <Position<5>>::start_position();
+    do_moves_and_check_validity(&mut position, &["a1", "e5", "Cc3", "Sd3", "c3>", "Sd4"]);
+    assert_eq!(position.flattens(), 1);
+
+    let mv = position.move_from_san("d3+").unwrap();
+    let reverse_move = position.do_move(mv);
+    assert_eq!(position.flattens(), 2);
+
+    position.reverse_move(reverse_move);
+    assert_eq!(position.flattens(), 1);
+}
+
+#[test]
+fn do_san_move_test() {
+    let mut position = <Position<5>>::start_position();
+    let reverse_move = position.do_san_move("a1").unwrap();
+    assert_eq!(
+        position[Square::parse_square::<5>("a1").unwrap()].top_stone(),
+        Some(BlackFlat)
+    );
+
+    assert!(position.do_san_move("a1").is_err());
+    assert!(position.do_san_move("Ce5").is_err());
+    assert!(position.do_san_move("f7").is_err());
+    assert_eq!(position.half_moves_played(), 1);
+
+    position.reverse_move(reverse_move);
+    assert_eq!(position, <Position<5>>::start_position());
+}
+
+#[test]
+fn is_legal_position_test() {
+    let mut position = <Position<5>>::start_position();
+    assert_eq!(position.is_legal_position(), Ok(()));
+    do_moves_and_check_validity(&mut position, &["a1", "e5", "Cc3", "Sd3", "c3>", "d4"]);
+    assert_eq!(position.is_legal_position(), Ok(()));
+
+    position[Square::parse_square::<5>("a5").unwrap()].push(Piece::WhiteCap);
+    position[Square::parse_square::<5>("b5").unwrap()].replace_top(WhiteFlat);
+    position[Square::parse_square::<5>("e1").unwrap()].push(BlackFlat);
+    position.null_move();
+
+    let errors = position.is_legal_position().unwrap_err();
+    assert_eq!(errors.len(), 4, "{:?}", errors);
+    assert!(errors[0].starts_with("Stack on b5 has height 0"));
+    assert_eq!(
+        errors[1],
+        "White has 2 capstones on the board and 0 in reserve, but starts with 1 in 5s"
+    );
+    assert_eq!(
+        errors[2],
+        "Black has 4 stones on the board and 18 in reserve, but starts with 21 in 5s"
+    );
+    assert_eq!(errors[3], "Black to move after 6 plies");
+}
+
+#[test]
+fn zobrist_hash_test() {
+    // The same position, reached with different move orders
+    let mut position = <Position<5>>::start_position();
+    do_moves_and_check_validity(&mut position, &["a1", "e5", "c3", "d3", "c4"]);
+    let mut transposed_position = <Position<5>>::start_position();
+    do_moves_and_check_validity(&mut transposed_position, &["a1", "e5", "c4", "d3", "c3"]);
+    assert_eq!(position, transposed_position);
+    assert_eq!(position.zobrist_hash(), transposed_position.zobrist_hash());
+
+    let mut positions = HashSet::new();
+    positions.insert(position.clone());
+    assert!(!positions.insert(transposed_position));
+
+    let mut passed = position.clone();
+    passed.null_move();
+    assert_ne!(passed.zobrist_hash(), position.zobrist_hash());
+    assert!(positions.insert(passed.clone()));
+
+    for transformed in position
+        .symmetries_with_swapped_colors()
+        .into_iter()
+        .chain([passed])
+    {
+        assert_eq!(
+            transformed.zobrist_hash(),
+            transformed.zobrist_hash_from_scratch()
+        );
+    }
+
+    let mut symmetric_position = position.clone();
+    symmetric_position.apply_symmetry(Symmetry::Rotate90);
+    assert_eq!(
+        symmetric_position.zobrist_hash(),
+        position.transform(Symmetry::Rotate90).zobrist_hash()
+    );
+    symmetric_position.apply_inverse_symmetry(Symmetry::Rotate90);
+    assert_eq!(symmetric_position.zobrist_hash(), position.zobrist_hash());
+}
+
+#[test]
+fn capstone_cannot_be_buried_test() {
+    // Black's capstone on c3 is next to a tall white stack on c2
+    let mut position = <Position<5>>::start_position();
+    do_moves_and_check_validity(
+        &mut position,
+        &["a1", "e5", "c2", "Cc3", "b2", "a5", "b2>", "b5"],
+    );
+    let capstone_square = Square::parse_square::<5>("c3").unwrap();
+
+    let mut moves = vec![];
+    position.generate_moves(&mut moves);
+    for mv in moves {
+        let mut child = position.clone();
+        child.do_move(mv);
+        assert_eq!(child[capstone_square].top_stone(), Some(BlackCap));
+        assert_eq!(child[capstone_square].len(), 1);
+    }
+}
+
+#[test]
+fn material_balance_test() {
+    assert_eq!(<Position<5>>::start_position().material_balance(), 0.0);
+
+    let symmetric_position =
+        <Position<5>>::from_fen("2,x3,1/x5/x,1S,x,2S,x/x5/1C,x3,2C 1 6").unwrap();
+    assert_eq!(symmetric_position.material_balance(), 0.0);
+
+    let position = <Position<5>>::from_fen("2,x3,1/x5/x,1,x,2S,x/x5/1C,x2,1,2C 1 6").unwrap();
+    assert!(position.material_balance() > 0.0);
+    assert_eq!(
+        position.flip_colors().material_balance(),
+        -position.material_balance()
+    );
+}
+
+#[test]
+fn parse_mixed_move_list_test() {
+    let position = <Position<5>>::start_position();
+    let tokens = [
+        "a1",
+        "P E5",
+        "c3",
+        "P D3",
+        "M C3 D3 1",
+        "Sc2",
+        "M D3 B3 1 1",
+        "P C4 C",
+    ];
+    let moves = position.parse_move_list(&tokens).unwrap();
+
+    let expected: Vec<Move> = ["a1", "e5", "c3", "d3", "c3>", "Sc2", "2d3<11", "Cc4"]
+        .iter()
+        .map(|mv| Move::from_string::<5>(mv).unwrap())
+        .collect();
+    assert_eq!(moves, expected);
+
+    assert!(position.parse_move_list(&["a1", "P A1"]).is_err());
+    assert!(position.parse_move_list(&["a1", "M A1 B2 1"]).is_err());
+    assert!(position.parse_move_list(&["a1", "Q A1"]).is_err());
+}
@@ -0,0 +1,218 @@
+use crate::evaluation::policy_eval::policy_feature_names;
+use crate::evaluation::value_eval::value_feature_names;
+use crate::position::{Position, TunableBoard};
+use crate::tests::do_moves_and_check_validity;
+use board_game_traits::{EvalPosition, Position as PositionTrait};
+use pgn_traits::PgnPosition;
+
+#[test]
+fn policy_features_match_coefficients_test() {
+    policy_features_match_coefficients_prop::<4>(&["a1", "d4", "b2", "c3", "b2+"]);
+    policy_features_match_coefficients_prop::<5>(&["a1", "e5", "c3", "Cd3", "b3", "d3<"]);
+    policy_features_match_coefficients_prop::<6>(&["a1", "f6", "c3", "d4", "Cc4", "Sd3"]);
+}
+
+fn policy_features_match_coefficients_prop<const S: usize>(move_strings: &[&str]) {
+    let mut position = <Position<S>>::start_position();
+    do_moves_and_check_validity(&mut position, move_strings);
+
+    assert_eq!(
+        policy_feature_names::<S>().len(),
+        <Position<S>>::policy_params().len()
+    );
+
+    let group_data = position.group_data();
+    let mut moves = vec![];
+    position.generate_moves(&mut moves);
+
+    for mv in moves.iter() {
+        let features = position.policy_features(mv);
+        assert_eq!(features.len(), <Position<S>>::policy_params().len());
+
+        let mut coefficients = vec![0.0; <Position<S>>::policy_params().len()];
+        position.coefficients_for_move(&mut coefficients, mv, &group_data, moves.len());
+        for ((_, value), coefficient) in features.iter().zip(coefficients) {
+            assert_eq!(*value, coefficient);
+        }
+    }
+}
+
+#[test]
+fn value_feature_names_test() {
+    assert_eq!(
+        value_feature_names::<4>().len(),
+        <Position<4>>::value_params().len()
+    );
+    assert_eq!(
+        value_feature_names::<5>().len(),
+        <Position<5>>::value_params().len()
+    );
+    assert_eq!(
+        value_feature_names::<6>().len(),
+        <Position<6>>::value_params().len()
+    );
+}
+
+/// Returns the value coefficient with the given name
+fn value_coefficient<const S: usize>(position: &Position<S>, name: &str) -> f32 {
+    position
+        .value_features()
+        .into_iter()
+        .find(|(feature, _)| feature == name)
+        .unwrap()
+        .1
+}
+
+#[test]
+fn capstone_in_reserve_test() {
+    let mut cap_placed = <Position<5>>::start_position();
+    do_moves_and_check_validity(&mut cap_placed, &["a1", "e5", "Cc3"]);
+
+    let mut cap_in_reserve = <Position<5>>::start_position();
+    do_moves_and_check_validity(&mut cap_in_reserve, &["a1", "e5", "c3"]);
+
+    assert_eq!(
+        value_coefficient(&cap_placed, "capstone_in_reserve[0]"),
+        -1.0
+    );
+    assert_eq!(
+        value_coefficient(&cap_in_reserve, "capstone_in_reserve[0]"),
+        0.0
+    );
+}
+
+#[test]
+fn road_articulation_points_test() {
+    // b1 holds White's group together, while Black's square group has no weak points
+    let position = <Position<5>>::from_fen("x5/x5/x5/x3,2,2/1,1,1,2,2 1 8").unwrap();
+    assert_eq!(
+        value_coefficient(&position, "road_articulation_points"),
+        1.0
+    );
+
+    let position = <Position<5>>::from_fen("x5/x5/x5/x5/1,1,1,x2 1 8").unwrap();
+    assert_eq!(
+        value_coefficient(&position, "road_articulation_points"),
+        1.0
+    );
+
+    let position = <Position<5>>::from_fen("x5/x5/x5/1,x4/1,1,1,x2 1 8").unwrap();
+    assert_eq!(
+        value_coefficient(&position, "road_articulation_points"),
+        2.0
+    );
+
+    let position = <Position<5>>::from_fen("x5/x5/x5/1,1,x3/1,1,x3 1 8").unwrap();
+    assert_eq!(
+        value_coefficient(&position, "road_articulation_points"),
+        0.0
+    );
+
+    // c3 splits the group, but neither part touches an edge
+    let position = <Position<5>>::from_fen("x5/x5/x,1,1,1,x/x5/x5 1 8").unwrap();
+    assert_eq!(
+        value_coefficient(&position, "road_articulation_points"),
+        0.0
+    );
+}
+
+#[test]
+fn symmetrized_eval_of_symmetric_position_test() {
+    let mut position = <Position<5>>::start_position();
+    do_moves_and_check_validity(&mut position, &["c3"]);
+    assert!((position.evaluate_symmetrized() - position.static_eval()).abs() < 0.0001);
+}
+
+#[test]
+fn symmetrized_eval_of_asymmetric_position_test() {
+    let mut position = <Position<5>>::start_position();
+    do_moves_and_check_validity(&mut position, &["a1", "e5", "b2", "c2", "b3"]);
+
+    let evals: Vec<f32> = position
+        .symmetries()
+        .iter()
+        .map(|position| position.static_eval())
+        .collect();
+    let min = evals.iter().cloned().fold(f32::INFINITY, f32::min);
+    let max = evals.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+
+    let symmetrized = position.evaluate_symmetrized();
+    assert!(min <= symmetrized && symmetrized <= max);
+}
+
+#[test]
+fn road_track_progress_test() {
+    let position = <Position<5>>::from_fen("x4,2/x5/1,1,1,1,x/x5/2,x,2,x2 1 6").unwrap();
+    assert_eq!(value_coefficient(&position, "road_track_progress[0]"), 3.0);
+    assert_eq!(value_coefficient(&position, "road_track_progress[1]"), 0.0);
+
+    let rotated = position.rotate_board();
+    assert_eq!(value_coefficient(&rotated, "road_track_progress[0]"), 0.0);
+    assert_eq!(value_coefficient(&rotated, "road_track_progress[1]"), 3.0);
+}
+
+#[test]
+fn flat_stack_height_test() {
+    let isolated_flat = <Position<5>>::from_fen("x5/x5/x2,1,x2/x5/2,x4 2 2").unwrap();
+    assert_eq!(value_coefficient(&isolated_flat, "flat_stack_height"), 0.0);
+
+    let tall_stack = <Position<5>>::from_fen("x5/x5/x2,2121,x2/x5/x5 2 3").unwrap();
+    assert_eq!(value_coefficient(&tall_stack, "flat_stack_height"), 3.0);
+
+    let black_tall_stack = <Position<5>>::from_fen("x5/x5/x2,1212,x2/x5/x5 1 4").unwrap();
+    assert_eq!(
+        value_coefficient(&black_tall_stack, "flat_stack_height"),
+        -3.0
+    );
+
+    let wall_on_stack = <Position<5>>::from_fen("x5/x5/x2,212S,x2/x5/x5 2 3").unwrap();
+    assert_eq!(value_coefficient(&wall_on_stack, "flat_stack_height"), 0.0);
+}
+
+#[test]
+#[cfg(feature = "cap-smash-road")]
+fn cap_smash_road_test() {
+    let cap_next_to_wall = <Position<5>>::from_fen("x5/x5/x5/x2,1C,x2/1,1,2S,1,1 2 6").unwrap();
+    assert_eq!(value_coefficient(&cap_next_to_wall, "cap_smash_road"), 1.0);
+    assert_eq!(
+        value_coefficient(&cap_next_to_wall.flip_colors(), "cap_smash_road"),
+        -1.0
+    );
+
+    let cap_out_of_reach = <Position<5>>::from_fen("x5/x5/x4,1C/x5/1,1,2S,1,1 2 6").unwrap();
+    assert_eq!(value_coefficient(&cap_out_of_reach, "cap_smash_road"), 0.0);
+
+    // Moving the capstone breaks the road, unless it leaves our flat behind
+    let cap_in_road = <Position<5>>::from_fen("x5/x5/x5/x5/1,1,2S,1C,1 2 6").unwrap();
+    assert_eq!(value_coefficient(&cap_in_road, "cap_smash_road"), 0.0);
+    let capped_stack_in_road = <Position<5>>::from_fen("x5/x5/x5/x5/1,1,2S,21C,1 2 6").unwrap();
+    assert_eq!(
+        value_coefficient(&capped_stack_in_road, "cap_smash_road"),
+        0.0
+    );
+    let capped_stack_in_road = <Position<5>>::from_fen("x5/x5/x5/x5/1,1,2S,11C,1 2 6").unwrap();
+    assert_eq!(
+        value_coefficient(&capped_stack_in_road, "cap_smash_road"),
+        1.0
+    );
+
+    let two_walls = <Position<5>>::from_fen("x5/x5/x5/x2,1C,x2/1,2S,2S,1,1 2 6").unwrap();
+    assert_eq!(value_coefficient(&two_walls, "cap_smash_road"), 0.0);
+
+    // A capstone on a stack can reach walls further away
+    let cap_on_stack = <Position<5>>::from_fen("x5/x5/x2,11C,x2/x5/1,1,2S,1,1 2 6").unwrap();
+    assert_eq!(value_coefficient(&cap_on_stack, "cap_smash_road"), 1.0);
+    let blocked_cap_on_stack =
+        <Position<5>>::from_fen("x5/x5/x2,11C,x2/x2,1S,x2/1,1,2S,1,1 2 6").unwrap();
+    assert_eq!(
+        value_coefficient(&blocked_cap_on_stack, "cap_smash_road"),
+        0.0
+    );
+}
+
+#[test]
+#[cfg(not(feature = "cap-smash-road"))]
+fn cap_smash_road_is_not_computed_by_default_test() {
+    let cap_next_to_wall = <Position<5>>::from_fen("x5/x5/x5/x2,1C,x2/1,1,2S,1,1 2 6").unwrap();
+    assert_eq!(value_coefficient(&cap_next_to_wall, "cap_smash_road"), 0.0);
+}
@@ -0,0 +1,30 @@
+use std::time::Duration;
+
+use board_game_traits::Color;
+
+use crate::game_clock::GameClock;
+
+#[test]
+fn game_clock_attributes_time_to_mover_test() {
+    let mut clock = GameClock::new();
+    assert_eq!(clock.average_time_per_move(Color::White), Duration::ZERO);
+
+    clock.record_move(Color::White, Duration::from_millis(300));
+    clock.record_move(Color::Black, Duration::from_millis(100));
+    clock.record_move(Color::White, Duration::from_millis(500));
+
+    assert_eq!(clock.time_used(Color::White), Duration::from_millis(800));
+    assert_eq!(clock.time_used(Color::Black), Duration::from_millis(100));
+    assert_eq!(clock.moves(Color::White), 2);
+    assert_eq!(clock.moves(Color::Black), 1);
+    assert_eq!(clock.total_moves(), 3);
+
+    assert_eq!(
+        clock.average_time_per_move(Color::White),
+        Duration::from_millis(400)
+    );
+    assert_eq!(
+        clock.average_time_per_move(Color::Black),
+        Duration::from_millis(100)
+    );
+}
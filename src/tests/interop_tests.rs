@@ -0,0 +1,48 @@
+use crate::interop::{parse_shared_link, shared_link_size};
+use crate::position::Position;
+use crate::tests::do_moves_and_check_validity;
+use board_game_traits::Position as PositionTrait;
+use pgn_traits::PgnPosition;
+
+#[test]
+fn parse_tps_link_test() {
+    let url =
+        "https://ptn.ninja/?size=5&tps=x5%2Fx5%2Fx2%2C1%2Cx2%2Fx5%2Fx4%2C2+1+2&moves=2.+d3+c4";
+
+    assert_eq!(shared_link_size(url), Some(5));
+
+    let (position, moves) = parse_shared_link::<5>(url).unwrap();
+    assert_eq!(
+        position.to_fen(),
+        <Position<5>>::from_fen("x5/x5/x2,1,x2/x5/x4,2 1 2")
+            .unwrap()
+            .to_fen()
+    );
+    assert_eq!(
+        moves,
+        vec![
+            position.move_from_san("d3").unwrap(),
+            position.move_from_san("c4").unwrap()
+        ]
+    );
+}
+
+#[test]
+fn parse_moves_link_test() {
+    let url = "https://playtak.com/playground?moves=1.%20a1%20e5%202.%20Cc3%20b2%20*";
+
+    assert_eq!(shared_link_size(url), None);
+
+    let (position, moves) = parse_shared_link::<5>(url).unwrap();
+    assert_eq!(position, <Position<5>>::start_position());
+
+    let mut expected_position = <Position<5>>::start_position();
+    do_moves_and_check_validity(&mut expected_position, &["a1", "e5", "Cc3", "b2"]);
+    assert_eq!(moves, expected_position.moves().to_vec());
+}
+
+#[test]
+fn reject_link_with_wrong_size_test() {
+    assert!(parse_shared_link::<6>("https://ptn.ninja/?size=5&moves=a1").is_err());
+    assert!(parse_shared_link::<5>("https://ptn.ninja/?size=5&moves=a1+a1").is_err());
+}
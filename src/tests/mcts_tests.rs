@@ -1,9 +1,12 @@
-use crate::position::Position;
+use crate::position::{Move, Position, Role, TunableBoard};
 use crate::search;
-use crate::search::MctsSetting;
+use crate::search::{EnsembleEvaluator, Evaluator, MctsSetting, Score};
 use crate::tests::do_moves_and_check_validity;
-use board_game_traits::Position as PositionTrait;
+use board_game_traits::{GameResult, Position as PositionTrait};
 use pgn_traits::PgnPosition;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
 use std::time;
 use std::time::Duration;
 
@@ -233,3 +236,1191 @@ fn best_move_temperature_test() {
     assert!(b1_selected > 75);
     assert!(b1_selected < 150);
 }
+
+#[test]
+fn zero_temperature_selects_best_move_test() {
+    let position = <Position<5>>::start_position();
+    let moves = vec![
+        (position.move_from_san("a1").unwrap(), 0.2),
+        (position.move_from_san("a2").unwrap(), 0.5),
+        (position.move_from_san("a3").unwrap(), 0.3),
+    ];
+
+    let mut rng = rand::thread_rng();
+    for temperature in [0.0, 0.001] {
+        for _ in 0..100 {
+            assert_eq!(
+                search::best_move(&mut rng, temperature, &moves),
+                position.move_from_san("a2").unwrap()
+            );
+        }
+    }
+}
+
+#[test]
+fn training_with_temperature_test() {
+    let mut position = <Position<5>>::default();
+    do_moves_and_check_validity(&mut position, &["a1", "e5", "c3"]);
+    let move_scores = search::mcts_training(position.clone(), 5000, MctsSetting::default());
+    let top_score = |move_scores: &[(Move, Score)]| {
+        move_scores
+            .iter()
+            .map(|(_, score)| *score)
+            .fold(0.0, Score::max)
+    };
+
+    let proportional_scores =
+        search::mcts_training_with_temperature(position.clone(), 5000, MctsSetting::default(), 1.0);
+    for ((mv, score), (proportional_mv, proportional_score)) in
+        move_scores.iter().zip(proportional_scores.iter())
+    {
+        assert_eq!(mv, proportional_mv);
+        assert!((score - proportional_score).abs() < 0.0001);
+    }
+
+    let sharpened_scores = search::apply_temperature(0.5, &move_scores);
+    let flattened_scores = search::apply_temperature(2.0, &move_scores);
+    for scores in [&sharpened_scores, &flattened_scores] {
+        let score_sum: Score = scores.iter().map(|(_, score)| *score).sum();
+        assert!((score_sum - 1.0).abs() < 0.001, "Sum was {}", score_sum);
+    }
+    assert!(top_score(&sharpened_scores) > top_score(&move_scores));
+    assert!(top_score(&flattened_scores) < top_score(&move_scores));
+
+    let argmax_scores = search::apply_temperature(0.0, &move_scores);
+    assert_eq!(top_score(&argmax_scores), 1.0);
+    assert_eq!(
+        argmax_scores
+            .iter()
+            .filter(|(_, score)| *score > 0.0)
+            .count(),
+        1
+    );
+}
+
+#[test]
+fn phased_win_percentage_is_more_extreme_in_endgame_test() {
+    for &cp in &[-2.0, -0.5, 0.3, 1.0] {
+        let opening = search::cp_to_win_percentage_phased(cp, 30);
+        let endgame = search::cp_to_win_percentage_phased(cp, 2);
+        assert!((endgame - 0.5).abs() > (opening - 0.5).abs());
+        assert!((opening - 0.5).abs() > (search::cp_to_win_percentage(cp) - 0.5).abs());
+    }
+    assert_eq!(search::cp_to_win_percentage_phased(0.0, 0), 0.5);
+}
+
+#[test]
+fn best_move_white_perspective_test() {
+    let mut position = <Position<5>>::default();
+    do_moves_and_check_validity(&mut position, &["a1", "e5", "c3"]);
+
+    let mut tree = search::MonteCarloTree::new(position);
+    for _ in 0..1000 {
+        tree.select();
+    }
+    let (best_move, score) = tree.best_move();
+    assert_eq!(
+        tree.best_move_white_perspective(),
+        (best_move.clone(), 1.0 - score)
+    );
+
+    let mut position = <Position<5>>::default();
+    do_moves_and_check_validity(&mut position, &["a1", "e5"]);
+
+    let mut tree = search::MonteCarloTree::new(position);
+    for _ in 0..1000 {
+        tree.select();
+    }
+    assert_eq!(tree.best_move_white_perspective(), tree.best_move());
+}
+
+#[test]
+fn mcts_reports_minimum_node_count_test() {
+    let position = <Position<5>>::default();
+    for nodes in 0..search::MIN_MCTS_NODES {
+        let (_, _, nodes_searched) = search::mcts_with_node_count(position.clone(), nodes);
+        assert_eq!(nodes_searched, search::MIN_MCTS_NODES);
+    }
+    let (_, _, nodes_searched) = search::mcts_with_node_count(position, 100);
+    assert_eq!(nodes_searched, 100);
+}
+
+#[test]
+fn separate_param_setters_match_combined_setter_test() {
+    let value_params: Vec<f32> = <Position<5>>::value_params()
+        .iter()
+        .map(|p| p * 0.5)
+        .collect();
+    let policy_params: Vec<f32> = <Position<5>>::policy_params()
+        .iter()
+        .map(|p| p * 2.0)
+        .collect();
+
+    let combined =
+        <MctsSetting<5>>::default().with_eval_params(value_params.clone(), policy_params.clone());
+    let chained = <MctsSetting<5>>::default()
+        .with_value_params(value_params)
+        .with_policy_params(policy_params);
+
+    assert_eq!(combined, chained);
+    assert_ne!(combined, MctsSetting::default());
+}
+
+#[test]
+#[should_panic]
+fn wrong_number_of_value_params_test() {
+    <MctsSetting<5>>::default().with_value_params(vec![0.0; 3]);
+}
+
+#[test]
+fn stable_best_move_stops_search_early_test() {
+    let mut position = <Position<5>>::default();
+    do_moves_and_check_validity(
+        &mut position,
+        &["a1", "e1", "e2", "a2", "e3", "a3", "e4", "a4"],
+    );
+
+    let (best_move, _, nodes) = search::mcts_until_stable(position.clone(), 1_000_000, 100, 5);
+    assert_eq!(best_move, position.move_from_san("e5").unwrap());
+    assert!(nodes < 10_000, "Searched {} nodes", nodes);
+}
+
+#[test]
+fn ensemble_evaluator_averages_param_sets_test() {
+    let mut position = <Position<5>>::default();
+    do_moves_and_check_validity(&mut position, &["a1", "e5", "c3", "d3", "c4"]);
+    let group_data = position.group_data();
+
+    let params_a = (
+        <Position<5>>::value_params().to_vec(),
+        <Position<5>>::policy_params().to_vec(),
+    );
+    let params_b = (
+        params_a.0.iter().map(|p| p * 0.5).collect::<Vec<f32>>(),
+        params_a.1.iter().map(|p| p * 0.5).collect::<Vec<f32>>(),
+    );
+    let single_a = EnsembleEvaluator::new(vec![params_a.clone()]);
+    let single_b = EnsembleEvaluator::new(vec![params_b.clone()]);
+    let identical = EnsembleEvaluator::new(vec![params_a.clone(), params_a.clone()]);
+    let different = EnsembleEvaluator::new(vec![params_a, params_b]);
+
+    let policy = |evaluator: &EnsembleEvaluator<5>| {
+        let mut moves = vec![];
+        evaluator.policy(&position, &group_data, &mut moves);
+        moves
+    };
+
+    assert!(
+        (identical.value(&position, &group_data) - single_a.value(&position, &group_data)).abs()
+            < 0.0001
+    );
+    for ((mv, score), (single_mv, single_score)) in policy(&identical).iter().zip(policy(&single_a))
+    {
+        assert_eq!(*mv, single_mv);
+        assert!((score - single_score).abs() < 0.0001);
+    }
+
+    let average_value =
+        (single_a.value(&position, &group_data) + single_b.value(&position, &group_data)) / 2.0;
+    assert!((different.value(&position, &group_data) - average_value).abs() < 0.0001);
+    for (((_, score), (_, a_score)), (_, b_score)) in policy(&different)
+        .iter()
+        .zip(policy(&single_a))
+        .zip(policy(&single_b))
+    {
+        assert!((score - (a_score + b_score) / 2.0).abs() < 0.0001);
+    }
+
+    let settings = <MctsSetting<5>>::default().with_ensemble(different);
+    let mut tree = search::MonteCarloTree::with_settings(position, settings);
+    for _ in 0..1000 {
+        tree.select();
+    }
+    tree.best_move();
+}
+
+#[test]
+fn c_puct_settings_test() {
+    let settings = <MctsSetting<5>>::default().with_c_puct(2.0, 1000.0);
+    assert_eq!(settings.c_puct_init(), 2.0);
+    assert_eq!(settings.c_puct_base(), 1000.0);
+    assert_eq!(
+        settings.initial_mean_action_value(),
+        <MctsSetting<5>>::default().initial_mean_action_value()
+    );
+
+    #[allow(deprecated)]
+    let positional_settings = <MctsSetting<5>>::default().add_search_params(vec![2.0, 1000.0]);
+    assert_eq!(positional_settings, settings);
+
+    let position = <Position<5>>::default();
+    let mut tree = search::MonteCarloTree::with_settings(position, settings);
+    for _ in 0..1000 {
+        tree.select();
+    }
+    assert_eq!(tree.visits(), 1000);
+}
+
+#[test]
+fn fpu_reduction_narrows_search_test() {
+    let mut position = <Position<5>>::default();
+    do_moves_and_check_validity(&mut position, &["a1", "e5", "c3"]);
+
+    let visited_root_moves = |settings: MctsSetting<5>| {
+        let mut tree = search::MonteCarloTree::with_settings(position.clone(), settings);
+        for _ in 0..2000 {
+            tree.select();
+        }
+        tree.root_moves()
+            .iter()
+            .filter(|(_, _, visits)| *visits > 0)
+            .count()
+    };
+
+    let optimistic = visited_root_moves(<MctsSetting<5>>::default().with_fpu(0.0));
+    let pessimistic = visited_root_moves(<MctsSetting<5>>::default().with_fpu(1.0));
+    assert!(
+        pessimistic < optimistic,
+        "{} moves visited with FPU reduction 1.0, {} moves with 0.0",
+        pessimistic,
+        optimistic
+    );
+}
+
+#[test]
+fn max_nodes_bounds_tree_size_test() {
+    let mut position = <Position<5>>::default();
+    do_moves_and_check_validity(&mut position, &["a1", "e5", "c3"]);
+
+    let mut unbounded_tree = search::MonteCarloTree::new(position.clone());
+    for _ in 0..5000 {
+        unbounded_tree.select();
+    }
+    assert_eq!(unbounded_tree.allocated_nodes(), 5000);
+
+    let settings = <MctsSetting<5>>::default().with_max_nodes(500);
+    let mut tree = search::MonteCarloTree::with_settings(position.clone(), settings.clone());
+    for _ in 0..5000 {
+        tree.select();
+    }
+    assert_eq!(tree.visits(), 5000);
+    assert_eq!(tree.allocated_nodes(), 500);
+    assert!(tree.mem_usage() < unbounded_tree.mem_usage() / 2);
+    assert!(tree.best_move().1 > 0.0);
+
+    let mut parallel_tree = search::MonteCarloTree::with_settings(position, settings);
+    parallel_tree.select_parallel(5000, 4);
+    assert_eq!(parallel_tree.visits(), 5000);
+    assert!(parallel_tree.allocated_nodes() <= 504);
+}
+
+#[test]
+fn dirichlet_noise_decays_with_ply_test() {
+    let settings = <MctsSetting<5>>::default().add_dirichlet(0.2);
+    assert_eq!(
+        settings.dirichlet_epsilon(0),
+        settings.dirichlet_epsilon(30)
+    );
+
+    let settings = settings.with_dirichlet_decay(0.95);
+    assert!(settings.dirichlet_epsilon(0) > settings.dirichlet_epsilon(30));
+    assert!(settings.dirichlet_epsilon(30) > 0.0);
+
+    let mut position = <Position<5>>::default();
+    do_moves_and_check_validity(&mut position, &["a1", "e5"]);
+    search::play_move_time(position, Duration::from_millis(20), settings);
+}
+
+#[test]
+fn seeded_dirichlet_noise_is_reproducible_test() {
+    let play_game = |seed| {
+        let settings = <MctsSetting<5>>::default().add_dirichlet_seeded(0.2, seed);
+        let mut position = <Position<5>>::default();
+        let mut moves_and_visits = vec![];
+        for _ in 0..6 {
+            let mut tree =
+                search::MonteCarloTree::with_settings(position.clone(), settings.clone());
+            for _ in 0..200 {
+                tree.select();
+            }
+            let (best_move, _) = tree.best_move();
+            moves_and_visits.push((best_move.clone(), tree.root_moves()));
+            position.do_move(best_move);
+        }
+        moves_and_visits
+    };
+
+    let game = play_game(1);
+    assert_eq!(game, play_game(1));
+    assert!((2..10).any(|seed| play_game(seed) != game));
+}
+
+#[test]
+fn search_with_symmetrized_eval_test() {
+    let mut position = <Position<5>>::default();
+    do_moves_and_check_validity(&mut position, &["a1", "e5", "c3"]);
+    let settings = <MctsSetting<5>>::default().with_symmetrized_eval(true);
+    let mut tree = search::MonteCarloTree::with_settings(position, settings);
+    for _ in 0..1000 {
+        tree.select();
+    }
+    let (_, score) = tree.best_move();
+    assert!(score > 0.0 && score < 1.0);
+}
+
+#[test]
+fn contempt_avoids_immediate_draw_test() {
+    // Filling the last square draws on flat count
+    let position = <Position<4>>::from_fen("2,1,2,1/1,2,1,2/2,1,2,1/1,2,1,x 2 8").unwrap();
+    let drawing_move = position.move_from_san("d1").unwrap();
+
+    let search_with_contempt = |contempt| {
+        let settings = <MctsSetting<4>>::default().with_contempt(contempt);
+        let mut tree = search::MonteCarloTree::with_settings(position.clone(), settings);
+        for _ in 0..10_000 {
+            tree.select();
+        }
+        tree.best_move().0
+    };
+
+    assert_eq!(search_with_contempt(0.0), drawing_move);
+    assert_ne!(search_with_contempt(0.1), drawing_move);
+}
+
+#[test]
+fn evaluate_leaf_scores_draws_with_contempt_test() {
+    let mut position = <Position<4>>::from_fen("2,1,2,1/1,2,1,2/2,1,2,1/1,2,1,x 2 8").unwrap();
+    do_moves_and_check_validity(&mut position, &["d1"]);
+    assert_eq!(position.game_result(), Some(GameResult::Draw));
+    assert_eq!(position.evaluate_leaf(), 0.5);
+
+    // Outside of a search, the contempt applies to white
+    let settings = <MctsSetting<4>>::default().with_contempt(0.2);
+    let (score, is_terminal) = search::evaluate_leaf(&position, &position.group_data(), &settings);
+    assert!(is_terminal);
+    assert!((score - 0.3).abs() < 0.001, "Score was {}", score);
+}
+
+#[test]
+#[should_panic]
+fn contempt_out_of_range_test() {
+    <MctsSetting<4>>::default().with_contempt(0.6);
+}
+
+#[test]
+fn training_target_matches_mcts_training_test() {
+    let mut position = <Position<5>>::start_position();
+    do_moves_and_check_validity(&mut position, &["a1", "e5", "c3", "d3"]);
+
+    let mut tree = search::MonteCarloTree::new(position.clone());
+    for _ in 0..5000 {
+        tree.select();
+    }
+    let (move_scores, root_value) = tree.training_target();
+
+    let visit_sum: f32 = move_scores.iter().map(|(_, score)| *score).sum();
+    assert!((visit_sum - 1.0).abs() < 0.001, "Sum was {}", visit_sum);
+    assert!(root_value > 0.0 && root_value < 1.0);
+
+    let training_move_scores = search::mcts_training(position, 5000, MctsSetting::default());
+    assert_eq!(move_scores, training_move_scores);
+}
+
+#[test]
+fn training_target_root_value_is_for_side_to_move_test() {
+    let mut position = <Position<5>>::start_position();
+    do_moves_and_check_validity(
+        &mut position,
+        &["e5", "a1", "b1", "e4", "c1", "e3", "d1", "e2"],
+    );
+
+    let mut tree = search::MonteCarloTree::new(position);
+    for _ in 0..1000 {
+        tree.select();
+    }
+    let (_, root_value) = tree.training_target();
+    assert!(root_value > 0.9, "Root value was {}", root_value);
+}
+
+#[test]
+fn mcts_training_root_value_sign_test() {
+    // White threatens roads on both e1 and e3, and Black cannot stop both
+    let mut position = <Position<5>>::start_position();
+    do_moves_and_check_validity(
+        &mut position,
+        &[
+            "e5", "a1", "b1", "c5", "c1", "e4", "d1", "a4", "a3", "b5", "b3", "a2", "c3", "d4",
+            "d3",
+        ],
+    );
+    let (_, root_value) =
+        search::mcts_training_with_root_value(position, 1000, MctsSetting::default());
+    assert!(root_value < 0.1, "Root value was {}", root_value);
+}
+
+#[test]
+fn visit_distribution_test() {
+    let mut position = <Position<5>>::start_position();
+    do_moves_and_check_validity(&mut position, &["a1", "e5", "c3", "d3"]);
+    let excluded_move = position.move_from_san("c4").unwrap();
+
+    let mut tree = search::MonteCarloTree::with_settings(
+        position.clone(),
+        MctsSetting::default().exclude_moves(vec![excluded_move.clone()]),
+    );
+    for _ in 0..500 {
+        tree.select();
+    }
+
+    let visited_moves = tree.visit_distribution(false);
+    assert!(visited_moves.iter().all(|(_, share)| *share > 0.0));
+    let visit_sum: f32 = visited_moves.iter().map(|(_, share)| *share).sum();
+    assert!((visit_sum - 1.0).abs() < 0.001, "Sum was {}", visit_sum);
+
+    let mut legal_moves = vec![];
+    position.generate_moves(&mut legal_moves);
+    let all_moves = tree.visit_distribution(true);
+    assert_eq!(
+        all_moves
+            .iter()
+            .map(|(mv, _)| mv.clone())
+            .collect::<Vec<_>>(),
+        legal_moves
+    );
+    assert!(all_moves.contains(&(excluded_move, 0.0)));
+    assert!(all_moves.len() > visited_moves.len());
+    for (mv, share) in all_moves {
+        let visited_share = visited_moves
+            .iter()
+            .find(|(visited_move, _)| *visited_move == mv)
+            .map_or(0.0, |(_, share)| *share);
+        assert_eq!(share, visited_share);
+    }
+}
+
+#[test]
+fn pv_as_game_test() {
+    let mut position = <Position<5>>::start_position();
+    do_moves_and_check_validity(&mut position, &["a1", "e5", "c3", "d3"]);
+    let mut tree = search::MonteCarloTree::new(position.clone());
+    for _ in 0..5000 {
+        tree.select();
+    }
+    let game = tree.pv_as_game();
+    assert_eq!(game.start_position, position);
+    assert_eq!(
+        game.moves
+            .iter()
+            .map(|ptn_move| ptn_move.mv.clone())
+            .collect::<Vec<_>>(),
+        tree.pv().collect::<Vec<_>>()
+    );
+    assert!(game.moves.len() > 1);
+    assert_eq!(game.game_result, None);
+
+    // White wins immediately with a road
+    let mut position = <Position<5>>::default();
+    do_moves_and_check_validity(
+        &mut position,
+        &["b4", "c2", "d2", "c4", "b2", "d4", "e2", "c3"],
+    );
+    let mut tree = search::MonteCarloTree::new(position);
+    for _ in 0..1000 {
+        tree.select();
+    }
+    let game = tree.pv_as_game();
+    assert_eq!(game.moves.len(), 1);
+    assert_eq!(game.game_result, Some(GameResult::WhiteWin));
+}
+
+#[test]
+fn prune_to_move_frees_siblings_test() {
+    let mut position = <Position<5>>::default();
+    do_moves_and_check_validity(&mut position, &["a1", "e5", "c3"]);
+
+    let mut tree = search::MonteCarloTree::new(position);
+    for _ in 0..10_000 {
+        tree.select();
+    }
+    let (best_move, _) = tree.best_move();
+    let (_, best_move_score, best_move_visits) = tree
+        .root_moves()
+        .into_iter()
+        .find(|(mv, _, _)| *mv == best_move)
+        .unwrap();
+    let mem_usage_before = tree.mem_usage();
+    let visits_before = tree.visits();
+
+    tree.prune_to_move(&best_move);
+
+    assert!(tree.mem_usage() < mem_usage_before);
+    assert_eq!(tree.visits(), visits_before);
+    assert_eq!(
+        tree.root_moves(),
+        vec![(best_move.clone(), best_move_score, best_move_visits)]
+    );
+    assert_eq!(tree.best_move().0, best_move);
+
+    for _ in 0..100 {
+        tree.select();
+    }
+    assert_eq!(tree.root_moves()[0].2, best_move_visits + 100);
+}
+
+#[test]
+fn advance_root_preserves_subtree_test() {
+    let mut position = <Position<5>>::default();
+    do_moves_and_check_validity(&mut position, &["a1", "e5", "c3"]);
+
+    let mut tree = search::MonteCarloTree::new(position.clone());
+    for _ in 0..10_000 {
+        tree.select();
+    }
+    let (best_move, best_move_score, best_move_visits) = tree
+        .root_moves()
+        .into_iter()
+        .max_by_key(|(_, _, visits)| *visits)
+        .unwrap();
+
+    tree.advance_root(&best_move);
+    position.do_move(best_move);
+
+    assert_eq!(tree.visits(), best_move_visits);
+    assert_eq!(tree.mean_action_value(), 1.0 - best_move_score);
+
+    let mut legal_moves = vec![];
+    position.generate_moves(&mut legal_moves);
+    for _ in 0..1000 {
+        tree.select();
+    }
+    assert_eq!(tree.visits(), best_move_visits + 1000);
+    assert!(legal_moves.contains(&tree.best_move().0));
+
+    let unexpanded_move = tree
+        .root_moves()
+        .into_iter()
+        .find(|(_, _, visits)| *visits == 0)
+        .map(|(mv, _, _)| mv)
+        .unwrap();
+    tree.advance_root(&unexpanded_move);
+    position.do_move(unexpanded_move);
+    assert_eq!(tree.visits(), 0);
+
+    legal_moves.clear();
+    position.generate_moves(&mut legal_moves);
+    for _ in 0..100 {
+        tree.select();
+    }
+    assert!(legal_moves.contains(&tree.best_move().0));
+}
+
+#[test]
+fn proven_result_test() {
+    let mut position = <Position<5>>::default();
+    do_moves_and_check_validity(
+        &mut position,
+        &["b4", "c2", "d2", "c4", "b2", "d4", "e2", "c3"],
+    );
+    let mut tree = search::MonteCarloTree::new(position.clone());
+    assert_eq!(tree.proven_result(), None);
+    for _ in 0..1000 {
+        tree.select();
+    }
+    assert_eq!(tree.proven_result(), Some((GameResult::WhiteWin, 1)));
+
+    let mut tree = search::MonteCarloTree::new(position);
+    tree.select_parallel(1000, 4);
+    assert_eq!(tree.proven_result(), Some((GameResult::WhiteWin, 1)));
+
+    // Black to move, and can force a road with b4
+    let mut position = <Position<5>>::default();
+    do_moves_and_check_validity(
+        &mut position,
+        &["e5", "c3", "c2", "d5", "c1", "c5", "d3", "a4", "e3"],
+    );
+    let mut tree = search::MonteCarloTree::new(position);
+    for _ in 0..1000 {
+        tree.select();
+    }
+    assert_eq!(tree.proven_result(), Some((GameResult::BlackWin, 3)));
+
+    // White's only move loses
+    let position = <Position<5>>::from_fen(
+        "1,12S,12S,12S,1/12S,12S,12,12S,12S/12S,12,12,12,12S/12S,12,12S,12S,2S/1,2S,2S,12S,x 1 30",
+    )
+    .unwrap();
+    let mut tree = search::MonteCarloTree::new(position);
+    for _ in 0..100 {
+        tree.select();
+    }
+    assert_eq!(tree.proven_result(), Some((GameResult::BlackWin, 1)));
+}
+
+/// White's only legal move is to place the capstone on the last empty square, which ends the game on flat count.
+/// A forced spread can never end the game, since the side to move must have an empty square or stones in reserve.
+fn forced_terminal_move_prop(tps: &str, expected_result: GameResult, expected_score: Score) {
+    let mut position = <Position<5>>::from_fen(tps).unwrap();
+    let mut legal_moves = vec![];
+    position.generate_moves(&mut legal_moves);
+    let only_move = position.move_from_san("Ce1").unwrap();
+    assert_eq!(legal_moves, vec![only_move.clone()]);
+    assert_eq!(position.game_result(), None);
+
+    for nodes in [search::MIN_MCTS_NODES, 100, 10_000] {
+        let (mv, score) = search::mcts(position.clone(), nodes);
+        assert_eq!(mv, only_move);
+        assert_eq!(score, expected_score, "Wrong score after {} nodes", nodes);
+    }
+
+    let (mv, score) = search::play_move_time(
+        position.clone(),
+        Duration::from_millis(100),
+        MctsSetting::default(),
+    );
+    assert_eq!(mv, only_move);
+    assert_eq!(score, expected_score);
+
+    position.do_move(only_move);
+    assert_eq!(position.game_result(), Some(expected_result));
+}
+
+#[test]
+fn forced_winning_move_test() {
+    forced_terminal_move_prop(
+        "1,12S,12S,12S,1/12S,12S,12S,12S,12S/12S,12,12,12S,12S/12S,12S,12S,12S,2S/1,2S,2S,12S,x 1 30",
+        GameResult::WhiteWin,
+        1.0,
+    );
+}
+
+#[test]
+fn forced_suicide_move_test() {
+    forced_terminal_move_prop(
+        "1,12S,12S,12S,1/12S,12S,12,12S,12S/12S,12,12,12,12S/12S,12,12S,12S,2S/1,2S,2S,12S,x 1 30",
+        GameResult::BlackWin,
+        0.0,
+    );
+}
+
+#[test]
+fn forced_draw_move_test() {
+    forced_terminal_move_prop(
+        "1,12S,12S,12S,1/12S,12S,12S,12S,12S/12S,12,12,12S,12S/12S,12S,12,12S,2S/1,2S,2S,12S,x 1 30",
+        GameResult::Draw,
+        0.5,
+    );
+}
+
+#[test]
+fn best_move_wdl_test() {
+    // White wins immediately with a road
+    let mut position = <Position<5>>::default();
+    do_moves_and_check_validity(
+        &mut position,
+        &["b4", "c2", "d2", "c4", "b2", "d4", "e2", "c3"],
+    );
+    let mut tree = search::MonteCarloTree::new(position);
+    for _ in 0..1000 {
+        tree.select();
+    }
+    let (mv, score) = tree.best_move();
+    let (wdl_move, wdl) = tree.best_move_wdl();
+    assert_eq!(mv, wdl_move);
+    assert_eq!(wdl, [1.0, 0.0, 0.0]);
+    assert_eq!(score, wdl[0] + 0.5 * wdl[1]);
+
+    // White's only move draws on flat count
+    let position = <Position<5>>::from_fen(
+        "1,12S,12S,12S,1/12S,12S,12S,12S,12S/12S,12,12,12S,12S/12S,12S,12,12S,2S/1,2S,2S,12S,x 1 30",
+    )
+    .unwrap();
+    let only_move = position.move_from_san("Ce1").unwrap();
+    let mut tree = search::MonteCarloTree::new(position.clone());
+    for _ in 0..100 {
+        tree.select();
+    }
+    assert_eq!(tree.best_move_wdl(), (only_move.clone(), [0.0, 1.0, 0.0]));
+
+    let mut tree = search::MonteCarloTree::new(position);
+    tree.select_parallel(100, 4);
+    assert_eq!(tree.best_move_wdl(), (only_move, [0.0, 1.0, 0.0]));
+}
+
+#[test]
+fn ponder_in_background_thread_test() {
+    let mut position = <Position<5>>::default();
+    do_moves_and_check_validity(&mut position, &["a1", "e5", "c3"]);
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let mut tree = search::MonteCarloTree::new(position.clone());
+    let ponder_thread = {
+        let stop = stop.clone();
+        thread::spawn(move || {
+            while !stop.load(Ordering::Relaxed) || tree.visits() < 1000 {
+                tree.select();
+            }
+            tree
+        })
+    };
+    thread::sleep(Duration::from_millis(50));
+    stop.store(true, Ordering::Relaxed);
+    let mut tree = ponder_thread.join().unwrap();
+
+    let (opponent_move, _, opponent_move_visits) = tree
+        .root_moves()
+        .into_iter()
+        .max_by_key(|(_, _, visits)| *visits)
+        .unwrap();
+    tree.advance_root(&opponent_move);
+    assert_eq!(tree.visits(), opponent_move_visits);
+}
+
+#[test]
+fn play_move_time_with_stop_test() {
+    let position = <Position<5>>::default();
+    let start_time = time::Instant::now();
+    let (mv, _) = search::play_move_time_with_stop(
+        position.clone(),
+        Duration::from_secs(1000),
+        MctsSetting::default(),
+        Arc::new(AtomicBool::new(true)),
+    );
+    assert!(start_time.elapsed() < Duration::from_secs(10));
+
+    let mut legal_moves = vec![];
+    position.generate_moves(&mut legal_moves);
+    assert!(legal_moves.contains(&mv));
+}
+
+#[test]
+fn mcts_parallel_finds_win_in_one_test() {
+    let mut position = <Position<5>>::default();
+    do_moves_and_check_validity(
+        &mut position,
+        &["b4", "c2", "d2", "c4", "b2", "d4", "e2", "c3"],
+    );
+    let winning_moves = [
+        position.move_from_san("a2").unwrap(),
+        position.move_from_san("Ca2").unwrap(),
+    ];
+
+    for threads in [1, 4] {
+        let (mv, score) = search::mcts_parallel(position.clone(), 20_000, threads);
+        assert!(winning_moves.contains(&mv));
+        assert!(score > 0.9, "Score was {} with {} threads", score, threads);
+    }
+}
+
+#[test]
+fn select_parallel_single_thread_matches_sequential_test() {
+    let mut position = <Position<5>>::default();
+    do_moves_and_check_validity(&mut position, &["a1", "e5", "c3", "d4"]);
+
+    let mut sequential_tree = search::MonteCarloTree::new(position.clone());
+    for _ in 0..5000 {
+        sequential_tree.select();
+    }
+    let mut parallel_tree = search::MonteCarloTree::new(position);
+    parallel_tree.select_parallel(5000, 1);
+
+    assert_eq!(parallel_tree.visits(), sequential_tree.visits());
+    assert_eq!(
+        parallel_tree.mean_action_value(),
+        sequential_tree.mean_action_value()
+    );
+    assert_eq!(parallel_tree.root_moves(), sequential_tree.root_moves());
+}
+
+#[test]
+fn select_parallel_removes_virtual_losses_test() {
+    let mut position = <Position<5>>::default();
+    do_moves_and_check_validity(&mut position, &["a1", "e5", "c3", "d4"]);
+
+    let mut sequential_tree = search::MonteCarloTree::new(position.clone());
+    for _ in 0..20_000 {
+        sequential_tree.select();
+    }
+    let mut parallel_tree = search::MonteCarloTree::new(position);
+    parallel_tree.select_parallel(20_000, 4);
+
+    assert_eq!(parallel_tree.visits(), 20_000);
+    let root_moves = parallel_tree.root_moves();
+    assert_eq!(
+        root_moves.iter().map(|(_, _, visits)| visits).sum::<u64>() + 1,
+        parallel_tree.visits()
+    );
+    for (_, score, _) in root_moves {
+        assert!((0.0..=1.0).contains(&score), "Score was {}", score);
+    }
+    assert!(
+        (parallel_tree.mean_action_value() - sequential_tree.mean_action_value()).abs() < 0.05,
+        "Parallel value {}, sequential value {}",
+        parallel_tree.mean_action_value(),
+        sequential_tree.mean_action_value()
+    );
+
+    // The search continues normally after the parallel search
+    for _ in 0..100 {
+        parallel_tree.select();
+    }
+    assert_eq!(parallel_tree.visits(), 20_100);
+}
+
+#[test]
+fn mcts_tree_parallel_finds_win_in_one_test() {
+    let mut position = <Position<5>>::default();
+    do_moves_and_check_validity(
+        &mut position,
+        &["b4", "c2", "d2", "c4", "b2", "d4", "e2", "c3"],
+    );
+    let winning_moves = [
+        position.move_from_san("a2").unwrap(),
+        position.move_from_san("Ca2").unwrap(),
+    ];
+
+    let (mv, score) = search::mcts_tree_parallel(position, 20_000, 4);
+    assert!(winning_moves.contains(&mv));
+    assert!(score > 0.9, "Score was {}", score);
+}
+
+#[test]
+fn top_moves_test() {
+    let mut position = <Position<5>>::default();
+    do_moves_and_check_validity(&mut position, &["a1", "e5", "c3", "d4"]);
+
+    let mut tree = search::MonteCarloTree::new(position);
+    for _ in 0..5000 {
+        tree.select();
+    }
+    let top_moves = tree.top_moves(5);
+
+    assert_eq!(top_moves.len(), 5);
+    let (best_move, _) = tree.best_move();
+    let best_move_visits = tree
+        .root_moves()
+        .into_iter()
+        .find(|(mv, _, _)| *mv == best_move)
+        .unwrap()
+        .2;
+    assert_eq!(top_moves[0].2, best_move_visits);
+    for window in top_moves.windows(2) {
+        assert!(window[0].2 >= window[1].2);
+    }
+    for (mv, score, visits) in top_moves.iter() {
+        assert!(tree.root_moves().contains(&(mv.clone(), *score, *visits)));
+    }
+
+    let num_root_moves = tree.root_moves().len();
+    assert_eq!(tree.top_moves(1000).len(), num_root_moves);
+}
+
+#[test]
+fn flat_opening_only_places_flats_test() {
+    let settings = MctsSetting::default().with_flat_opening(8);
+    let mut position = <Position<5>>::default();
+
+    for _ in 0..8 {
+        let mut tree = search::MonteCarloTree::with_settings(position.clone(), settings.clone());
+        for _ in 0..1000 {
+            tree.select();
+        }
+        for (mv, _, _) in tree.root_moves() {
+            assert!(
+                matches!(mv, Move::Place(Role::Flat, _)),
+                "{} considered at ply {}",
+                mv.to_string::<5>(),
+                position.half_moves_played()
+            );
+        }
+        position.do_move(tree.best_move().0);
+    }
+
+    // Other moves are considered again after the opening
+    let mut tree = search::MonteCarloTree::with_settings(position, settings);
+    for _ in 0..1000 {
+        tree.select();
+    }
+    assert!(tree
+        .root_moves()
+        .iter()
+        .any(|(mv, _, _)| !matches!(mv, Move::Place(Role::Flat, _))));
+}
+
+#[test]
+fn play_move_limits_test() {
+    let mut position = <Position<5>>::default();
+    do_moves_and_check_validity(&mut position, &["a1", "e5", "c3"]);
+    let mut legal_moves = vec![];
+    position.generate_moves(&mut legal_moves);
+
+    // The node limit is hit long before the time limit
+    let start_time = time::Instant::now();
+    let (mv, _) = search::play_move_limits(
+        position.clone(),
+        Some(Duration::from_secs(60)),
+        Some(1000),
+        MctsSetting::default(),
+    )
+    .unwrap();
+    assert!(legal_moves.contains(&mv));
+    assert!(start_time.elapsed() < Duration::from_secs(30));
+
+    let (mv, _) =
+        search::play_move_limits(position.clone(), None, Some(1000), MctsSetting::default())
+            .unwrap();
+    assert!(legal_moves.contains(&mv));
+
+    let (mv, _) = search::play_move_limits(
+        position.clone(),
+        Some(Duration::from_millis(50)),
+        None,
+        MctsSetting::default(),
+    )
+    .unwrap();
+    assert!(legal_moves.contains(&mv));
+
+    assert!(search::play_move_limits(position, None, None, MctsSetting::default()).is_err());
+}
+
+#[test]
+fn mcts_with_stats_test() {
+    let mut position = <Position<5>>::default();
+    do_moves_and_check_validity(&mut position, &["a1", "e5", "c3", "d4"]);
+
+    let (mv, score, stats) = search::mcts_with_stats(position.clone(), 5000);
+    assert_eq!((mv, score), search::mcts(position.clone(), 5000));
+    assert_eq!(stats.visits, 5000);
+    assert!(stats.nodes_per_second > 0.0);
+
+    let mut tree = search::MonteCarloTree::new(position.clone());
+    for _ in 0..5000 {
+        tree.select();
+    }
+    assert_eq!(stats.max_depth, tree.max_depth());
+    assert!(tree.pv().count() <= tree.max_depth() as usize);
+    assert!(tree.max_depth() > 2);
+
+    let mut parallel_tree = search::MonteCarloTree::new(position);
+    parallel_tree.select_parallel(5000, 1);
+    assert_eq!(parallel_tree.max_depth(), tree.max_depth());
+}
+
+#[test]
+fn mcts_with_callback_test() {
+    let mut position = <Position<5>>::default();
+    do_moves_and_check_validity(&mut position, &["a1", "e5", "c3", "d4"]);
+
+    let mut visits = vec![];
+    let mut pv_lengths = vec![];
+    let (mv, score) = search::mcts_with_callback(position.clone(), 3500, |tree| {
+        visits.push(tree.visits());
+        pv_lengths.push(tree.pv().count());
+    });
+    assert_eq!((mv, score), search::mcts(position, 3500));
+    assert_eq!(visits, vec![1000, 2000, 3000, 3500]);
+    assert!(pv_lengths.iter().all(|length| *length > 0));
+}
+
+#[test]
+fn deterministic_select_test() {
+    let mut position = <Position<5>>::default();
+    do_moves_and_check_validity(&mut position, &["a1", "e5", "c3", "d4"]);
+    let settings = MctsSetting::default()
+        .add_dirichlet(0.2)
+        .add_rollout_depth(4)
+        .add_rollout_temperature(1.0)
+        .with_deterministic_select();
+
+    let search = || {
+        let mut tree = search::MonteCarloTree::with_settings(position.clone(), settings.clone());
+        for _ in 0..2000 {
+            tree.select();
+        }
+        tree
+    };
+    let (tree, other_tree) = (search(), search());
+    // Compares the visits, action values and heuristic scores of every node
+    assert!(tree == other_tree);
+    assert_eq!(
+        tree.root_moves()
+            .iter()
+            .map(|(_, score, _)| score.to_bits())
+            .collect::<Vec<_>>(),
+        other_tree
+            .root_moves()
+            .iter()
+            .map(|(_, score, _)| score.to_bits())
+            .collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn save_and_load_tree_test() {
+    let mut position = <Position<5>>::default();
+    do_moves_and_check_validity(&mut position, &["a1", "e5", "c3"]);
+    let mut tree = search::MonteCarloTree::new(position.clone());
+    for _ in 0..5000 {
+        tree.select();
+    }
+
+    let mut bytes = vec![];
+    tree.save(&mut bytes).unwrap();
+    let mut loaded_tree =
+        search::MonteCarloTree::<5>::load(bytes.as_slice(), MctsSetting::default()).unwrap();
+
+    assert_eq!(loaded_tree.best_move(), tree.best_move());
+    assert_eq!(loaded_tree.root_moves(), tree.root_moves());
+    assert_eq!(
+        loaded_tree.pv().collect::<Vec<_>>(),
+        tree.pv().collect::<Vec<_>>()
+    );
+    assert_eq!(loaded_tree.visits(), tree.visits());
+    assert_eq!(loaded_tree.allocated_nodes(), tree.allocated_nodes());
+
+    // The loaded tree continues the search exactly like the original
+    for _ in 0..1000 {
+        tree.select();
+        loaded_tree.select();
+    }
+    assert_eq!(loaded_tree.root_moves(), tree.root_moves());
+
+    // Positions set up from TPS are stored without their move history
+    let position =
+        <Position<5>>::from_fen("x,1S,1,1S,x/x2,2,2,1S/x2,2,x2/x2,2,x2/2C,x,2,x2 1 7").unwrap();
+    let mut tree = search::MonteCarloTree::new(position);
+    for _ in 0..1000 {
+        tree.select();
+    }
+    let mut tps_bytes = vec![];
+    tree.save(&mut tps_bytes).unwrap();
+    let loaded_tree =
+        search::MonteCarloTree::<5>::load(tps_bytes.as_slice(), MctsSetting::default()).unwrap();
+    assert_eq!(loaded_tree.best_move(), tree.best_move());
+    assert_eq!(loaded_tree.root_moves(), tree.root_moves());
+
+    assert!(search::MonteCarloTree::<6>::load(bytes.as_slice(), MctsSetting::default()).is_err());
+    assert!(
+        search::MonteCarloTree::<5>::load(&bytes[..bytes.len() - 1], MctsSetting::default())
+            .is_err()
+    );
+}
+
+#[test]
+fn save_and_load_tree_with_contempt_test() {
+    // Black to move, and filling the last square draws on flat count
+    let position = <Position<4>>::from_fen("2,1,2,1/1,2,1,2/2,1,2,1/1,2,1,x 2 8").unwrap();
+    let settings = <MctsSetting<4>>::default().with_contempt(0.2);
+    let mut tree = search::MonteCarloTree::with_settings(position, settings.clone());
+    for _ in 0..1000 {
+        tree.select();
+    }
+
+    let mut bytes = vec![];
+    tree.save(&mut bytes).unwrap();
+    let mut loaded_tree = search::MonteCarloTree::<4>::load(bytes.as_slice(), settings).unwrap();
+
+    for _ in 0..1000 {
+        tree.select();
+        loaded_tree.select();
+    }
+    assert_eq!(loaded_tree.root_moves(), tree.root_moves());
+}
+
+#[test]
+fn min_visit_fraction_test() {
+    let mut position = <Position<5>>::start_position();
+    do_moves_and_check_validity(&mut position, &["a1", "e5", "c3", "d3"]);
+    // With negated policy parameters, good moves get few visits, and some of them end up with lucky scores
+    let mut tree = search::MonteCarloTree::with_settings(
+        position,
+        MctsSetting::default()
+            .with_deterministic_select()
+            .with_policy_params(
+                <Position<5>>::policy_params()
+                    .iter()
+                    .map(|param| -param)
+                    .collect(),
+            ),
+    );
+    for _ in 0..500 {
+        tree.select();
+    }
+    let mut bytes = vec![];
+    tree.save(&mut bytes).unwrap();
+
+    // The highest scoring move among the moves with at least `min_visits` visits
+    let root_moves = tree.root_moves();
+    let best_move_with_visits = |min_visits: f32| {
+        root_moves
+            .iter()
+            .filter(|(_, _, visits)| *visits > 0 && *visits as f32 >= min_visits)
+            .max_by(|(_, score1, visits1), (_, score2, visits2)| {
+                score1
+                    .partial_cmp(score2)
+                    .unwrap()
+                    .then(visits1.cmp(visits2))
+            })
+            .unwrap()
+            .0
+            .clone()
+    };
+    let visits = |mv: &Move| {
+        root_moves
+            .iter()
+            .find(|(root_move, _, _)| root_move == mv)
+            .unwrap()
+            .2
+    };
+    let max_visits = tree.top_moves(1)[0].2;
+    assert_eq!(visits(&tree.best_move().0), max_visits);
+
+    let load_tree = |settings: MctsSetting<5>| {
+        search::MonteCarloTree::<5>::load(bytes.as_slice(), settings).unwrap()
+    };
+
+    // With a threshold, a less visited move with a better score can be chosen
+    let tree = load_tree(MctsSetting::default().with_min_visit_fraction(0.5));
+    assert_eq!(
+        tree.best_move().0,
+        best_move_with_visits(max_visits as f32 * 0.5)
+    );
+    assert!(visits(&tree.best_move().0) < max_visits);
+    assert_eq!(tree.best_move_wdl().0, tree.best_move().0);
+
+    // A lucky move with few visits is only chosen without a threshold
+    let tree = load_tree(MctsSetting::default().with_min_visit_fraction(0.0));
+    let lucky_move = best_move_with_visits(0.0);
+    assert!((visits(&lucky_move) as f32) < max_visits as f32 * 0.5);
+    assert_eq!(tree.best_move().0, lucky_move);
+
+    let tree = load_tree(MctsSetting::default().with_min_visit_fraction(1.0));
+    assert_eq!(visits(&tree.best_move().0), max_visits);
+}
+
+#[test]
+fn wall_cap_bonus_test() {
+    let mut position = <Position<5>>::start_position();
+    do_moves_and_check_validity(&mut position, &["a1", "e5", "c3", "d3", "c4", "d4"]);
+
+    let wall_cap_visits = |settings: MctsSetting<5>| -> u64 {
+        let mut tree = search::MonteCarloTree::with_settings(position.clone(), settings);
+        for _ in 0..2000 {
+            tree.select();
+        }
+        tree.root_moves()
+            .iter()
+            .filter(|(mv, _, _)| matches!(mv, Move::Place(Role::Wall | Role::Cap, _)))
+            .map(|(_, _, visits)| visits)
+            .sum()
+    };
+
+    let settings = MctsSetting::default().with_deterministic_select();
+    let visits_without_bonus = wall_cap_visits(settings.clone());
+    let visits_with_bonus = wall_cap_visits(settings.add_wall_cap_bonus(0.05));
+    assert!(
+        visits_with_bonus > visits_without_bonus,
+        "{} wall and cap visits with bonus, {} without",
+        visits_with_bonus,
+        visits_without_bonus
+    );
+}
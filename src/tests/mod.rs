@@ -1,12 +1,17 @@
 mod blunder_tests;
 mod board_generic_tests;
 mod board_tests;
+mod evaluation_tests;
+mod game_clock_tests;
+mod interop_tests;
 mod mcts_tests;
 mod move_gen_5s_tests;
 mod move_gen_generic_tests;
 mod ptn_tests;
 mod tactics_tests_5s;
 mod tactics_tests_6s;
+#[cfg(feature = "constant-tuning")]
+mod tune_tests;
 
 use crate::position::Position;
 use crate::search;
@@ -1,5 +1,6 @@
 use crate::position::Move;
 use crate::position::Position;
+use crate::position::Square;
 use crate::tests::do_moves_and_check_validity;
 use crate::tests::move_gen_generic_tests::perft_check_answers;
 use board_game_traits::Position as PositionTrait;
@@ -98,3 +99,37 @@ fn suicide_perf_test() {
     do_moves_and_check_validity(&mut position, &move_strings);
     perft_check_answers(&mut position, &[1, 85, 11_206, 957_000]);
 }
+
+#[test]
+fn tall_stack_respects_carry_limit_test() {
+    let mut position = <Position<5>>::from_fen("x5/x5/x2,121212121,x2/x5/x5 1 10").unwrap();
+    let square = Square::parse_square::<5>("c3").unwrap();
+    let original_stack = position[square];
+    assert_eq!(original_stack.len(), 9);
+
+    let mut moves = vec![];
+    position.generate_moves(&mut moves);
+    let mut max_pieces_taken = 0;
+
+    for mv in moves {
+        if let Move::Move(origin, _, stack_movement) = mv.clone() {
+            let pieces_taken = stack_movement.get(0).pieces_to_take;
+            assert!(
+                pieces_taken <= 5,
+                "{} takes too many pieces",
+                mv.to_string::<5>()
+            );
+            max_pieces_taken = max_pieces_taken.max(pieces_taken);
+
+            let old_position = position.clone();
+            let reverse_move = position.do_move(mv.clone());
+            assert_eq!(position[origin].len(), 9 - pieces_taken);
+            for i in 0..9 - pieces_taken {
+                assert_eq!(position[origin].get(i), original_stack.get(i));
+            }
+            position.reverse_move(reverse_move);
+            assert_eq!(position, old_position);
+        }
+    }
+    assert_eq!(max_pieces_taken, 5);
+}
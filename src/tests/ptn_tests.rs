@@ -1,8 +1,9 @@
 use crate::position::Position;
-use crate::ptn::{ptn_parser, Game, PtnMove};
+use crate::ptn::{annotation_glyph, ptn_parser, Game, GlyphThresholds, PtnMove};
 use crate::tests::do_moves_and_check_validity;
 use board_game_traits::{GameResult, Position as PositionTrait};
 use std::io::Cursor;
+use std::time::Duration;
 
 #[test]
 pub fn write_and_read_ptn_test() {
@@ -18,6 +19,7 @@ pub fn write_and_read_ptn_test() {
             mv: mv.clone(),
             annotations: vec![],
             comment: "".to_string(),
+            clock: None,
         })
         .collect();
 
@@ -44,6 +46,47 @@ pub fn write_and_read_ptn_test() {
     assert_eq!(parsed_games, vec![game])
 }
 
+#[test]
+fn write_and_read_ptn_with_komi_test() {
+    let mut position = <Position<5>>::start_position_with_komi(5);
+    do_moves_and_check_validity(&mut position, &["a1", "e5", "c3"]);
+    let game = Game {
+        start_position: <Position<5>>::start_position_with_komi(5),
+        moves: position
+            .moves()
+            .iter()
+            .map(|mv| PtnMove {
+                mv: mv.clone(),
+                annotations: vec![],
+                comment: "".to_string(),
+                clock: None,
+            })
+            .collect(),
+        game_result: None,
+        tags: vec![],
+    };
+
+    let mut ptn_writer = Cursor::new(vec![]);
+    game.game_to_ptn(&mut ptn_writer).unwrap();
+    let ptn = String::from_utf8(ptn_writer.into_inner()).unwrap();
+    assert!(ptn.contains("[Komi \"2.5\"]"), "{}", ptn);
+    assert!(!ptn.contains("FEN"), "{}", ptn);
+
+    let parsed_games: Vec<Game<Position<5>>> = ptn_parser::parse_ptn(&ptn).unwrap();
+    assert_eq!(parsed_games[0].start_position, game.start_position);
+    assert_eq!(parsed_games[0].moves, game.moves);
+
+    assert_eq!(<Position<5>>::parse_komi("2"), Some(4));
+    assert_eq!(<Position<5>>::parse_komi("-1.5"), Some(-3));
+    assert_eq!(<Position<5>>::parse_komi("2.25"), None);
+    assert_eq!(<Position<5>>::parse_komi("1000"), None);
+    assert!(
+        ptn_parser::parse_ptn::<Position<5>>("[Komi \"half\"]\n\n1. a1 e5 *")
+            .unwrap()
+            .is_empty()
+    );
+}
+
 #[test]
 // PTNs without a result shouldn't exist, but try to handle it correctly anyway
 fn parse_ptn_without_result() {
@@ -65,3 +108,133 @@ fn parse_ptn_without_result2() {
     assert_eq!(games[0].game_result, None);
     assert_eq!(games[1].game_result, None)
 }
+
+#[test]
+fn parse_ptn_clock_test() {
+    let ptn = "[Player1 \"tiltak\"]\n\n1. c4 {[clock 0:09:55]} a5 {0.6 [%clk 9:41.5]} 2. e1 {no clock} b3 *";
+
+    let games: Vec<Game<Position<6>>> = ptn_parser::parse_ptn(ptn).unwrap();
+    let clocks: Vec<Option<Duration>> = games[0].moves.iter().map(|mv| mv.clock).collect();
+
+    assert_eq!(
+        clocks,
+        vec![
+            Some(Duration::from_secs(595)),
+            Some(Duration::from_millis(581_500)),
+            None,
+            None
+        ]
+    );
+    assert_eq!(games[0].moves[1].comment, "0.6 [%clk 9:41.5]");
+
+    assert_eq!(
+        ptn_parser::parse_clock("[clock 1:00:00]"),
+        Some(Duration::from_secs(3600))
+    );
+    assert_eq!(ptn_parser::parse_clock("[clock -5]"), None);
+    assert_eq!(ptn_parser::parse_clock("[clock 1:2:3:4]"), None);
+    assert_eq!(ptn_parser::parse_clock("[clock 1e300]"), None);
+    assert_eq!(ptn_parser::parse_clock("[clock NaN]"), None);
+    assert_eq!(
+        ptn_parser::parse_clock("[clock 18446744073709551615:00:00]"),
+        None
+    );
+    assert_eq!(
+        ptn_parser::parse_clock("[clock 5124095576030431:0:18446744073709551615]"),
+        None
+    );
+
+    let ptn = "1. c4 {[clock 1e300]} a5 *";
+    let games: Vec<Game<Position<6>>> = ptn_parser::parse_ptn(ptn).unwrap();
+    assert_eq!(games[0].moves[0].clock, None);
+}
+
+#[test]
+fn iter_positions_test() {
+    let move_strings = ["a1", "e5", "c3", "d3", "c4", "1d3<"];
+    let mut position = <Position<5>>::start_position();
+    do_moves_and_check_validity(&mut position, &move_strings);
+
+    let game: Game<Position<5>> = Game {
+        start_position: Position::start_position(),
+        moves: position
+            .moves()
+            .iter()
+            .map(|mv| PtnMove {
+                mv: mv.clone(),
+                annotations: vec![],
+                comment: String::new(),
+                clock: None,
+            })
+            .collect(),
+        game_result: None,
+        tags: vec![],
+    };
+
+    assert_eq!(game.iter_positions().count(), move_strings.len());
+
+    let mut expected_position = <Position<5>>::start_position();
+    for ((position, ptn_move), mv) in game.iter_positions().zip(position.moves()) {
+        assert_eq!(position, expected_position);
+        assert_eq!(ptn_move.mv, *mv);
+        expected_position.do_move(mv.clone());
+    }
+}
+
+#[test]
+fn truncate_and_subgame_test() {
+    let move_strings = ["a5", "e1", "b1", "e2", "c1", "e3", "d1", "e4", "a1"];
+    let mut position = <Position<5>>::start_position();
+    do_moves_and_check_validity(&mut position, &move_strings);
+
+    let game: Game<Position<5>> = Game {
+        start_position: Position::start_position(),
+        moves: position
+            .moves()
+            .iter()
+            .map(|mv| PtnMove {
+                mv: mv.clone(),
+                annotations: vec![],
+                comment: String::new(),
+                clock: None,
+            })
+            .collect(),
+        game_result: Some(GameResult::WhiteWin),
+        tags: vec![("Result".to_string(), "R-0".to_string())],
+    };
+
+    let truncated_game = game.truncate_at_ply(2);
+    assert_eq!(truncated_game.moves, game.moves[..2]);
+    assert_eq!(truncated_game.start_position, Position::start_position());
+    assert_eq!(truncated_game.game_result, None);
+    assert!(truncated_game.tags.is_empty());
+
+    assert_eq!(game.truncate_at_ply(9), game);
+    assert_eq!(game.truncate_at_ply(100), game);
+
+    let subgame = game.subgame(2, 5);
+    assert_eq!(subgame.moves, game.moves[2..5]);
+    let mut start_position = <Position<5>>::start_position();
+    do_moves_and_check_validity(&mut start_position, &move_strings[..2]);
+    assert_eq!(subgame.start_position, start_position);
+    assert_eq!(subgame.game_result, None);
+    assert_eq!(subgame.iter_positions().count(), 3);
+
+    assert_eq!(game.truncate_at_ply(8).game_result, None);
+    assert_eq!(game.subgame(6, 9).game_result, Some(GameResult::WhiteWin));
+}
+
+#[test]
+fn annotation_glyph_test() {
+    assert_eq!(annotation_glyph(0.7, 0.2), Some("??"));
+    assert_eq!(annotation_glyph(0.5, 0.52), None);
+    assert_eq!(annotation_glyph(0.5, 0.48), None);
+    assert_eq!(annotation_glyph(0.2, 0.7), Some("!!"));
+
+    let thresholds = GlyphThresholds {
+        mistake: -0.01,
+        dubious: -0.005,
+        ..GlyphThresholds::default()
+    };
+    assert_eq!(thresholds.glyph(0.5, 0.48), Some("?"));
+}
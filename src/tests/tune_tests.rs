@@ -0,0 +1,534 @@
+use crate::evaluation::parameters::{
+    NUM_POLICY_PARAMS_4S, NUM_VALUE_PARAMS_4S, NUM_VALUE_PARAMS_5S,
+};
+use crate::position::Move;
+use crate::position::TunableBoard;
+use crate::position::{Position, Symmetry};
+use crate::ptn::{ptn_parser, Game};
+use crate::search::MctsSetting;
+use crate::tests::do_moves_and_check_validity;
+use crate::tune::compare::move_agreement;
+use crate::tune::export::{
+    read_positions, read_training_binary, write_positions, write_training_binary,
+};
+use crate::tune::gradient_descent::{
+    gradient_descent, gradient_descent_with_report, weighted_gradient_descent,
+};
+use crate::tune::openings::OpeningBook;
+use crate::tune::play_match::{
+    adjudicate, play_game, play_game_with_adjudication, select_move, Adjudication,
+};
+use crate::tune::search_params::tune_cpuct;
+use crate::tune::tournament::{elo_difference, round_robin};
+use crate::tune::training::{
+    aggregate_value_positions, positions_and_results_from_games, tune_value_and_policy,
+    tune_value_and_policy_sequential,
+};
+use board_game_traits::{GameResult, Position as PositionTrait};
+use pgn_traits::PgnPosition;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use std::time::Duration;
+
+#[test]
+fn play_game_records_stats_for_every_ply_test() {
+    let mut position = <Position<4>>::start_position();
+    do_moves_and_check_validity(&mut position, &["d4", "a1", "b1", "d3", "c1", "d2"]);
+
+    let settings = <MctsSetting<4>>::default();
+    let (game, move_scores, stats) = play_game(&settings, &settings, position.moves(), 0.1, true);
+    let stats = stats.unwrap();
+
+    assert!(game.game_result.is_some());
+    assert_eq!(stats.len(), game.moves.len());
+    assert_eq!(move_scores.len(), game.moves.len());
+
+    assert!(stats.iter().take(6).all(|stats| stats.nodes == 0));
+    for stats in stats.iter().skip(6) {
+        assert!(stats.nodes > 0);
+        assert!(stats.top_move_visit_fraction > 0.0 && stats.top_move_visit_fraction <= 1.0);
+        assert!(stats.root_value >= 0.0 && stats.root_value <= 1.0);
+    }
+
+    let (_, _, stats) = play_game(&settings, &settings, position.moves(), 0.1, false);
+    assert!(stats.is_none());
+}
+
+#[test]
+fn adjudicate_winning_position_test() {
+    let mut position = <Position<5>>::start_position();
+    // White can win with a2
+    do_moves_and_check_validity(
+        &mut position,
+        &["b4", "c2", "d2", "c4", "b2", "d4", "e2", "c3"],
+    );
+    let adjudication = Adjudication {
+        max_plies: 8,
+        nodes: 10_000,
+        threshold: 0.9,
+    };
+    assert_eq!(adjudicate(&position, &adjudication), GameResult::WhiteWin);
+
+    let settings = <MctsSetting<5>>::default();
+    let (game, _, _) = play_game_with_adjudication(
+        &settings,
+        &settings,
+        position.moves(),
+        0.1,
+        false,
+        Some(&adjudication),
+    );
+    assert_eq!(game.moves.len(), 8);
+    assert_eq!(game.game_result, Some(GameResult::WhiteWin));
+
+    let mut position = <Position<5>>::start_position();
+    do_moves_and_check_validity(&mut position, &["a1", "e5"]);
+    assert_eq!(adjudicate(&position, &adjudication), GameResult::Draw);
+}
+
+#[test]
+fn adjudicated_games_are_not_abandoned_after_200_plies_test() {
+    // Play random moves that do not end the game, to get a long opening
+    let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+    let mut position = <Position<5>>::start_position();
+    let mut moves = vec![];
+    while position.half_moves_played() < 202 {
+        moves.clear();
+        position.generate_moves(&mut moves);
+        moves.shuffle(&mut rng);
+        let mv = moves
+            .iter()
+            .find(|mv| {
+                let mut next_position = position.clone();
+                next_position.do_move((*mv).clone());
+                next_position.game_result().is_none()
+            })
+            .unwrap()
+            .clone();
+        position.do_move(mv);
+    }
+
+    let adjudication = Adjudication {
+        max_plies: 203,
+        nodes: 1000,
+        threshold: 0.9,
+    };
+    let settings = <MctsSetting<5>>::default();
+    let (game, _, _) = play_game_with_adjudication(
+        &settings,
+        &settings,
+        position.moves(),
+        0.1,
+        false,
+        Some(&adjudication),
+    );
+    assert_eq!(game.moves.len(), 203);
+    assert!(game.game_result.is_some());
+}
+
+#[test]
+fn play_game_writes_node_count_comments_test() {
+    let mut position = <Position<4>>::start_position();
+    do_moves_and_check_validity(&mut position, &["d4", "a1", "b1", "d3", "c1", "d2"]);
+
+    let settings = <MctsSetting<4>>::default();
+    let (game, _, _) = play_game(&settings, &settings, position.moves(), 0.1, false);
+
+    assert!(game.moves.iter().take(6).all(|mv| mv.comment.is_empty()));
+    assert!(game
+        .moves
+        .iter()
+        .skip(6)
+        .all(|mv| mv.comment.starts_with("100000 nodes, ") && mv.comment.ends_with(" ms")));
+
+    let mut ptn = vec![];
+    game.game_to_ptn(&mut ptn).unwrap();
+    let ptn = String::from_utf8(ptn).unwrap();
+    assert_eq!(
+        ptn.matches("{100000 nodes, ").count(),
+        game.moves.len() - 6,
+        "{}",
+        ptn
+    );
+}
+
+#[test]
+fn weak_moves_stay_within_max_rank_test() {
+    let position = <Position<4>>::start_position();
+    let moves_scores: Vec<(Move, f32)> = [
+        ("a1", 0.5),
+        ("b1", 0.2),
+        ("c1", 0.15),
+        ("d1", 0.1),
+        ("a2", 0.05),
+    ]
+    .iter()
+    .map(|(move_string, score)| (position.move_from_san(move_string).unwrap(), *score))
+    .collect();
+    let best_move = moves_scores[0].0.clone();
+    let allowed_moves = &moves_scores[0..3];
+
+    let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+    let settings = <MctsSetting<4>>::default();
+    for _ in 0..1000 {
+        let mv = select_move(&mut rng, &settings, 30, 1.0, &moves_scores);
+        assert_eq!(mv, best_move);
+    }
+
+    let settings = <MctsSetting<4>>::default().with_weak_moves(0.2, 3);
+    let mut weak_moves = 0;
+    for _ in 0..1000 {
+        let mv = select_move(&mut rng, &settings, 30, 1.0, &moves_scores);
+        assert!(allowed_moves
+            .iter()
+            .any(|(allowed_move, _)| *allowed_move == mv));
+        if mv != best_move {
+            weak_moves += 1;
+        }
+    }
+    assert!(
+        weak_moves > 100 && weak_moves < 300,
+        "{} weak moves",
+        weak_moves
+    );
+}
+
+#[test]
+fn training_binary_round_trip_test() {
+    let mut position = <Position<4>>::start_position();
+    do_moves_and_check_validity(&mut position, &["d4", "a1", "b1", "d3", "c1", "d2"]);
+
+    let settings = <MctsSetting<4>>::default();
+    let (mut game, move_scores, _) = play_game(&settings, &settings, position.moves(), 0.1, false);
+    game.tags
+        .push(("Site".to_string(), "Tiltak selfplay".to_string()));
+    game.moves[1].annotations = vec!["!", "?"];
+    game.moves[1].comment = "0.6 [clock 0:09:55]".to_string();
+    game.moves[1].clock = Some(Duration::from_secs(595));
+
+    let mut tps_game = game.clone();
+    tps_game.start_position = <Position<4>>::from_fen("x4/x2,2,x/x,1,21,x/x4 1 3")
+        .unwrap()
+        .with_komi(4);
+    tps_game.moves.truncate(0);
+    tps_game.game_result = None;
+
+    let games = vec![game, tps_game];
+    let move_scoress = vec![move_scores, vec![]];
+
+    let mut data = vec![];
+    write_training_binary(&games, &move_scoress, &mut data).unwrap();
+    let (read_games, read_move_scoress) = read_training_binary::<_, 4>(data.as_slice()).unwrap();
+
+    assert_eq!(read_games.len(), games.len());
+    for (read_game, game) in read_games.iter().zip(games.iter()) {
+        assert_eq!(read_game.start_position, game.start_position);
+        assert_eq!(read_game.moves, game.moves);
+        assert_eq!(read_game.game_result, game.game_result);
+        assert_eq!(read_game.tags, game.tags);
+    }
+    assert_eq!(read_move_scoress, move_scoress);
+
+    assert!(read_training_binary::<_, 5>(data.as_slice()).is_err());
+
+    // Moves are checked for legality when reading
+    let mut illegal_game = games[0].clone();
+    illegal_game.moves[1] = illegal_game.moves[0].clone();
+    let mut data = vec![];
+    write_training_binary(&[illegal_game], &move_scoress[0..1], &mut data).unwrap();
+    assert!(read_training_binary::<_, 4>(data.as_slice()).is_err());
+}
+
+#[test]
+fn positions_file_round_trip_test() {
+    let games = ptn_parser::parse_ptn::<Position<5>>(
+        "1. a1 e5 2. c3 d3 3. c4 d4 4. c2 1-0\n\n[Komi \"2.5\"]\n\n1. e1 a5 2. Cc3 Cc4 3. Sb3 c4< 0-1",
+    )
+    .unwrap();
+    assert_eq!(games.len(), 2);
+    let (positions, results) = positions_and_results_from_games(games);
+
+    let mut data = vec![];
+    write_positions(&positions, &results, &mut data).unwrap();
+    assert_eq!(
+        String::from_utf8(data.clone()).unwrap().lines().count(),
+        positions.len()
+    );
+    let (positions_read, results_read) = read_positions::<_, 5>(data.as_slice()).unwrap();
+
+    assert_eq!(positions_read, positions);
+    assert_eq!(positions_read.last().unwrap().komi(), 5);
+    assert_eq!(results_read, results);
+    for (read_position, position) in positions_read.iter().zip(positions.iter()) {
+        let mut coefficients = [0.0; NUM_VALUE_PARAMS_5S];
+        let mut read_coefficients = [0.0; NUM_VALUE_PARAMS_5S];
+        position.static_eval_coefficients(&mut coefficients);
+        read_position.static_eval_coefficients(&mut read_coefficients);
+        assert_eq!(read_coefficients, coefficients);
+    }
+
+    assert!(read_positions::<_, 5>("x5/x5/x5/x5/x5 1 1".as_bytes()).is_err());
+    assert!(read_positions::<_, 5>("x5/x5/x5/x5/x5 1 1;2-0".as_bytes()).is_err());
+    assert!(read_positions::<_, 5>("x5/x5/x5/x5/x5 1 1;1-0;2.25".as_bytes()).is_err());
+    let (positions_without_komi, _) =
+        read_positions::<_, 5>("x5/x5/x5/x5/x5 1 1;1-0".as_bytes()).unwrap();
+    assert_eq!(positions_without_komi[0].komi(), 0);
+}
+
+#[test]
+fn gradient_descent_reports_every_eta_test() {
+    let coefficient_sets: Vec<[f32; 2]> = (0..100)
+        .map(|i| [1.0, (i % 10) as f32 / 10.0 - 0.5])
+        .collect();
+    let results: Vec<f32> = coefficient_sets
+        .iter()
+        .map(|coefficients| 0.5 + coefficients[1] * 0.6)
+        .collect();
+
+    let (_, report) = gradient_descent_with_report(
+        &coefficient_sets,
+        &results,
+        &coefficient_sets,
+        &results,
+        &[0.0, 0.0],
+        1.0,
+    );
+
+    assert_eq!(report.phases.len(), 4);
+    assert_eq!(report.phases[0].eta, 1.0);
+    for window in report.phases.windows(2) {
+        assert!(window[1].lowest_error <= window[0].lowest_error);
+    }
+    assert!(report.phases.iter().all(|phase| phase.iterations > 0));
+}
+
+#[test]
+fn weighted_gradient_descent_matches_repeated_samples_test() {
+    let coefficient_sets: Vec<[f32; 2]> = (0..10).map(|i| [1.0, i as f32 / 10.0 - 0.5]).collect();
+    let results: Vec<f32> = (0..10)
+        .map(|i| if i % 3 == 0 { 1.0 } else { 0.0 })
+        .collect();
+    let weights: Vec<f32> = (0..10).map(|i| (i % 4 + 1) as f32).collect();
+
+    let mut repeated_coefficient_sets = vec![];
+    let mut repeated_results = vec![];
+    for ((coefficients, result), weight) in coefficient_sets.iter().zip(&results).zip(&weights) {
+        for _ in 0..*weight as usize {
+            repeated_coefficient_sets.push(*coefficients);
+            repeated_results.push(*result);
+        }
+    }
+
+    let weighted_params = weighted_gradient_descent(
+        &coefficient_sets,
+        &results,
+        &weights,
+        &coefficient_sets,
+        &results,
+        &weights,
+        &[0.0, 0.0],
+        1.0,
+    );
+    let repeated_params = gradient_descent(
+        &repeated_coefficient_sets,
+        &repeated_results,
+        &repeated_coefficient_sets,
+        &repeated_results,
+        &[0.0, 0.0],
+        1.0,
+    );
+    for (weighted_param, repeated_param) in weighted_params.iter().zip(&repeated_params) {
+        assert!(
+            (weighted_param - repeated_param).abs() < 0.01,
+            "{:?} vs {:?}",
+            weighted_params,
+            repeated_params
+        );
+    }
+}
+
+#[test]
+fn identical_settings_agree_on_every_move_test() {
+    let games =
+        ptn_parser::parse_ptn::<Position<5>>("1. a1 e5 2. c3 d3 3. c4 d4 4. c2 1-0").unwrap();
+    let settings = <MctsSetting<5>>::default();
+
+    let agreement = move_agreement(&games[0], &settings, &settings, 1000);
+
+    assert_eq!(agreement.len(), games[0].moves.len());
+    assert!(agreement
+        .iter()
+        .all(|(_, move_a, move_b, agree)| *agree && move_a == move_b));
+    assert_eq!(agreement[3].0, 3);
+}
+
+#[test]
+fn opening_book_coverage_test() {
+    let games = ptn_parser::parse_ptn::<Position<5>>(
+        "1. a1 e5 2. c3 *\n\n1. a1 e5 2. d3 *\n\n1. e5 a1 2. c3 *\n\n1. a1 *\n\n",
+    )
+    .unwrap();
+    assert_eq!(games.len(), 4);
+
+    let book = OpeningBook::from_games(&games, 2);
+    let report = book.coverage_report();
+
+    assert_eq!(report, vec![(0, 1, 4), (1, 2, 3), (2, 2, 2)]);
+}
+
+#[test]
+fn tune_cpuct_returns_candidate_test() {
+    let candidates = [0.5, 2.0];
+    let best = tune_cpuct(&<MctsSetting<4>>::default(), &candidates, 1);
+    assert!(candidates.contains(&best));
+}
+
+type MoveScoresForGame = Vec<Vec<(Move, f32)>>;
+
+/// A few short games, with move scores that give the played move most of the visits
+fn tuning_test_games() -> (Vec<Game<Position<4>>>, Vec<MoveScoresForGame>) {
+    let games = ptn_parser::parse_ptn::<Position<4>>(
+        "1. a1 d4 2. b2 c3 3. b1 c4 4. b3 c2 5. b4 R-0\n\n\
+         1. d4 a1 2. b2 c3 3. a2 c4 4. a3 c2 5. d3 c1 0-R\n\n\
+         1. a1 d4 2. c2 b3 3. c1 b4 4. c3 b2 5. d2 b1 0-R\n\n\
+         1. a4 d1 2. b3 c2 3. a3 c3 4. a2 b1 5. a1 1-0\n\n",
+    )
+    .unwrap();
+    assert_eq!(games.len(), 4);
+
+    // Give the played move most of the visits, and spread the rest over a few other moves
+    let move_scoress = games
+        .iter()
+        .map(|game| {
+            game.iter_positions()
+                .map(|(position, ptn_move)| {
+                    let mut legal_moves = vec![];
+                    position.generate_moves(&mut legal_moves);
+                    let mut move_scores = vec![(ptn_move.mv.clone(), 0.7)];
+                    move_scores.extend(
+                        legal_moves
+                            .into_iter()
+                            .filter(|mv| *mv != ptn_move.mv)
+                            .take(3)
+                            .map(|mv| (mv, 0.1)),
+                    );
+                    move_scores
+                })
+                .collect()
+        })
+        .collect();
+    (games, move_scoress)
+}
+
+#[test]
+fn parallel_and_sequential_tuning_agree_test() {
+    let (games, move_scoress) = tuning_test_games();
+
+    let initial_value_params = [0.01; NUM_VALUE_PARAMS_4S];
+    let initial_policy_params = [0.01; NUM_POLICY_PARAMS_4S];
+
+    let parallel = tune_value_and_policy(
+        &games,
+        &move_scoress,
+        &initial_value_params,
+        &initial_policy_params,
+        None,
+    )
+    .unwrap();
+    let sequential = tune_value_and_policy_sequential(
+        &games,
+        &move_scoress,
+        &initial_value_params,
+        &initial_policy_params,
+        None,
+    )
+    .unwrap();
+
+    assert_eq!(parallel.0, sequential.0);
+    assert_eq!(parallel.1, sequential.1);
+}
+
+#[test]
+fn tuning_shuffle_seed_test() {
+    let (games, move_scoress) = tuning_test_games();
+
+    let initial_value_params = [0.01; NUM_VALUE_PARAMS_4S];
+    let initial_policy_params = [0.01; NUM_POLICY_PARAMS_4S];
+
+    let tune_with_seed = |seed| {
+        tune_value_and_policy(
+            &games,
+            &move_scoress,
+            &initial_value_params,
+            &initial_policy_params,
+            Some(seed),
+        )
+        .unwrap()
+    };
+
+    let first = tune_with_seed(1);
+    assert_eq!(first, tune_with_seed(1));
+
+    assert!((2..10).any(|seed| tune_with_seed(seed) != first));
+}
+
+#[test]
+fn round_robin_crosstable_test() {
+    let value_params = <Position<4>>::value_params().to_vec();
+    let policy_params = <Position<4>>::policy_params().to_vec();
+    let param_sets: Vec<(Vec<f32>, Vec<f32>)> = [1.0, 0.5, 2.0]
+        .iter()
+        .map(|factor| {
+            (
+                value_params.iter().map(|param| param * factor).collect(),
+                policy_params.clone(),
+            )
+        })
+        .collect();
+
+    let cross_table = round_robin::<4>(&param_sets, 2);
+
+    assert_eq!(cross_table.num_players(), 3);
+    for i in 0..3 {
+        for j in 0..3 {
+            if i != j {
+                assert_eq!(cross_table.scores[i][j] + cross_table.scores[j][i], 2.0);
+            }
+        }
+    }
+    let ranking = cross_table.ranking();
+    assert_eq!(ranking.len(), 3);
+    assert!(ranking.windows(2).all(|pair| pair[0].1 >= pair[1].1));
+    assert_eq!(ranking.iter().map(|(_, score, _)| score).sum::<f32>(), 6.0);
+    assert_eq!(cross_table.to_string().lines().count(), 8);
+
+    assert_eq!(elo_difference(0.5), 0.0);
+    assert!(elo_difference(0.75) > 190.0 && elo_difference(0.75) < 192.0);
+}
+
+#[test]
+fn aggregate_value_positions_test() {
+    let mut position = <Position<5>>::default();
+    do_moves_and_check_validity(&mut position, &["a1", "e5", "c3"]);
+    let mut other_position = <Position<5>>::default();
+    do_moves_and_check_validity(&mut other_position, &["a1", "e5", "c2"]);
+
+    // The same position appears twice, once rotated
+    let positions = vec![
+        position.clone(),
+        other_position,
+        position.transform(Symmetry::Rotate90),
+    ];
+    let results = vec![
+        GameResult::WhiteWin,
+        GameResult::WhiteWin,
+        GameResult::BlackWin,
+    ];
+
+    let (coefficient_sets, aggregated_results, weights) =
+        aggregate_value_positions::<5, NUM_VALUE_PARAMS_5S>(&positions, &results);
+
+    assert_eq!(coefficient_sets.len(), 2);
+    assert_eq!(aggregated_results, vec![0.5, 1.0]);
+    assert_eq!(weights, vec![2.0, 1.0]);
+}
@@ -0,0 +1,40 @@
+//! Compare the decisions of two search settings, to see how a parameter change affects play.
+
+use board_game_traits::Position as PositionTrait;
+
+use crate::position::{Move, Position};
+use crate::ptn::Game;
+use crate::search::{MctsSetting, MonteCarloTree, MIN_MCTS_NODES};
+
+/// Search every position in `game` with both settings, for `nodes` nodes each.
+/// For each ply, returns the move chosen by each setting, and whether they agree.
+/// The final position is skipped if the game is over.
+pub fn move_agreement<const S: usize>(
+    game: &Game<Position<S>>,
+    settings_a: &MctsSetting<S>,
+    settings_b: &MctsSetting<S>,
+    nodes: u64,
+) -> Vec<(usize, Move, Move, bool)> {
+    game.iter_positions()
+        .enumerate()
+        .filter(|(_, (position, _))| position.game_result().is_none())
+        .map(|(ply, (position, _))| {
+            let move_a = best_move(&position, settings_a, nodes);
+            let move_b = best_move(&position, settings_b, nodes);
+            let agree = move_a == move_b;
+            (ply, move_a, move_b, agree)
+        })
+        .collect()
+}
+
+fn best_move<const S: usize>(
+    position: &Position<S>,
+    settings: &MctsSetting<S>,
+    nodes: u64,
+) -> Move {
+    let mut tree = MonteCarloTree::with_settings(position.clone(), settings.clone());
+    for _ in 0..nodes.max(MIN_MCTS_NODES) {
+        tree.select();
+    }
+    tree.best_move().0
+}
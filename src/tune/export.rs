@@ -0,0 +1,340 @@
+//! Compact binary format for self-play training data.
+//!
+//! This is much smaller and faster to read than the PTN and move scores text files.
+//! All integers are little-endian. The file starts with the magic bytes `TKTD`, a version byte and the board size,
+//! followed by the number of games as a `u32`. Each game is stored as:
+//! * The start position in TPS as a length-prefixed string, followed by its komi in half-flats as an `i8`
+//! * The game's tags, as length-prefixed strings
+//! * The game result, as a single byte
+//! * The number of moves as a `u16`. Each move is stored in the encoding of `Move::to_u16`, followed by its annotations and its comment.
+//!   The annotations are a `u8` count, followed by the index of each annotation in `POSSIBLE_MOVE_ANNOTATIONS`
+//! * The number of move scores as a `u16`, followed by the move scores for each ply. Each of these is a `u16` count, followed by the moves and their `f32` scores
+//!
+//! Like in PTN, the clock of each move is read from its comment.
+//!
+//! For value tuning alone, positions can also be stored as text, one per line, as the TPS, the game result and the komi in flats, separated by semicolons.
+//! For example `x4/x2,2,x/x,1,21,x/x4 1 3;1-0;2.5`. If the komi is missing, it is zero
+
+use std::io;
+use std::io::{BufRead, Read, Write};
+use std::iter;
+
+use board_game_traits::{GameResult, Position as PositionTrait};
+use pgn_traits::PgnPosition;
+
+use crate::binary_io::{invalid_data, read_bytes, read_move, read_u16, read_u8};
+use crate::position::{Move, Position};
+use crate::ptn::ptn_parser::parse_clock;
+use crate::ptn::{Game, PtnMove};
+use crate::tune::play_match::MoveScores;
+
+const MAGIC: &[u8; 4] = b"TKTD";
+const VERSION: u8 = 3;
+
+/// Games, along with the move scores for every ply of each game
+pub type TrainingData<const S: usize> = (Vec<Game<Position<S>>>, Vec<Vec<MoveScores>>);
+
+/// Write games and their move scores to `writer`.
+/// `move_scores` must contain one entry for each game.
+pub fn write_training_binary<W: Write, const S: usize>(
+    games: &[Game<Position<S>>],
+    move_scores: &[Vec<MoveScores>],
+    mut writer: W,
+) -> io::Result<()> {
+    if games.len() != move_scores.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Got {} games, but move scores for {} games",
+                games.len(),
+                move_scores.len()
+            ),
+        ));
+    }
+    writer.write_all(MAGIC)?;
+    writer.write_all(&[VERSION, S as u8])?;
+    writer.write_all(&(games.len() as u32).to_le_bytes())?;
+
+    for (game, move_scores) in games.iter().zip(move_scores) {
+        write_string(&mut writer, &game.start_position.to_fen())?;
+        writer.write_all(&game.start_position.komi().to_le_bytes())?;
+        write_length(&mut writer, game.tags.len())?;
+        for (tag, value) in game.tags.iter() {
+            write_string(&mut writer, tag)?;
+            write_string(&mut writer, value)?;
+        }
+        let result_byte: u8 = match game.game_result {
+            None => 0,
+            Some(GameResult::WhiteWin) => 1,
+            Some(GameResult::BlackWin) => 2,
+            Some(GameResult::Draw) => 3,
+        };
+        writer.write_all(&[result_byte])?;
+
+        write_length(&mut writer, game.moves.len())?;
+        for PtnMove {
+            mv,
+            annotations,
+            comment,
+            ..
+        } in game.moves.iter()
+        {
+            writer.write_all(&mv.to_u16().to_le_bytes())?;
+            write_annotations::<_, S>(&mut writer, annotations)?;
+            write_string(&mut writer, comment)?;
+        }
+
+        write_length(&mut writer, move_scores.len())?;
+        for scores in move_scores {
+            write_length(&mut writer, scores.len())?;
+            for (mv, score) in scores {
+                writer.write_all(&mv.to_u16().to_le_bytes())?;
+                writer.write_all(&score.to_le_bytes())?;
+            }
+        }
+    }
+    writer.flush()
+}
+
+/// Read games and move scores written by `write_training_binary`.
+pub fn read_training_binary<R: Read, const S: usize>(mut reader: R) -> io::Result<TrainingData<S>> {
+    let mut header = [0; 6];
+    reader.read_exact(&mut header)?;
+    if &header[0..4] != MAGIC || header[4] != VERSION {
+        return Err(invalid_data("Not a training data file".to_string()));
+    }
+    if header[5] as usize != S {
+        return Err(invalid_data(format!(
+            "Training data is for {}s, expected {}s",
+            header[5], S
+        )));
+    }
+    let num_games = read_u32(&mut reader)?;
+
+    let mut games = vec![];
+    let mut move_scoress = vec![];
+    for _ in 0..num_games {
+        let start_position = Position::from_fen(&read_string(&mut reader)?)
+            .map_err(|err| invalid_data(err.to_string()))?
+            .with_komi(i8::from_le_bytes(read_bytes(&mut reader)?));
+        let num_tags = read_u16(&mut reader)?;
+        let tags = (0..num_tags)
+            .map(|_| Ok((read_string(&mut reader)?, read_string(&mut reader)?)))
+            .collect::<io::Result<Vec<_>>>()?;
+        let game_result = match read_u8(&mut reader)? {
+            0 => None,
+            1 => Some(GameResult::WhiteWin),
+            2 => Some(GameResult::BlackWin),
+            3 => Some(GameResult::Draw),
+            byte => return Err(invalid_data(format!("Invalid game result {}", byte))),
+        };
+
+        let num_moves = read_u16(&mut reader)?;
+        let moves = (0..num_moves)
+            .map(|_| {
+                let mv = read_move::<_, S>(&mut reader)?;
+                let annotations = read_annotations::<_, S>(&mut reader)?;
+                let comment = read_string(&mut reader)?;
+                Ok(PtnMove {
+                    mv,
+                    annotations,
+                    clock: parse_clock(&comment),
+                    comment,
+                })
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let num_plies = read_u16(&mut reader)?;
+        if num_plies > num_moves {
+            return Err(invalid_data(format!(
+                "Move scores for {} plies, but only {} moves",
+                num_plies, num_moves
+            )));
+        }
+        let mut move_scores = Vec::with_capacity(num_plies as usize);
+        for _ in 0..num_plies {
+            let num_scores = read_u16(&mut reader)?;
+            let scores = (0..num_scores)
+                .map(|_| {
+                    let mv = read_move::<_, S>(&mut reader)?;
+                    let mut bytes = [0; 4];
+                    reader.read_exact(&mut bytes)?;
+                    Ok((mv, f32::from_le_bytes(bytes)))
+                })
+                .collect::<io::Result<MoveScores>>()?;
+            move_scores.push(scores);
+        }
+        check_moves(&start_position, &moves, &move_scores)?;
+
+        games.push(Game {
+            start_position,
+            moves,
+            game_result,
+            tags,
+        });
+        move_scoress.push(move_scores);
+    }
+    Ok((games, move_scoress))
+}
+
+/// Check that every move of the game, and every move with a score, is legal in its position.
+fn check_moves<const S: usize>(
+    start_position: &Position<S>,
+    moves: &[PtnMove<Move>],
+    move_scores: &[MoveScores],
+) -> io::Result<()> {
+    let mut position = start_position.clone();
+    let mut legal_moves = vec![];
+    for (ply, PtnMove { mv, .. }) in moves.iter().enumerate() {
+        position.generate_moves(&mut legal_moves);
+        let scored_moves = move_scores
+            .get(ply)
+            .into_iter()
+            .flat_map(|scores| scores.iter().map(|(mv, _)| mv));
+        if let Some(illegal_move) = iter::once(mv)
+            .chain(scored_moves)
+            .find(|mv| !legal_moves.contains(mv))
+        {
+            return Err(invalid_data(format!(
+                "Illegal move {} in {}",
+                illegal_move.to_string::<S>(),
+                position.to_fen()
+            )));
+        }
+        legal_moves.clear();
+        position.do_move(mv.clone());
+    }
+    Ok(())
+}
+
+/// Write positions and the results of their games to `writer`, one position per line.
+/// `results` must contain one entry for each position.
+pub fn write_positions<W: Write, const S: usize>(
+    positions: &[Position<S>],
+    results: &[GameResult],
+    mut writer: W,
+) -> io::Result<()> {
+    if positions.len() != results.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Got {} positions, but {} results",
+                positions.len(),
+                results.len()
+            ),
+        ));
+    }
+    for (position, result) in positions.iter().zip(results) {
+        let result_string = match result {
+            GameResult::WhiteWin => "1-0",
+            GameResult::BlackWin => "0-1",
+            GameResult::Draw => "1/2-1/2",
+        };
+        writeln!(
+            writer,
+            "{};{};{}",
+            position.to_fen(),
+            result_string,
+            position.komi_string()
+        )?;
+    }
+    writer.flush()
+}
+
+/// Read positions and results written by `write_positions`. Empty lines are skipped.
+pub fn read_positions<R: BufRead, const S: usize>(
+    reader: R,
+) -> io::Result<(Vec<Position<S>>, Vec<GameResult>)> {
+    let mut positions = vec![];
+    let mut results = vec![];
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let mut columns = line.split(';');
+        let tps = columns.next().unwrap_or_default();
+        let result_string = columns
+            .next()
+            .ok_or_else(|| invalid_data(format!("Missing game result in \"{}\"", line)))?;
+        let komi = match columns.next() {
+            Some(komi_string) => Position::<S>::parse_komi(komi_string)
+                .ok_or_else(|| invalid_data(format!("Invalid komi in \"{}\"", line)))?,
+            None => 0,
+        };
+        if columns.next().is_some() {
+            return Err(invalid_data(format!("Too many columns in \"{}\"", line)));
+        }
+        positions.push(
+            Position::from_fen(tps)
+                .map_err(|err| invalid_data(err.to_string()))?
+                .with_komi(komi),
+        );
+        results.push(match result_string.trim() {
+            "1-0" => GameResult::WhiteWin,
+            "0-1" => GameResult::BlackWin,
+            "1/2-1/2" => GameResult::Draw,
+            s => return Err(invalid_data(format!("Invalid game result {}", s))),
+        });
+    }
+    Ok((positions, results))
+}
+
+fn write_length<W: Write>(writer: &mut W, length: usize) -> io::Result<()> {
+    if length > u16::MAX as usize {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Length {} is too large to store", length),
+        ));
+    }
+    writer.write_all(&(length as u16).to_le_bytes())
+}
+
+fn write_string<W: Write>(writer: &mut W, string: &str) -> io::Result<()> {
+    write_length(writer, string.len())?;
+    writer.write_all(string.as_bytes())
+}
+
+fn write_annotations<W: Write, const S: usize>(
+    writer: &mut W,
+    annotations: &[&str],
+) -> io::Result<()> {
+    writer.write_all(&[annotations.len() as u8])?;
+    for annotation in annotations {
+        let index = <Position<S>>::POSSIBLE_MOVE_ANNOTATIONS
+            .iter()
+            .position(|possible_annotation| possible_annotation == annotation)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Unknown move annotation \"{}\"", annotation),
+                )
+            })?;
+        writer.write_all(&[index as u8])?;
+    }
+    Ok(())
+}
+
+fn read_annotations<R: Read, const S: usize>(reader: &mut R) -> io::Result<Vec<&'static str>> {
+    let num_annotations = read_u8(reader)?;
+    (0..num_annotations)
+        .map(|_| {
+            let index = read_u8(reader)?;
+            <Position<S>>::POSSIBLE_MOVE_ANNOTATIONS
+                .get(index as usize)
+                .copied()
+                .ok_or_else(|| invalid_data(format!("Invalid move annotation {}", index)))
+        })
+        .collect()
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    Ok(u32::from_le_bytes(read_bytes(reader)?))
+}
+
+fn read_string<R: Read>(reader: &mut R) -> io::Result<String> {
+    let length = read_u16(reader)?;
+    let mut bytes = vec![0; length as usize];
+    reader.read_exact(&mut bytes)?;
+    String::from_utf8(bytes).map_err(|err| invalid_data(err.to_string()))
+}
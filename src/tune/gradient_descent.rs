@@ -1,7 +1,22 @@
-use log::trace;
+use log::{debug, trace};
 use rayon::prelude::*;
 use std::time::Instant;
 
+/// Summary of a gradient descent run, with one entry for each learning rate in the schedule
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct GdReport {
+    pub phases: Vec<EtaPhase>,
+}
+
+/// Result of running gradient descent with a single learning rate
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct EtaPhase {
+    pub eta: f32,
+    /// Lowest test error seen so far, including earlier phases
+    pub lowest_error: f32,
+    pub iterations: usize,
+}
+
 pub fn gradient_descent<const N: usize>(
     coefficient_sets: &[[f32; N]],
     results: &[f32],
@@ -10,8 +25,80 @@ pub fn gradient_descent<const N: usize>(
     params: &[f32; N],
     initial_learning_rate: f32,
 ) -> [f32; N] {
+    gradient_descent_with_report(
+        coefficient_sets,
+        results,
+        test_coefficient_sets,
+        test_results,
+        params,
+        initial_learning_rate,
+    )
+    .0
+}
+
+/// Like `gradient_descent`, but also returns a report of each learning rate phase.
+/// The phase results are also logged at debug level.
+pub fn gradient_descent_with_report<const N: usize>(
+    coefficient_sets: &[[f32; N]],
+    results: &[f32],
+    test_coefficient_sets: &[[f32; N]],
+    test_results: &[f32],
+    params: &[f32; N],
+    initial_learning_rate: f32,
+) -> ([f32; N], GdReport) {
+    weighted_gradient_descent_with_report(
+        coefficient_sets,
+        results,
+        &vec![1.0; coefficient_sets.len()],
+        test_coefficient_sets,
+        test_results,
+        &vec![1.0; test_coefficient_sets.len()],
+        params,
+        initial_learning_rate,
+    )
+}
+
+/// Like `gradient_descent`, but each sample counts `weight` times towards the slope and the error.
+/// Used for samples that aggregate several occurrences of the same position.
+#[allow(clippy::too_many_arguments)]
+pub fn weighted_gradient_descent<const N: usize>(
+    coefficient_sets: &[[f32; N]],
+    results: &[f32],
+    weights: &[f32],
+    test_coefficient_sets: &[[f32; N]],
+    test_results: &[f32],
+    test_weights: &[f32],
+    params: &[f32; N],
+    initial_learning_rate: f32,
+) -> [f32; N] {
+    weighted_gradient_descent_with_report(
+        coefficient_sets,
+        results,
+        weights,
+        test_coefficient_sets,
+        test_results,
+        test_weights,
+        params,
+        initial_learning_rate,
+    )
+    .0
+}
+
+#[allow(clippy::too_many_arguments)]
+fn weighted_gradient_descent_with_report<const N: usize>(
+    coefficient_sets: &[[f32; N]],
+    results: &[f32],
+    weights: &[f32],
+    test_coefficient_sets: &[[f32; N]],
+    test_results: &[f32],
+    test_weights: &[f32],
+    params: &[f32; N],
+    initial_learning_rate: f32,
+) -> ([f32; N], GdReport) {
     assert_eq!(coefficient_sets.len(), results.len());
+    assert_eq!(coefficient_sets.len(), weights.len());
     assert_eq!(test_coefficient_sets.len(), test_results.len());
+    assert_eq!(test_coefficient_sets.len(), test_weights.len());
 
     let start_time = Instant::now();
     let beta = 0.95;
@@ -19,7 +106,7 @@ pub fn gradient_descent<const N: usize>(
     // If error is not reduced this number of times, reduce eta, or abort if eta is already low
     const MAX_TRIES: usize = 100;
 
-    let initial_error = average_error(test_coefficient_sets, test_results, params);
+    let initial_error = average_error(test_coefficient_sets, test_results, test_weights, params);
     println!(
         "Running gradient descent on {} positions and {} test positions",
         coefficient_sets.len(),
@@ -29,11 +116,12 @@ pub fn gradient_descent<const N: usize>(
     println!("Initial test error: {}", initial_error);
     println!(
         "Initial training error: {}",
-        average_error(coefficient_sets, results, params)
+        average_error(coefficient_sets, results, weights, params)
     );
 
     let mut lowest_error = initial_error;
     let mut best_parameter_set = *params;
+    let mut report = GdReport::default();
 
     for eta in [
         initial_learning_rate,
@@ -49,8 +137,10 @@ pub fn gradient_descent<const N: usize>(
 
         let mut iterations_since_improvement = 0;
         let mut iterations_since_large_improvement = 0;
+        let mut iterations = 0;
         loop {
-            let slopes = calc_slope(coefficient_sets, results, &parameter_set);
+            iterations += 1;
+            let slopes = calc_slope(coefficient_sets, results, weights, &parameter_set);
             trace!("Slopes: {:?}", slopes);
             gradients
                 .iter_mut()
@@ -64,7 +154,12 @@ pub fn gradient_descent<const N: usize>(
                 .for_each(|(param, gradient)| *param -= gradient * eta);
             trace!("New parameters: {:?}", parameter_set);
 
-            let error = average_error(test_coefficient_sets, test_results, &parameter_set);
+            let error = average_error(
+                test_coefficient_sets,
+                test_results,
+                test_weights,
+                &parameter_set,
+            );
             trace!("Error now {}, eta={}\n", error, eta);
 
             if error < lowest_error {
@@ -87,6 +182,15 @@ pub fn gradient_descent<const N: usize>(
                 }
             }
         }
+        debug!(
+            "Finished eta = {} after {} iterations, lowest error {}",
+            eta, iterations, lowest_error
+        );
+        report.phases.push(EtaPhase {
+            eta: *eta,
+            lowest_error,
+            iterations,
+        });
     }
 
     let elapsed = start_time.elapsed();
@@ -100,19 +204,21 @@ pub fn gradient_descent<const N: usize>(
             .map(|f| *f as f32)
             .collect::<Vec<f32>>()
     );
-    best_parameter_set
+    (best_parameter_set, report)
 }
 
 /// For each parameter, calculate the slope for that dimension
 fn calc_slope<const N: usize>(
     coefficient_sets: &[[f32; N]],
     results: &[f32],
+    weights: &[f32],
     params: &[f32; N],
 ) -> [f32; N] {
     let mut slopes = coefficient_sets
         .par_iter()
         .zip(results)
-        .map(|(coefficients, result)| {
+        .zip(weights)
+        .map(|((coefficients, result), weight)| {
             let estimated_result = eval_from_params(coefficients, params);
             let estimated_sigmoid = sigmoid(estimated_result);
             let derived_sigmoid_result = sigmoid_derived(estimated_result);
@@ -122,7 +228,10 @@ fn calc_slope<const N: usize>(
                 .iter_mut()
                 .zip(coefficients)
                 .for_each(|(gradient, coefficient)| {
-                    *gradient = (estimated_sigmoid - result) * derived_sigmoid_result * *coefficient
+                    *gradient = (estimated_sigmoid - result)
+                        * derived_sigmoid_result
+                        * *coefficient
+                        * weight
                 });
             gradients_for_this_training_sample
         })
@@ -156,8 +265,9 @@ fn calc_slope<const N: usize>(
             },
         );
 
+    let total_weight = weights.iter().map(|weight| *weight as f64).sum::<f64>();
     for slope in slopes.iter_mut() {
-        *slope /= coefficient_sets.len() as f64;
+        *slope /= total_weight;
     }
     let mut f32_slopes = [0.0; N];
     for (f64_slope, slope) in f32_slopes.iter_mut().zip(&slopes) {
@@ -166,22 +276,25 @@ fn calc_slope<const N: usize>(
     f32_slopes
 }
 
-/// Mean squared error of the parameter set, measured against given results and positions
+/// Mean squared error of the parameter set, measured against given results and positions, and weighted by `weights`
 fn average_error<const N: usize>(
     coefficient_sets: &[[f32; N]],
     results: &[f32],
+    weights: &[f32],
     params: &[f32; N],
 ) -> f32 {
     assert_eq!(coefficient_sets.len(), results.len());
+    let total_weight = weights.iter().map(|weight| *weight as f64).sum::<f64>();
     coefficient_sets
         .into_par_iter()
         .zip(results)
-        .map(|(coefficients, game_result)| {
-            (sigmoid(eval_from_params(coefficients, params)) - game_result).powf(2.0)
+        .zip(weights)
+        .map(|((coefficients, game_result), weight)| {
+            (sigmoid(eval_from_params(coefficients, params)) - game_result).powf(2.0) * weight
         })
         .map(|f| f as f64)
         .sum::<f64>() as f32
-        / (coefficient_sets.len() as f32)
+        / (total_weight as f32)
 }
 
 pub fn eval_from_params<const N: usize>(coefficients: &[f32; N], params: &[f32; N]) -> f32 {
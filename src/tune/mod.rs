@@ -1,5 +1,9 @@
+pub mod compare;
+pub mod export;
 pub mod gradient_descent;
-mod openings;
+pub mod openings;
 pub mod play_match;
+pub mod search_params;
 pub mod spsa;
+pub mod tournament;
 pub mod training;
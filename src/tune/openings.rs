@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::fs;
 use std::io;
 use std::io::BufRead;
@@ -7,6 +8,7 @@ use pgn_traits::PgnPosition;
 
 use crate::position::Move;
 use crate::position::Position;
+use crate::ptn::{Game, PtnMove};
 
 pub fn openings_from_file<const S: usize>(path: &str) -> io::Result<Vec<Vec<Move>>> {
     let reader = io::BufReader::new(fs::File::open(path)?);
@@ -34,3 +36,44 @@ pub fn openings_from_file<const S: usize>(path: &str) -> io::Result<Vec<Vec<Move
     }
     Ok(openings)
 }
+
+/// The positions reached in the opening of a set of games, and how often each one was reached
+pub struct OpeningBook<const S: usize> {
+    positions_per_ply: Vec<HashMap<Position<S>, u64>>,
+}
+
+impl<const S: usize> OpeningBook<S> {
+    /// Build a book from the first `max_plies` plies of each game. The start position is ply 0.
+    pub fn from_games(games: &[Game<Position<S>>], max_plies: usize) -> Self {
+        let mut positions_per_ply: Vec<HashMap<Position<S>, u64>> =
+            vec![HashMap::new(); max_plies + 1];
+        for game in games {
+            let mut position = game.start_position.clone();
+            *positions_per_ply[0].entry(position.clone()).or_default() += 1;
+            for (ply, PtnMove { mv, .. }) in game.moves.iter().take(max_plies).enumerate() {
+                position.do_move(mv.clone());
+                *positions_per_ply[ply + 1]
+                    .entry(position.clone())
+                    .or_default() += 1;
+            }
+        }
+        OpeningBook { positions_per_ply }
+    }
+
+    /// For each ply, returns the number of distinct positions in the book, and the number of games reaching the most common one.
+    /// Plies that no game reaches are omitted.
+    pub fn coverage_report(&self) -> Vec<(usize, usize, u64)> {
+        self.positions_per_ply
+            .iter()
+            .enumerate()
+            .filter(|(_, positions)| !positions.is_empty())
+            .map(|(ply, positions)| {
+                (
+                    ply,
+                    positions.len(),
+                    positions.values().copied().max().unwrap_or_default(),
+                )
+            })
+            .collect()
+    }
+}
@@ -1,61 +1,148 @@
-use board_game_traits::{Color, Position as PositionTrait};
+use std::time::{Duration, Instant};
+
+use board_game_traits::{Color, GameResult, Position as PositionTrait};
 use rand::seq::SliceRandom;
+use rand::Rng;
 
 use crate::position::Move;
 use crate::position::Position;
 use crate::position::Role;
 use crate::ptn::{Game, PtnMove};
 use crate::search;
-use crate::search::{MctsSetting, Score};
+use crate::search::{GameResultForUs, MctsSetting, Score};
+
+/// Visit fractions for each move from a single search
+pub type MoveScores = Vec<(Move, Score)>;
+
+/// Search statistics for a single ply of a game.
+/// Plies from the opening are not searched, and have zero `nodes`.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct MoveStats {
+    pub nodes: u64,
+    /// Fraction of the root's visits that went to the most visited move
+    pub top_move_visit_fraction: Score,
+    /// Winning probability for the side to move, according to the search
+    pub root_value: Score,
+    pub time: Duration,
+}
+
+impl MoveStats {
+    /// A PTN comment describing the search effort behind the move
+    pub fn comment(&self) -> String {
+        format!("{} nodes, {} ms", self.nodes, self.time.as_millis())
+    }
+}
+
+/// Settings for adjudicating long self-play games with a deeper search, instead of leaving them unfinished
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Adjudication {
+    /// Adjudicate the game once this many plies have been played
+    pub max_plies: usize,
+    /// The number of nodes of the adjudication search
+    pub nodes: u64,
+    /// The winning probability the search must give one side for it to be adjudicated the winner.
+    /// If neither side reaches it, the game is adjudicated as a draw
+    pub threshold: Score,
+}
+
+/// Adjudicate an unfinished game by searching its final position
+pub fn adjudicate<const S: usize>(
+    position: &Position<S>,
+    adjudication: &Adjudication,
+) -> GameResult {
+    let (_, score) = search::mcts(position.clone(), adjudication.nodes);
+    let side_to_move_result = if score >= adjudication.threshold {
+        GameResultForUs::Win
+    } else if score <= 1.0 - adjudication.threshold {
+        GameResultForUs::Loss
+    } else {
+        GameResultForUs::Draw
+    };
+    side_to_move_result.to_game_result(position.side_to_move())
+}
 
 /// Play a single training game between two parameter sets
+/// The node count and search time of each searched move is written to the move's comment.
+/// If `record_stats` is set, search statistics for every ply are also returned.
 pub fn play_game<const S: usize>(
     white_settings: &MctsSetting<S>,
     black_settings: &MctsSetting<S>,
     opening: &[Move],
     temperature: f64,
-) -> (Game<Position<S>>, Vec<Vec<(Move, Score)>>) {
+    record_stats: bool,
+) -> (Game<Position<S>>, Vec<MoveScores>, Option<Vec<MoveStats>>) {
+    play_game_with_adjudication(
+        white_settings,
+        black_settings,
+        opening,
+        temperature,
+        record_stats,
+        None,
+    )
+}
+
+/// Same as `play_game`, but games that reach `adjudication.max_plies` are adjudicated by `adjudicate`.
+/// Without adjudication, games are abandoned without a result after 200 plies
+pub fn play_game_with_adjudication<const S: usize>(
+    white_settings: &MctsSetting<S>,
+    black_settings: &MctsSetting<S>,
+    opening: &[Move],
+    temperature: f64,
+    record_stats: bool,
+    adjudication: Option<&Adjudication>,
+) -> (Game<Position<S>>, Vec<MoveScores>, Option<Vec<MoveStats>>) {
     const MCTS_NODES: u64 = 100_000;
 
     let mut position = Position::start_position();
     let mut game_moves = opening.to_vec();
     let mut move_scores = vec![vec![]; opening.len()];
+    let mut stats = vec![MoveStats::default(); opening.len()];
     for mv in opening {
         position.do_move(mv.clone());
     }
     let mut rng = rand::thread_rng();
+    let mut adjudicated_result = None;
 
     while position.game_result().is_none() {
         let num_plies = game_moves.len();
-        if num_plies > 200 {
-            break;
+        match adjudication {
+            Some(adjudication) if num_plies >= adjudication.max_plies => {
+                adjudicated_result = Some(adjudicate(&position, adjudication));
+                break;
+            }
+            None if num_plies > 200 => break,
+            _ => (),
         }
 
-        let moves_scores = match position.side_to_move() {
-            Color::White => {
-                search::mcts_training::<S>(position.clone(), MCTS_NODES, white_settings.clone())
-            }
-            Color::Black => {
-                search::mcts_training::<S>(position.clone(), MCTS_NODES, black_settings.clone())
-            }
+        let settings = match position.side_to_move() {
+            Color::White => white_settings,
+            Color::Black => black_settings,
         };
+        let search_start_time = Instant::now();
+        let (moves_scores, root_value) = search::mcts_training_with_root_value::<S>(
+            position.clone(),
+            MCTS_NODES,
+            settings.clone(),
+        );
+        let time = search_start_time.elapsed();
 
-        // For the first regular move (White's move #2), choose a random flatstone move
-        // This reduces white's first move advantage, and prevents white from always playing 2.Cc3
-        let best_move = if position.half_moves_played() == 2 {
-            let flat_moves = moves_scores
+        stats.push(MoveStats {
+            nodes: MCTS_NODES,
+            top_move_visit_fraction: moves_scores
                 .iter()
-                .map(|(mv, _)| mv)
-                .filter(|mv| matches!(*mv, Move::Place(Role::Flat, _)))
-                .collect::<Vec<_>>();
-            (*flat_moves.choose(&mut rng).unwrap()).clone()
-        }
-        // Turn off temperature in the middle-game, when all games are expected to be unique
-        else if position.half_moves_played() < 20 {
-            search::best_move(&mut rand::thread_rng(), temperature, &moves_scores[..])
-        } else {
-            search::best_move(&mut rand::thread_rng(), 0.1, &moves_scores[..])
-        };
+                .map(|(_, score)| *score)
+                .fold(0.0, Score::max),
+            root_value,
+            time,
+        });
+
+        let best_move = select_move(
+            &mut rng,
+            settings,
+            position.half_moves_played(),
+            temperature,
+            &moves_scores,
+        );
         position.do_move(best_move.clone());
         game_moves.push(best_move);
         move_scores.push(moves_scores);
@@ -65,15 +152,57 @@ pub fn play_game<const S: usize>(
             start_position: Position::default(),
             moves: game_moves
                 .into_iter()
-                .map(|mv| PtnMove {
+                .zip(&stats)
+                .map(|(mv, stats)| PtnMove {
                     mv,
                     annotations: vec![],
-                    comment: String::new(),
+                    comment: if stats.nodes > 0 {
+                        stats.comment()
+                    } else {
+                        String::new()
+                    },
+                    clock: None,
                 })
                 .collect::<Vec<_>>(),
-            game_result: position.game_result(),
+            game_result: position.game_result().or(adjudicated_result),
             tags: vec![],
         },
         move_scores,
+        if record_stats { Some(stats) } else { None },
     )
 }
+
+/// Select the move to play in a self-play game, from the move scores returned by the search
+pub fn select_move<R: Rng, const S: usize>(
+    rng: &mut R,
+    settings: &MctsSetting<S>,
+    half_moves_played: usize,
+    temperature: f64,
+    moves_scores: &[(Move, Score)],
+) -> Move {
+    // For the first regular move (White's move #2), choose a random flatstone move
+    // This reduces white's first move advantage, and prevents white from always playing 2.Cc3
+    if half_moves_played == 2 {
+        let flat_moves = moves_scores
+            .iter()
+            .map(|(mv, _)| mv)
+            .filter(|mv| matches!(*mv, Move::Place(Role::Flat, _)))
+            .collect::<Vec<_>>();
+        return (*flat_moves.choose(rng).unwrap()).clone();
+    }
+
+    let max_rank = settings.weak_move_max_rank().min(moves_scores.len());
+    if max_rank > 1 && rng.gen_bool(settings.weak_move_probability()) {
+        let mut sorted_moves: Vec<&(Move, Score)> = moves_scores.iter().collect();
+        sorted_moves.sort_by(|(_, score1), (_, score2)| score2.partial_cmp(score1).unwrap());
+        let rank = rng.gen_range(2..=max_rank);
+        return sorted_moves[rank - 1].0.clone();
+    }
+
+    // Turn off temperature in the middle-game, when all games are expected to be unique
+    if half_moves_played < 20 {
+        search::best_move(rng, temperature, moves_scores)
+    } else {
+        search::best_move(rng, 0.1, moves_scores)
+    }
+}
@@ -0,0 +1,67 @@
+//! Tune the search parameters by playing self-play matches between different settings.
+
+use board_game_traits::GameResult;
+use log::info;
+
+use crate::search::{MctsSetting, Score};
+use crate::tune::play_match::play_game;
+
+/// Play a round-robin self-play tournament between different values of `c_puct_init`, and return the value that scored best.
+/// Every pair of candidates plays `games_per_candidate` games against each other, alternating colors.
+/// All other settings, including `c_puct_base`, are taken from `base_settings`.
+pub fn tune_cpuct<const S: usize>(
+    base_settings: &MctsSetting<S>,
+    candidate_cpucts: &[Score],
+    games_per_candidate: usize,
+) -> Score {
+    assert!(!candidate_cpucts.is_empty(), "No c_puct candidates given");
+    let candidate_settings: Vec<MctsSetting<S>> = candidate_cpucts
+        .iter()
+        .map(|c_puct_init| {
+            base_settings
+                .clone()
+                .with_c_puct(*c_puct_init, base_settings.c_puct_base())
+        })
+        .collect();
+
+    let mut scores = vec![0.0; candidate_cpucts.len()];
+    for i in 0..candidate_settings.len() {
+        for j in i + 1..candidate_settings.len() {
+            for game_number in 0..games_per_candidate {
+                let (white, black) = if game_number % 2 == 0 { (i, j) } else { (j, i) };
+                let (game, _, _) = play_game::<S>(
+                    &candidate_settings[white],
+                    &candidate_settings[black],
+                    &[],
+                    0.2,
+                    false,
+                );
+                match game.game_result {
+                    Some(GameResult::WhiteWin) => scores[white] += 1.0,
+                    Some(GameResult::BlackWin) => scores[black] += 1.0,
+                    None | Some(GameResult::Draw) => {
+                        scores[white] += 0.5;
+                        scores[black] += 0.5;
+                    }
+                }
+            }
+        }
+    }
+
+    for (c_puct_init, score) in candidate_cpucts.iter().zip(scores.iter()) {
+        info!("c_puct_init {}: {} points", c_puct_init, score);
+    }
+
+    let (best_index, _) =
+        scores
+            .iter()
+            .enumerate()
+            .fold((0, Score::MIN), |(best_index, best_score), (i, score)| {
+                if *score > best_score {
+                    (i, *score)
+                } else {
+                    (best_index, best_score)
+                }
+            });
+    candidate_cpucts[best_index]
+}
@@ -89,15 +89,30 @@ fn tuning_iteration<R: rand::Rng, const S: usize>(
         .map(|(a, b)| if rng.gen() { (a, b) } else { (b, a) })
         .unzip();
 
-    let player1_settings = <MctsSetting<S>>::default()
-        .add_search_params(player1_variables.iter().map(|(_, a)| *a).collect());
-    let player2_settings = <MctsSetting<S>>::default()
-        .add_search_params(player2_variables.iter().map(|(_, a)| *a).collect());
+    let player1_settings = settings_from_values(
+        &player1_variables
+            .iter()
+            .map(|(_, a)| *a)
+            .collect::<Vec<_>>(),
+    );
+    let player2_settings = settings_from_values(
+        &player2_variables
+            .iter()
+            .map(|(_, a)| *a)
+            .collect::<Vec<_>>(),
+    );
 
-    let (game, _) = play_game::<S>(&player1_settings, &player2_settings, opening, 0.2);
+    let (game, _, _) = play_game::<S>(&player1_settings, &player2_settings, opening, 0.2, false);
     match game.game_result {
         Some(GameResult::WhiteWin) => player1_variables.iter().map(|(a, _)| *a).collect(),
         Some(GameResult::BlackWin) => player2_variables.iter().map(|(a, _)| *a).collect(),
         None | Some(GameResult::Draw) => vec![SpsaDirection::NoChange; variables.len()],
     }
 }
+
+/// Settings for one side of an SPSA game. The tuned variables are `c_puct_init`, `c_puct_base` and `initial_mean_action_value`, in that order
+fn settings_from_values<const S: usize>(values: &[f32]) -> MctsSetting<S> {
+    <MctsSetting<S>>::default()
+        .with_c_puct(values[0], values[1])
+        .with_initial_mean_action_value(values[2])
+}
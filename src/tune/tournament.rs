@@ -0,0 +1,134 @@
+//! Round robin tournaments between several parameter sets.
+
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use rayon::prelude::*;
+
+use crate::tune::training::play_game_pair;
+
+/// Results of a round robin tournament
+#[derive(Clone, PartialEq, Debug)]
+pub struct CrossTable {
+    /// `scores[i][j]` is the number of points player `i` scored against player `j`.
+    /// Wins count as one point, draws as half a point.
+    pub scores: Vec<Vec<f32>>,
+    pub games_per_pair: u64,
+}
+
+impl CrossTable {
+    pub fn num_players(&self) -> usize {
+        self.scores.len()
+    }
+
+    /// Total points scored by the player, against all opponents
+    pub fn total_score(&self, player: usize) -> f32 {
+        self.scores[player].iter().sum()
+    }
+
+    /// Every player's index, total score and Elo rating relative to the average opponent, best player first
+    pub fn ranking(&self) -> Vec<(usize, f32, f64)> {
+        let games_per_player = self.games_per_pair * (self.num_players() as u64 - 1);
+        let mut ranking: Vec<(usize, f32, f64)> = (0..self.num_players())
+            .map(|player| {
+                let score = self.total_score(player);
+                (
+                    player,
+                    score,
+                    elo_difference(score as f64 / games_per_player as f64),
+                )
+            })
+            .collect();
+        ranking.sort_by(|(_, score1, _), (_, score2, _)| score2.partial_cmp(score1).unwrap());
+        ranking
+    }
+}
+
+impl fmt::Display for CrossTable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "    ")?;
+        for opponent in 0..self.num_players() {
+            write!(f, "{:>6}", opponent)?;
+        }
+        writeln!(f, "{:>8}", "Total")?;
+        for (player, scores) in self.scores.iter().enumerate() {
+            write!(f, "{:>4}", player)?;
+            for (opponent, score) in scores.iter().enumerate() {
+                if opponent == player {
+                    write!(f, "{:>6}", "-")?;
+                } else {
+                    write!(f, "{:>6.1}", score)?;
+                }
+            }
+            writeln!(f, "{:>8.1}", self.total_score(player))?;
+        }
+        writeln!(f)?;
+        for (rank, (player, score, elo)) in self.ranking().iter().enumerate() {
+            writeln!(
+                f,
+                "{}. Player {}: {:.1} points, {:+.0} Elo",
+                rank + 1,
+                player,
+                score,
+                elo
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Play `games_per_pair` training games between every pair of `(value_params, policy_params)`, alternating colors.
+pub fn round_robin<const S: usize>(
+    param_sets: &[(Vec<f32>, Vec<f32>)],
+    games_per_pair: u64,
+) -> CrossTable {
+    let pairs: Vec<(usize, usize)> = (0..param_sets.len())
+        .flat_map(|i| (i + 1..param_sets.len()).map(move |j| (i, j)))
+        .collect();
+
+    let pair_scores: Vec<(usize, usize, u64, u64)> = pairs
+        .into_par_iter()
+        .map(|(i, j)| {
+            let (value_params, policy_params) = &param_sets[i];
+            let (opponent_value_params, opponent_policy_params) = &param_sets[j];
+            let wins = AtomicU64::new(0);
+            let opponent_wins = AtomicU64::new(0);
+            (0..games_per_pair as usize)
+                .into_par_iter()
+                .for_each(|game_number| {
+                    play_game_pair::<S>(
+                        opponent_value_params,
+                        opponent_policy_params,
+                        value_params,
+                        policy_params,
+                        &wins,
+                        &opponent_wins,
+                        game_number,
+                    );
+                });
+            (
+                i,
+                j,
+                wins.load(Ordering::SeqCst),
+                opponent_wins.load(Ordering::SeqCst),
+            )
+        })
+        .collect();
+
+    let mut scores = vec![vec![0.0; param_sets.len()]; param_sets.len()];
+    for (i, j, wins, opponent_wins) in pair_scores {
+        let draws = games_per_pair - wins - opponent_wins;
+        scores[i][j] = wins as f32 + draws as f32 / 2.0;
+        scores[j][i] = opponent_wins as f32 + draws as f32 / 2.0;
+    }
+    CrossTable {
+        scores,
+        games_per_pair,
+    }
+}
+
+/// The Elo difference corresponding to an expected score between 0.0 and 1.0.
+/// A perfect or zero score gives an infinite difference.
+pub fn elo_difference(score: f64) -> f64 {
+    -400.0 * f64::log10(1.0 / score - 1.0)
+}
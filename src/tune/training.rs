@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::io::Read;
 use std::io::Write;
 use std::sync::atomic::{AtomicU64, Ordering};
@@ -16,8 +17,8 @@ use crate::position::TunableBoard;
 use crate::ptn::Game;
 use crate::ptn::{ptn_parser, PtnMove};
 use crate::search::MctsSetting;
-use crate::tune::gradient_descent;
 use crate::tune::play_match::play_game;
+use crate::tune::{export, gradient_descent};
 
 // The score, or probability of being played, for a given move
 type MoveScore = (Move, f32);
@@ -169,6 +170,7 @@ pub fn train_perpetually<const S: usize, const N: usize, const M: usize>(
             &move_scores_in_training_batch,
             &value_params,
             &policy_params,
+            None,
         )?;
 
         last_value_params = value_params;
@@ -189,7 +191,7 @@ pub fn train_perpetually<const S: usize, const N: usize, const M: usize>(
     }
 }
 
-fn play_game_pair<const S: usize>(
+pub(crate) fn play_game_pair<const S: usize>(
     last_value_params: &[f32],
     last_policy_params: &[f32],
     value_params: &[f32],
@@ -199,16 +201,14 @@ fn play_game_pair<const S: usize>(
     i: usize,
 ) -> (Game<Position<S>>, Vec<Vec<(Move, f32)>>) {
     let settings = MctsSetting::default()
-        .add_value_params(value_params.to_vec())
-        .add_policy_params(policy_params.to_vec())
+        .with_eval_params(value_params.to_vec(), policy_params.to_vec())
         .add_dirichlet(0.2);
     let last_settings = MctsSetting::default()
-        .add_value_params(last_value_params.to_vec())
-        .add_policy_params(last_policy_params.to_vec())
+        .with_eval_params(last_value_params.to_vec(), last_policy_params.to_vec())
         .add_dirichlet(0.2);
     if i % 2 == 0 {
-        let game = play_game::<S>(&settings, &last_settings, &[], 1.0);
-        match game.0.game_result {
+        let (game, move_scores, _) = play_game::<S>(&settings, &last_settings, &[], 1.0, false);
+        match game.game_result {
             Some(GameResult::WhiteWin) => {
                 current_params_wins.fetch_add(1, Ordering::Relaxed);
             }
@@ -217,10 +217,10 @@ fn play_game_pair<const S: usize>(
             }
             Some(GameResult::Draw) | None => (),
         };
-        game
+        (game, move_scores)
     } else {
-        let game = play_game::<S>(&last_settings, &settings, &[], 1.0);
-        match game.0.game_result {
+        let (game, move_scores, _) = play_game::<S>(&last_settings, &settings, &[], 1.0, false);
+        match game.game_result {
             Some(GameResult::BlackWin) => {
                 current_params_wins.fetch_add(1, Ordering::Relaxed);
             }
@@ -229,7 +229,7 @@ fn play_game_pair<const S: usize>(
             }
             Some(GameResult::Draw) | None => (),
         };
-        game
+        (game, move_scores)
     }
 }
 
@@ -272,6 +272,24 @@ pub fn tune_value_from_file<const S: usize, const N: usize>(
 
     let (positions, results) = positions_and_results_from_games(games);
 
+    Ok(tune_value_from_positions(&positions, &results))
+}
+
+/// Like `tune_value_from_file`, but reads positions and results written by `export::write_positions`, instead of whole games
+pub fn tune_value_from_positions_file<const S: usize, const N: usize>(
+    file_name: &str,
+) -> Result<[f32; N], Box<dyn error::Error>> {
+    let reader = io::BufReader::new(fs::File::open(file_name)?);
+    let (positions, results) = export::read_positions::<_, S>(reader)?;
+
+    Ok(tune_value_from_positions(&positions, &results))
+}
+
+/// Tune value parameters from randomly initialized values, on positions labelled with the results of their games
+pub fn tune_value_from_positions<const S: usize, const N: usize>(
+    positions: &[Position<S>],
+    results: &[GameResult],
+) -> [f32; N] {
     let coefficient_sets = positions
         .iter()
         .map(|position| {
@@ -309,21 +327,90 @@ pub fn tune_value_from_file<const S: usize, const N: usize>(
 
     println!("Final parameters: {:?}", tuned_parameters);
 
-    Ok(tuned_parameters)
+    tuned_parameters
 }
 
+/// Like `tune_value_from_positions`, but positions that are identical up to symmetry are merged into a single sample,
+/// with their average result and weighted by the number of times they occur. See `aggregate_value_positions`
+pub fn tune_value_from_aggregated_positions<const S: usize, const N: usize>(
+    positions: &[Position<S>],
+    results: &[GameResult],
+) -> [f32; N] {
+    let (coefficient_sets, f32_results, weights) =
+        aggregate_value_positions::<S, N>(positions, results);
+
+    let middle_index = coefficient_sets.len() / 2;
+
+    let mut rng = rand::rngs::StdRng::from_seed([0; 32]);
+    let initial_params: [f32; N] = array_from_fn(|| rng.gen_range(-0.01..0.01));
+
+    let tuned_parameters = gradient_descent::weighted_gradient_descent(
+        &coefficient_sets[0..middle_index],
+        &f32_results[0..middle_index],
+        &weights[0..middle_index],
+        &coefficient_sets[middle_index..],
+        &f32_results[middle_index..],
+        &weights[middle_index..],
+        &initial_params,
+        50.0,
+    );
+
+    println!("Final parameters: {:?}", tuned_parameters);
+
+    tuned_parameters
+}
+
+/// Tune the value and policy parameters on the games and their move scores.
+/// The two sets of parameters are independent, and are tuned concurrently.
+/// The games are shuffled before tuning, using `shuffle_seed` if given, or a fixed default seed otherwise.
 pub fn tune_value_and_policy<const S: usize, const N: usize, const M: usize>(
     games: &[Game<Position<S>>],
     move_scoress: &[MoveScoresForGame],
     initial_value_params: &[f32; N],
     initial_policy_params: &[f32; M],
+    shuffle_seed: Option<u64>,
+) -> Result<([f32; N], [f32; M]), Box<dyn error::Error>> {
+    tune_value_and_policy_with_threading(
+        games,
+        move_scoress,
+        initial_value_params,
+        initial_policy_params,
+        shuffle_seed,
+        true,
+    )
+}
+
+/// Same as `tune_value_and_policy`, but tunes the value parameters before the policy parameters, instead of concurrently.
+/// The results are identical.
+pub fn tune_value_and_policy_sequential<const S: usize, const N: usize, const M: usize>(
+    games: &[Game<Position<S>>],
+    move_scoress: &[MoveScoresForGame],
+    initial_value_params: &[f32; N],
+    initial_policy_params: &[f32; M],
+    shuffle_seed: Option<u64>,
+) -> Result<([f32; N], [f32; M]), Box<dyn error::Error>> {
+    tune_value_and_policy_with_threading(
+        games,
+        move_scoress,
+        initial_value_params,
+        initial_policy_params,
+        shuffle_seed,
+        false,
+    )
+}
+
+fn tune_value_and_policy_with_threading<const S: usize, const N: usize, const M: usize>(
+    games: &[Game<Position<S>>],
+    move_scoress: &[MoveScoresForGame],
+    initial_value_params: &[f32; N],
+    initial_policy_params: &[f32; M],
+    shuffle_seed: Option<u64>,
+    parallel: bool,
 ) -> Result<([f32; N], [f32; M]), Box<dyn error::Error>> {
     let mut games_and_move_scoress: Vec<(&Game<Position<S>>, &MoveScoresForGame)> =
         games.iter().zip(move_scoress).collect();
 
-    let mut rng = rand::rngs::StdRng::from_seed([0; 32]);
-
-    games_and_move_scoress.shuffle(&mut rng);
+    games_and_move_scoress.shuffle(&mut shuffle_rng(shuffle_seed));
 
     let (games, move_scoress): (Vec<_>, Vec<_>) = games_and_move_scoress.into_iter().unzip();
 
@@ -355,14 +442,7 @@ pub fn tune_value_and_policy<const S: usize, const N: usize, const M: usize>(
     let mut policy_results: Vec<f32> = Vec::with_capacity(number_of_coefficient_sets);
 
     for (game, move_scores) in games.iter().zip(move_scoress) {
-        let mut position = game.start_position.clone();
-
-        for (mv, move_scores) in game
-            .moves
-            .iter()
-            .map(|PtnMove { mv, .. }| mv)
-            .zip(move_scores)
-        {
+        for ((position, _), move_scores) in game.iter_positions().zip(move_scores) {
             let group_data = position.group_data();
             for (possible_move, score) in move_scores {
                 let mut coefficients = [0.0; M];
@@ -376,35 +456,46 @@ pub fn tune_value_and_policy<const S: usize, const N: usize, const M: usize>(
                 policy_coefficients_sets.push(coefficients);
                 policy_results.push(*score);
             }
-            position.do_move(mv.clone());
         }
     }
 
-    let middle_index = value_coefficient_sets.len() / 2;
+    let tune_value = || {
+        let middle_index = value_coefficient_sets.len() / 2;
 
-    let tuned_value_parameters = gradient_descent::gradient_descent(
-        &value_coefficient_sets[0..middle_index],
-        &value_results[0..middle_index],
-        &value_coefficient_sets[middle_index..],
-        &value_results[middle_index..],
-        &initial_value_params,
-        10.0,
-    );
+        let tuned_value_parameters = gradient_descent::gradient_descent(
+            &value_coefficient_sets[0..middle_index],
+            &value_results[0..middle_index],
+            &value_coefficient_sets[middle_index..],
+            &value_results[middle_index..],
+            initial_value_params,
+            10.0,
+        );
 
-    println!("Final parameters: {:?}", tuned_value_parameters);
+        println!("Final value parameters: {:?}", tuned_value_parameters);
+        tuned_value_parameters
+    };
 
-    let middle_index = policy_coefficients_sets.len() / 2;
+    let tune_policy = || {
+        let middle_index = policy_coefficients_sets.len() / 2;
 
-    let tuned_policy_parameters = gradient_descent::gradient_descent(
-        &policy_coefficients_sets[0..middle_index],
-        &policy_results[0..middle_index],
-        &policy_coefficients_sets[middle_index..],
-        &policy_results[middle_index..],
-        &initial_policy_params,
-        10000.0,
-    );
+        let tuned_policy_parameters = gradient_descent::gradient_descent(
+            &policy_coefficients_sets[0..middle_index],
+            &policy_results[0..middle_index],
+            &policy_coefficients_sets[middle_index..],
+            &policy_results[middle_index..],
+            initial_policy_params,
+            10000.0,
+        );
+
+        println!("Final policy parameters: {:?}", tuned_policy_parameters);
+        tuned_policy_parameters
+    };
 
-    println!("Final parameters: {:?}", tuned_policy_parameters);
+    let (tuned_value_parameters, tuned_policy_parameters) = if parallel {
+        rayon::join(tune_value, tune_policy)
+    } else {
+        (tune_value(), tune_policy())
+    };
 
     Ok((tuned_value_parameters, tuned_policy_parameters))
 }
@@ -412,10 +503,33 @@ pub fn tune_value_and_policy<const S: usize, const N: usize, const M: usize>(
 pub fn tune_value_and_policy_from_file<const S: usize, const N: usize, const M: usize>(
     value_file_name: &str,
     policy_file_name: &str,
+    shuffle_seed: Option<u64>,
 ) -> Result<([f32; N], [f32; M]), Box<dyn error::Error>> {
+    // The games are shuffled once, by `tune_value_and_policy`
     let (games, move_scoress) =
-        games_and_move_scoress_from_file::<S>(value_file_name, policy_file_name)?;
+        games_and_move_scoress_from_file::<S>(value_file_name, policy_file_name, None)?;
 
+    tune_value_and_policy_from_scratch(&games, &move_scoress, shuffle_seed)
+}
+
+/// Like `tune_value_and_policy_from_file`, but reads games and move scores written by `export::write_training_binary`.
+/// Much faster to read than the PTN and move scores files
+pub fn tune_value_and_policy_from_binary_file<const S: usize, const N: usize, const M: usize>(
+    file_name: &str,
+    shuffle_seed: Option<u64>,
+) -> Result<([f32; N], [f32; M]), Box<dyn error::Error>> {
+    let reader = io::BufReader::new(fs::File::open(file_name)?);
+    let (games, move_scoress) = export::read_training_binary::<_, S>(reader)?;
+
+    tune_value_and_policy_from_scratch(&games, &move_scoress, shuffle_seed)
+}
+
+/// Tune value and policy parameters from randomly initialized values
+fn tune_value_and_policy_from_scratch<const S: usize, const N: usize, const M: usize>(
+    games: &[Game<Position<S>>],
+    move_scoress: &[MoveScoresForGame],
+    shuffle_seed: Option<u64>,
+) -> Result<([f32; N], [f32; M]), Box<dyn error::Error>> {
     let mut rng = rand::rngs::StdRng::from_seed([0; 32]);
 
     let initial_value_params: [f32; N] = array_from_fn(|| rng.gen_range(-0.01..0.01));
@@ -426,16 +540,20 @@ pub fn tune_value_and_policy_from_file<const S: usize, const N: usize, const M:
     // If we don't, variation of this parameter completely dominates the other parameters
     initial_policy_params[0] = 1.0;
     tune_value_and_policy(
-        &games,
-        &move_scoress,
+        games,
+        move_scoress,
         &initial_value_params,
         &initial_policy_params,
+        shuffle_seed,
     )
 }
 
+/// Read the most recent games and their move scores from the two files.
+/// If `shuffle_seed` is given, the games and their move scores are shuffled together with that seed.
 pub fn games_and_move_scoress_from_file<const S: usize>(
     value_file_name: &str,
     policy_file_name: &str,
+    shuffle_seed: Option<u64>,
 ) -> Result<(Vec<Game<Position<S>>>, Vec<MoveScoresForGame>), Box<dyn error::Error>> {
     let mut move_scoress = read_move_scores_from_file::<S>(policy_file_name)?;
     let mut games = read_games_from_file(value_file_name)?;
@@ -481,9 +599,23 @@ pub fn games_and_move_scoress_from_file<const S: usize>(
             position.do_move(mv.clone());
         }
     }
+
+    if let Some(seed) = shuffle_seed {
+        let mut games_and_move_scoress: Vec<_> = games.into_iter().zip(move_scoress).collect();
+        games_and_move_scoress.shuffle(&mut shuffle_rng(Some(seed)));
+        return Ok(games_and_move_scoress.into_iter().unzip());
+    }
     Ok((games, move_scoress))
 }
 
+/// The rng used for shuffling training data. Without a seed, a fixed default seed is used.
+fn shuffle_rng(shuffle_seed: Option<u64>) -> StdRng {
+    match shuffle_seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_seed([0; 32]),
+    }
+}
+
 pub fn read_move_scores_from_file<const S: usize>(
     file_name: &str,
 ) -> Result<Vec<MoveScoresForGame>, Box<dyn error::Error>> {
@@ -518,21 +650,65 @@ pub fn read_move_scores_from_file<const S: usize>(
     Ok(move_scoress)
 }
 
+/// Merge positions that are identical up to symmetry into single samples, for value tuning.
+/// Returns the value coefficients of each unique position, its average result from white's perspective,
+/// and its weight, which is the number of times it occurred. The weights are used by `tune_value_from_aggregated_positions`.
+/// Samples are returned in the order the positions first occur.
+pub fn aggregate_value_positions<const S: usize, const N: usize>(
+    positions: &[Position<S>],
+    results: &[GameResult],
+) -> (Vec<[f32; N]>, Vec<f32>, Vec<f32>) {
+    let mut indices: HashMap<Position<S>, usize> = HashMap::new();
+    let mut coefficient_sets: Vec<[f32; N]> = vec![];
+    let mut result_sums: Vec<f32> = vec![];
+    let mut weights: Vec<f32> = vec![];
+
+    for (position, result) in positions.iter().zip(results) {
+        let canonical_position = position.canonical();
+        let result = match result {
+            GameResult::WhiteWin => 1.0,
+            GameResult::Draw => 0.5,
+            GameResult::BlackWin => 0.0,
+        };
+
+        match indices.get(&canonical_position) {
+            Some(&index) => {
+                result_sums[index] += result;
+                weights[index] += 1.0;
+            }
+            None => {
+                let mut coefficients = [0.0; N];
+                canonical_position.static_eval_coefficients(&mut coefficients);
+                indices.insert(canonical_position, coefficient_sets.len());
+                coefficient_sets.push(coefficients);
+                result_sums.push(result);
+                weights.push(1.0);
+            }
+        }
+    }
+
+    let average_results = result_sums
+        .iter()
+        .zip(&weights)
+        .map(|(result_sum, weight)| result_sum / weight)
+        .collect();
+
+    (coefficient_sets, average_results, weights)
+}
+
 pub fn positions_and_results_from_games<const S: usize>(
     games: Vec<Game<Position<S>>>,
 ) -> (Vec<Position<S>>, Vec<GameResult>) {
     let mut positions = vec![];
     let mut results = vec![];
-    for game in games.into_iter() {
-        let mut position = game.start_position;
-        for PtnMove { mv, .. } in game.moves {
+    for game in games.iter() {
+        // The final position is deliberately skipped
+        for (position, _) in game.iter_positions() {
             if position.game_result().is_some() {
                 break;
             }
-            positions.push(position.clone());
+            positions.push(position);
             results.push(game.game_result.unwrap_or(GameResult::Draw));
-            position.do_move(mv);
-            // Deliberately skip the final position
         }
     }
     (positions, results)